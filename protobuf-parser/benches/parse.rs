@@ -0,0 +1,56 @@
+#[macro_use]
+extern crate criterion;
+extern crate protobuf_parser;
+
+use criterion::{black_box, Criterion};
+
+const FIELD_TYPES: &[&str] = &[
+    "int32",
+    "int64",
+    "uint32",
+    "uint64",
+    "sint32",
+    "sint64",
+    "fixed32",
+    "sfixed32",
+    "fixed64",
+    "sfixed64",
+    "bool",
+    "string",
+    "bytes",
+    "float",
+    "double",
+    "google.protobuf.Timestamp",
+    "Address",
+];
+
+/// Builds a proto file with many messages, each using every kind of field type.
+fn large_proto() -> String {
+    let mut proto = String::from("syntax = \"proto3\";\n\npackage bench;\n\n");
+    for i in 0..200 {
+        proto.push_str(&format!("message Message{} {{\n", i));
+        for (number, typ) in FIELD_TYPES.iter().enumerate() {
+            proto.push_str(&format!("  {} field_{} = {};\n", typ, number, number + 1));
+        }
+        proto.push_str(&format!(
+            "  repeated string tags = {};\n",
+            FIELD_TYPES.len() + 1
+        ));
+        proto.push_str(&format!(
+            "  map<string, int64> counts = {};\n",
+            FIELD_TYPES.len() + 2
+        ));
+        proto.push_str("}\n\n");
+    }
+    proto
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let proto = large_proto();
+    c.bench_function("parse large proto", |b| {
+        b.iter(|| protobuf_parser::parse(black_box(&proto)).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -9,53 +9,169 @@ extern crate nom;
 extern crate nom_locate;
 
 mod parser;
+mod validate;
 
 use nom::types::CompleteStr;
 use nom_locate::LocatedSpan;
 use std::convert::AsRef;
+use std::error::Error;
+use std::fmt;
 use std::ops::RangeInclusive;
 
+pub use validate::{Diagnostic, Severity, LINT_RULES};
+
 pub type Span<'a> = LocatedSpan<CompleteStr<'a>>;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Word<'a> {
     word: Span<'a>,
+    /// Name of a word generated by the parser rather than read from the file, such as a map
+    /// entry message. `word` then points at the declaration it was generated from.
+    synthetic: Option<String>,
+}
+
+impl<'a> Word<'a> {
+    /// Where the word is in the proto file, or the declaration a synthetic word was generated
+    /// from
+    pub fn position(&self) -> Span<'a> {
+        self.word
+    }
+
+    fn synthetic(position: Span<'a>, name: String) -> Self {
+        Word {
+            word: position,
+            synthetic: Some(name),
+        }
+    }
 }
 
 impl<'a> AsRef<str> for Word<'a> {
     fn as_ref(&self) -> &str {
-        self.word.fragment.as_ref()
+        match &self.synthetic {
+            Some(name) => name,
+            None => self.word.fragment.as_ref(),
+        }
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// An integer literal, compared by its value so `0x10` equals `16`
+#[derive(Debug, Clone)]
 pub struct Integer<'a> {
     position: Span<'a>,
+    literal: Span<'a>,
     value: i32,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl<'a> Integer<'a> {
+    /// Value of the integer
+    pub fn value(&self) -> i32 {
+        self.value
+    }
+
+    /// The integer as written in the proto file, such as `0x10` or `020` for 16
+    pub fn literal(&self) -> &'a str {
+        self.literal.fragment.0
+    }
+
+    /// Where the integer is in the proto file
+    pub fn position(&self) -> Span<'a> {
+        self.position
+    }
+}
+
+impl<'a> PartialEq for Integer<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum Syntax {
     /// Protobuf syntax [2](https://developers.google.com/protocol-buffers/docs/proto) (default)
+    #[default]
     Proto2,
     /// Protobuf syntax [3](https://developers.google.com/protocol-buffers/docs/proto3)
     Proto3,
+    /// Protobuf [editions](https://protobuf.dev/editions/overview/) (`edition = "2023";`), where
+    /// features such as field presence replace the `optional` and `required` labels
+    Editions,
+}
+
+/// Feature option setting the presence of fields in editions
+const FIELD_PRESENCE_FEATURE: &str = "features.field_presence";
+
+/// Whether a field tracks if it was set
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldPresence {
+    /// The field can be unset, such as proto2 and proto3 `optional` fields
+    Explicit,
+    /// An unset field reads as its default value, such as proto3 fields without a label and
+    /// repeated fields
+    Implicit,
+    /// The field must be set, such as proto2 `required` fields
+    LegacyRequired,
 }
 
-impl Default for Syntax {
-    fn default() -> Syntax {
-        Syntax::Proto2
+impl FieldPresence {
+    fn from_feature(value: &str) -> Option<Self> {
+        match value.trim() {
+            "EXPLICIT" => Some(FieldPresence::Explicit),
+            "IMPLICIT" => Some(FieldPresence::Implicit),
+            "LEGACY_REQUIRED" => Some(FieldPresence::LegacyRequired),
+            _ => None,
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+/// A scalar option or default value
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtoValue<'a> {
+    /// An integer, such as `17`, `-1`, `0x1F` or `017`
+    Int(i64),
+    /// An integer above `i64::MAX`, such as the `uint64` default `18446744073709551615`
+    UInt(u64),
+    /// A float, such as `1.5`, `1e-3` or `inf`
+    Float(f64),
+    /// `true` or `false`
+    Bool(bool),
+    /// A string with its escape sequences decoded, concatenating adjacent literals
+    Str(String),
+    /// An identifier, such as `SPEED` in `optimize_for = SPEED` or an enum value
+    Ident(Word<'a>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct BracketOption<'a> {
     key: Word<'a>,
-    // TODO(blt) This being a Span stinks. We should, instead, have a parser for
-    // ProtoValue or some such, which can be an integer, string or bool.
+    // The value as written, so diagnostics can point at it. `proto_value` parses it
     value: Span<'a>,
 }
 
+impl<'a> BracketOption<'a> {
+    /// Option name, such as `packed` or `(sql).type` for custom options
+    pub fn key(&self) -> &Word<'a> {
+        &self.key
+    }
+
+    /// Option value, as written in the proto file
+    pub fn value(&self) -> Span<'a> {
+        self.value
+    }
+
+    /// The parsed value, or `None` if it isn't a scalar, such as an aggregate `{ ... }`
+    pub fn proto_value(&self) -> Option<ProtoValue<'a>> {
+        parser::parse_proto_value(self.value)
+    }
+
+    /// Value of a string option without its quotes, or `None` if the value isn't a string
+    pub fn string_value(&self) -> Option<&'a str> {
+        let value = self.value.fragment.0;
+        ['"', '\'']
+            .iter()
+            .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum DeclOptionName<'a> {
     BuiltIn(Word<'a>),
@@ -65,11 +181,122 @@ pub enum DeclOptionName<'a> {
 #[derive(Debug, Clone, PartialEq)]
 pub struct DeclOption<'a> {
     name: DeclOptionName<'a>,
-    // TODO(blt) This being a Span stinks. We should, instead, have a parser for
-    // ProtoValue or some such, which can be an integer, string or bool.
+    // The value as written, so diagnostics can point at it. `proto_value` parses it
     value: Span<'a>,
 }
 
+impl<'a> DeclOption<'a> {
+    /// Option name
+    pub fn name(&self) -> &DeclOptionName<'a> {
+        &self.name
+    }
+
+    /// Option value, as written in the proto file
+    pub fn value(&self) -> Span<'a> {
+        self.value
+    }
+
+    /// The parsed value, or `None` if it isn't a scalar, such as an aggregate `{ ... }`
+    pub fn proto_value(&self) -> Option<ProtoValue<'a>> {
+        parser::parse_proto_value(self.value)
+    }
+
+    /// Value of a string option without its quotes, concatenating adjacent string literals such
+    /// as `"foo" "bar"`, or `None` if the value isn't a string
+    pub fn string_value(&self) -> Option<String> {
+        match parser::string_literals(self.value) {
+            Ok((rest, strings)) if rest.fragment.0.is_empty() => Some(
+                strings
+                    .iter()
+                    .map(|string| {
+                        let string = string.fragment.0;
+                        &string[1..string.len() - 1]
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Fields of an aggregate option value such as `{ get: "/v1/users/{id}" }`, in order, with
+    /// string values unquoted. Lists and nested aggregates are kept as written. Returns `None`
+    /// if the value isn't an aggregate
+    pub fn aggregate_fields(&self) -> Option<Vec<(String, String)>> {
+        parser::aggregate_fields(self.value)
+    }
+}
+
+/// An option value which could not be interpreted
+///
+/// Keeps the original source text of the value so diagnostics can point at it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptionValueError<'a> {
+    /// Option name
+    pub name: Word<'a>,
+    /// Option value, as written in the proto file
+    pub value: Span<'a>,
+    /// Description of the expected value
+    pub expected: &'static str,
+}
+
+impl<'a> OptionValueError<'a> {
+    /// Line of the value in the proto file (1-based)
+    pub fn line(&self) -> u32 {
+        self.value.line
+    }
+
+    /// Column of the value in the proto file (1-based)
+    pub fn column(&self) -> usize {
+        self.value.get_utf8_column()
+    }
+}
+
+impl<'a> fmt::Display for OptionValueError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid value '{}' for option '{}' at line {}, column {}: expected {}",
+            self.value.fragment.as_ref(),
+            self.name.as_ref(),
+            self.line(),
+            self.column(),
+            self.expected
+        )
+    }
+}
+
+impl<'a> Error for OptionValueError<'a> {}
+
+/// A declaration `parse_complete` could not read
+///
+/// `parse` stops at such a declaration, leaving it and everything after it unread.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError<'a> {
+    /// The unread rest of the proto file, starting at the declaration
+    pub rest: Span<'a>,
+}
+
+impl<'a> ParseError<'a> {
+    /// Line of the declaration in the proto file (1-based)
+    pub fn line(&self) -> u32 {
+        self.rest.line
+    }
+}
+
+impl<'a> fmt::Display for ParseError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let declaration = self.rest.fragment.0.lines().next().unwrap_or("").trim_end();
+        write!(
+            f,
+            "could not parse '{}' at line {}",
+            declaration,
+            self.line()
+        )
+    }
+}
+
+impl<'a> Error for ParseError<'a> {}
+
 /// A field rule
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Rule<'a> {
@@ -104,6 +331,65 @@ pub struct MapKVPair<'a> {
     value: FieldType<'a>,
 }
 
+impl<'a> MapKVPair<'a> {
+    /// Map key type
+    pub fn key(&self) -> &FieldType<'a> {
+        &self.key
+    }
+
+    /// Map value type
+    pub fn value(&self) -> &FieldType<'a> {
+        &self.value
+    }
+
+    /// Name of the value type if it is a message or enum, such as `Address` in
+    /// `map<string, Address>`
+    pub fn value_type_name(&self) -> Option<&Word<'a>> {
+        match &self.value {
+            FieldType::MessageOrEnum(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Whether the key type is allowed by protobuf: any integral or string type
+    pub fn has_valid_key(&self) -> bool {
+        matches!(
+            self.key,
+            FieldType::Int32
+                | FieldType::Int64
+                | FieldType::Uint32
+                | FieldType::Uint64
+                | FieldType::Sint32
+                | FieldType::Sint64
+                | FieldType::Fixed32
+                | FieldType::Fixed64
+                | FieldType::Sfixed32
+                | FieldType::Sfixed64
+                | FieldType::Bool
+                | FieldType::String
+        )
+    }
+}
+
+/// Keywords of the scalar field types, with the type each stands for
+pub const SCALAR_TYPES: &[(&str, FieldType<'static>)] = &[
+    ("int32", FieldType::Int32),
+    ("int64", FieldType::Int64),
+    ("uint32", FieldType::Uint32),
+    ("uint64", FieldType::Uint64),
+    ("sint32", FieldType::Sint32),
+    ("sint64", FieldType::Sint64),
+    ("fixed32", FieldType::Fixed32),
+    ("sfixed32", FieldType::Sfixed32),
+    ("fixed64", FieldType::Fixed64),
+    ("sfixed64", FieldType::Sfixed64),
+    ("bool", FieldType::Bool),
+    ("string", FieldType::String),
+    ("bytes", FieldType::Bytes),
+    ("float", FieldType::Float),
+    ("double", FieldType::Double),
+];
+
 /// Protobuf supported field types
 ///
 /// TODO: Groups (even if deprecated)
@@ -199,6 +485,127 @@ pub enum FieldType<'a> {
     Group(Vec<Field<'a>>),
 }
 
+impl<'a> FieldType<'a> {
+    /// The well-known type this type refers to, such as `google.protobuf.Timestamp`
+    pub fn well_known_type(&self) -> Option<WellKnownType> {
+        match self {
+            FieldType::MessageOrEnum(name) => WellKnownType::from_name(name.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Types from the `google.protobuf` package which ship with protobuf
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WellKnownType {
+    Any,
+    Timestamp,
+    Duration,
+    Struct,
+    Value,
+    ListValue,
+    NullValue,
+    Empty,
+    FieldMask,
+    DoubleValue,
+    FloatValue,
+    Int64Value,
+    UInt64Value,
+    Int32Value,
+    UInt32Value,
+    BoolValue,
+    StringValue,
+    BytesValue,
+}
+
+impl WellKnownType {
+    /// Every well-known type
+    pub const ALL: &'static [WellKnownType] = &[
+        WellKnownType::Any,
+        WellKnownType::Timestamp,
+        WellKnownType::Duration,
+        WellKnownType::Struct,
+        WellKnownType::Value,
+        WellKnownType::ListValue,
+        WellKnownType::NullValue,
+        WellKnownType::Empty,
+        WellKnownType::FieldMask,
+        WellKnownType::DoubleValue,
+        WellKnownType::FloatValue,
+        WellKnownType::Int64Value,
+        WellKnownType::UInt64Value,
+        WellKnownType::Int32Value,
+        WellKnownType::UInt32Value,
+        WellKnownType::BoolValue,
+        WellKnownType::StringValue,
+        WellKnownType::BytesValue,
+    ];
+
+    /// Recognizes a fully qualified type name, with or without a leading `.`
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.strip_prefix('.').unwrap_or(name);
+        let name = name.strip_prefix("google.protobuf.")?;
+        WellKnownType::ALL
+            .iter()
+            .find(|typ| typ.name() == name)
+            .copied()
+    }
+
+    /// Name of the type in the `google.protobuf` package, such as `Timestamp`
+    pub fn name(self) -> &'static str {
+        match self {
+            WellKnownType::Any => "Any",
+            WellKnownType::Timestamp => "Timestamp",
+            WellKnownType::Duration => "Duration",
+            WellKnownType::Struct => "Struct",
+            WellKnownType::Value => "Value",
+            WellKnownType::ListValue => "ListValue",
+            WellKnownType::NullValue => "NullValue",
+            WellKnownType::Empty => "Empty",
+            WellKnownType::FieldMask => "FieldMask",
+            WellKnownType::DoubleValue => "DoubleValue",
+            WellKnownType::FloatValue => "FloatValue",
+            WellKnownType::Int64Value => "Int64Value",
+            WellKnownType::UInt64Value => "UInt64Value",
+            WellKnownType::Int32Value => "Int32Value",
+            WellKnownType::UInt32Value => "UInt32Value",
+            WellKnownType::BoolValue => "BoolValue",
+            WellKnownType::StringValue => "StringValue",
+            WellKnownType::BytesValue => "BytesValue",
+        }
+    }
+}
+
+/// Comments attached to a declaration, without their `//` or `/* */` markers
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Comments<'a> {
+    /// Comments directly before the declaration
+    pub leading: Vec<Span<'a>>,
+    /// A comment after the declaration on the same line, such as `int32 id = 1; // the id`
+    pub trailing: Option<Span<'a>>,
+    /// Whether a blank line separates the declaration, or its leading comments, from what comes
+    /// before it, so groups of declarations can be told apart
+    pub blank_line_before: bool,
+}
+
+impl<'a> Comments<'a> {
+    /// The leading comments followed by the trailing comment, one per line, or `None` if there
+    /// are no comments
+    pub fn text(&self) -> Option<String> {
+        let lines: Vec<_> = self
+            .leading
+            .iter()
+            .chain(&self.trailing)
+            .map(|comment| comment.fragment.0)
+            .collect();
+        if lines.is_empty() {
+            None
+        } else {
+            Some(lines.join("\n"))
+        }
+    }
+}
+
 /// A Protobuf Field
 #[derive(Debug, Clone, PartialEq)]
 pub struct Field<'a> {
@@ -216,11 +623,50 @@ pub struct Field<'a> {
     pub packed: Option<bool>,
     /// Is the field deprecated
     pub deprecated: bool,
+    /// Comments on the field
+    pub comments: Comments<'a>,
+    /// Options in brackets, such as `[deprecated = true]`
+    pub options: Vec<BracketOption<'a>>,
+    /// Index in `Message::oneofs` of the oneof the field is declared in
+    pub oneof_index: Option<usize>,
+}
+
+impl<'a> Field<'a> {
+    /// Finds a bracket option by name, such as `(sql).type`
+    pub fn option(&self, key: &str) -> Option<&BracketOption<'a>> {
+        self.options.iter().find(|opt| opt.key.as_ref() == key)
+    }
+
+    /// The parsed `[default = ...]` value, such as `ProtoValue::Int(17)` for `[default = 17]`
+    pub fn default_value(&self) -> Option<ProtoValue<'a>> {
+        self.option("default")?.proto_value()
+    }
+
+    /// Name of the field in the proto3 JSON mapping, the `[json_name = "..."]` option or the
+    /// name in lowerCamelCase, such as `userId` for `user_id`
+    pub fn json_name(&self) -> String {
+        match self.option("json_name").and_then(|opt| opt.string_value()) {
+            Some(json_name) => json_name.to_string(),
+            None => camel_case(self.name.as_ref(), false),
+        }
+    }
+
+    /// The well-known type of the field, such as `google.protobuf.Timestamp`
+    pub fn well_known_type(&self) -> Option<WellKnownType> {
+        self.typ.well_known_type()
+    }
+
+    /// Is the field's type one of the `google.protobuf` well-known types
+    pub fn is_well_known_type(&self) -> bool {
+        self.well_known_type().is_some()
+    }
 }
 
 /// A protobuf message
 #[derive(Debug, Clone, Default)]
 pub struct Message<'a> {
+    position: Option<Span<'a>>,
+    end_position: Option<Span<'a>>,
     /// Message name
     pub name: Option<Word<'a>>,
     /// Message `Field`s
@@ -235,6 +681,177 @@ pub struct Message<'a> {
     pub messages: Vec<Message<'a>>,
     /// Nested enums
     pub enums: Vec<Enumeration<'a>>,
+    /// Extensions declared inside the message
+    pub extensions: Vec<Extension<'a>>,
+    /// Message options
+    pub options: Vec<DeclOption<'a>>,
+    /// Comments before the message
+    pub comments: Comments<'a>,
+}
+
+impl<'a> Message<'a> {
+    /// Is the message deprecated (`option deprecated = true;`)
+    pub fn is_deprecated(&self) -> bool {
+        bool_decl_option(&self.options, "deprecated").unwrap_or(false)
+    }
+
+    /// Fields of the message, followed by the fields of its oneofs
+    pub fn all_fields(&self) -> impl Iterator<Item = &Field<'a>> {
+        self.fields
+            .iter()
+            .chain(self.oneofs.iter().flat_map(|oneof| oneof.fields.iter()))
+    }
+
+    /// Field with the given name, including the fields of oneofs
+    pub fn field_by_name(&self, name: &str) -> Option<&Field<'a>> {
+        self.all_fields().find(|field| field.name.as_ref() == name)
+    }
+
+    /// Field with the given number, including the fields of oneofs
+    pub fn field_by_number(&self, number: i32) -> Option<&Field<'a>> {
+        self.all_fields()
+            .find(|field| field.number.value() == number)
+    }
+
+    /// Numbers between 1 and the highest field number which are neither used by a field nor
+    /// reserved, such as a field removed without reserving its number
+    ///
+    /// Numbers 19000 to 19999 are reserved for the protobuf implementation, so they are never
+    /// unused.
+    pub fn unused_numbers(&self) -> Vec<RangeInclusive<i32>> {
+        let max = match self.all_fields().map(|field| field.number.value()).max() {
+            Some(max) => max,
+            None => return Vec::new(),
+        };
+        let mut taken: Vec<_> = self
+            .all_fields()
+            .map(|field| field.number.value()..=field.number.value())
+            .chain(self.reserved_nums.iter().cloned())
+            .chain(Some(validate::IMPLEMENTATION_RESERVED))
+            .filter(|range| *range.start() <= max)
+            .collect();
+        taken.sort_by_key(|range| *range.start());
+
+        let mut unused = Vec::new();
+        let mut next = 1;
+        for range in taken {
+            if *range.start() > next {
+                unused.push(next..=range.start() - 1);
+            }
+            next = next.max(range.end().saturating_add(1));
+        }
+        unused
+    }
+
+    /// Lines spanned by the message in the proto file (1-based), from the `message` keyword to
+    /// the closing brace
+    ///
+    /// Messages which weren't parsed from a file report `0..=0`.
+    pub fn line_range(&self) -> RangeInclusive<u32> {
+        match (&self.position, &self.end_position) {
+            (Some(start), Some(end)) => start.line..=end.line,
+            _ => 0..=0,
+        }
+    }
+
+    /// Is this a map entry message, either declared with `option map_entry = true` or
+    /// generated by `AbstractProto::materialize_map_entries`
+    pub fn is_map_entry(&self) -> bool {
+        self.options.iter().any(|opt| {
+            matches!(&opt.name, DeclOptionName::BuiltIn(name) if name.as_ref() == "map_entry")
+                && opt.value.fragment.0.trim() == "true"
+        })
+    }
+
+    /// Adds the nested entry message `protoc` generates for each map field, such as
+    /// `LabelsEntry { optional K key = 1; optional V value = 2; }` for a field `labels`
+    fn materialize_map_entries(&mut self) {
+        for message in &mut self.messages {
+            message.materialize_map_entries();
+        }
+
+        let entries: Vec<_> = self
+            .fields
+            .iter()
+            .filter_map(|field| match &field.typ {
+                FieldType::Map(map) => Some(map_entry(field, map)),
+                _ => None,
+            })
+            .filter(|entry| {
+                !self
+                    .messages
+                    .iter()
+                    .any(|message| message.name == entry.name)
+            })
+            .collect();
+        self.messages.extend(entries);
+    }
+}
+
+/// The synthetic message `protoc` generates for a map field
+fn map_entry<'a>(field: &Field<'a>, map: &MapKVPair<'a>) -> Message<'a> {
+    let entry_field = |name: &str, typ: &FieldType<'a>, number: i32, literal: &'static str| Field {
+        name: Word::synthetic(map.position, name.to_string()),
+        rule: Rule {
+            position: None,
+            variant: RuleVariant::Optional,
+        },
+        typ: typ.clone(),
+        number: Integer {
+            position: map.position,
+            literal: LocatedSpan {
+                offset: map.position.offset,
+                line: map.position.line,
+                fragment: CompleteStr(literal),
+            },
+            value: number,
+        },
+        default: None,
+        packed: None,
+        deprecated: false,
+        comments: Comments::default(),
+        options: Vec::new(),
+        oneof_index: None,
+    };
+    let map_entry_option = DeclOption {
+        name: DeclOptionName::BuiltIn(Word::synthetic(map.position, "map_entry".to_string())),
+        value: LocatedSpan {
+            offset: map.position.offset,
+            line: map.position.line,
+            fragment: CompleteStr("true"),
+        },
+    };
+
+    Message {
+        name: Some(Word::synthetic(
+            field.name.word,
+            format!("{}Entry", camel_case(field.name.as_ref(), true)),
+        )),
+        fields: vec![
+            entry_field("key", &map.key, 1, "1"),
+            entry_field("value", &map.value, 2, "2"),
+        ],
+        options: vec![map_entry_option],
+        ..Message::default()
+    }
+}
+
+/// Converts a field name to CamelCase the way `protoc` names map entries, or to lowerCamelCase
+/// the way it names JSON fields
+fn camel_case(name: &str, upper_first: bool) -> String {
+    let mut camel = String::with_capacity(name.len());
+    let mut upper = upper_first;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            camel.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            camel.push(c);
+        }
+    }
+    camel
 }
 
 /// A protobuf enumeration field
@@ -244,6 +861,19 @@ pub struct EnumValue<'a> {
     pub name: Word<'a>,
     /// enum value number
     pub number: Integer<'a>,
+    /// Comments on the enum value
+    pub comments: Comments<'a>,
+    /// Options in brackets, such as `[deprecated = true]`
+    pub options: Vec<BracketOption<'a>>,
+}
+
+impl<'a> EnumValue<'a> {
+    /// Is the enum value deprecated (`[deprecated = true]`)
+    pub fn is_deprecated(&self) -> bool {
+        self.options
+            .iter()
+            .any(|opt| opt.key.as_ref() == "deprecated" && opt.value.fragment.0 == "true")
+    }
 }
 
 /// A protobuf enumerator
@@ -253,10 +883,12 @@ pub struct Enumeration<'a> {
     pub name: Word<'a>,
     /// enum values
     pub values: Vec<EnumValue<'a>>,
+    /// Comments before the enum
+    pub comments: Comments<'a>,
 }
 
 /// A OneOf
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct OneOf<'a> {
     position: Span<'a>,
     /// OneOf name
@@ -265,6 +897,7 @@ pub struct OneOf<'a> {
     pub fields: Vec<Field<'a>>,
 }
 
+/// A field added to another message by an `extend` block
 #[derive(Debug, Clone)]
 pub struct Extension<'a> {
     /// Extend this type with field
@@ -273,6 +906,34 @@ pub struct Extension<'a> {
     pub field: Field<'a>,
 }
 
+/// A gRPC service
+#[derive(Debug, Clone)]
+pub struct Service<'a> {
+    /// Service name
+    pub name: Word<'a>,
+    /// Methods of the service
+    pub rpcs: Vec<Rpc<'a>>,
+    /// Service options
+    pub options: Vec<DeclOption<'a>>,
+}
+
+/// A method of a service, such as `rpc Watch (WatchRequest) returns (stream Event);`
+#[derive(Debug, Clone)]
+pub struct Rpc<'a> {
+    /// Method name
+    pub name: Word<'a>,
+    /// Request message type
+    pub input_type: Word<'a>,
+    /// Response message type
+    pub output_type: Word<'a>,
+    /// Whether the client sends a stream of requests
+    pub client_streaming: bool,
+    /// Whether the server sends a stream of responses
+    pub server_streaming: bool,
+    /// Options in the method body
+    pub options: Vec<DeclOption<'a>>,
+}
+
 // NOTE(blt): It's possible that an invalid proto file will still parse into an
 // AbstractProto. The careful user will perform validation.
 #[derive(Debug, Default, Clone)]
@@ -283,6 +944,8 @@ pub struct AbstractProto<'a> {
     pub package: Option<Word<'a>>,
     /// Protobuf Syntax
     pub syntax: Syntax,
+    /// Whether the file declares its syntax or edition, instead of defaulting to proto2
+    pub syntax_declared: bool,
     /// Top level messages
     pub messages: Vec<Message<'a>>,
     /// Top level options
@@ -291,8 +954,195 @@ pub struct AbstractProto<'a> {
     pub enums: Vec<Enumeration<'a>>,
     /// Extensions
     pub extensions: Vec<Extension<'a>>,
+    /// Services
+    pub services: Vec<Service<'a>>,
+}
+
+impl<'a> AbstractProto<'a> {
+    /// Reads a built-in boolean file option such as `option deprecated = true;`
+    ///
+    /// Returns `None` if the option is not set or its value is not a boolean.
+    pub fn bool_option(&self, name: &str) -> Option<bool> {
+        bool_decl_option(&self.options, name)
+    }
+
+    /// Reads a built-in string file option such as `option csharp_namespace = "Shop";`, without
+    /// its quotes
+    ///
+    /// Returns `None` if the option is not set or its value is not a string.
+    pub fn string_option(&self, name: &str) -> Option<String> {
+        self.options
+            .iter()
+            .rev()
+            .find(|opt| matches!(&opt.name, DeclOptionName::BuiltIn(n) if n.as_ref() == name))
+            .and_then(|opt| opt.string_value())
+    }
+
+    /// Every message in the file with its full name, such as `User.Address`, listing each
+    /// message before the messages nested in it
+    pub fn all_messages(&self) -> impl Iterator<Item = (String, &Message<'a>)> {
+        let mut messages = Vec::new();
+        collect_messages("", &self.messages, &mut messages);
+        messages.into_iter()
+    }
+
+    /// Adds the nested entry messages `protoc` generates for map fields to every message,
+    /// for output which should match the descriptors `protoc` produces
+    ///
+    /// The map fields themselves are kept as `FieldType::Map`.
+    pub fn materialize_map_entries(&mut self) {
+        for message in &mut self.messages {
+            message.materialize_map_entries();
+        }
+    }
+
+    /// Checks the parsed file for problems which `protoc` would reject or warn about
+    pub fn validate(&self) -> Vec<Diagnostic<'a>> {
+        validate::validate(self)
+    }
+
+    /// Is the file deprecated (`option deprecated = true;`)
+    pub fn is_deprecated(&self) -> bool {
+        self.bool_option("deprecated").unwrap_or(false)
+    }
+
+    /// Should java classes be generated in separate files (`option java_multiple_files = true;`)
+    pub fn java_multiple_files(&self) -> bool {
+        self.bool_option("java_multiple_files").unwrap_or(false)
+    }
+
+    /// Should C++ arena allocation be enabled (`option cc_enable_arenas = true;`)
+    pub fn cc_enable_arenas(&self) -> bool {
+        self.bool_option("cc_enable_arenas").unwrap_or(false)
+    }
+
+    /// Prefix of generated Objective-C classes (`option objc_class_prefix = "...";`)
+    pub fn objc_class_prefix(&self) -> Option<String> {
+        self.string_option("objc_class_prefix")
+    }
+
+    /// Namespace of generated C# classes (`option csharp_namespace = "...";`)
+    pub fn csharp_namespace(&self) -> Option<String> {
+        self.string_option("csharp_namespace")
+    }
+
+    /// Namespace of generated PHP classes (`option php_namespace = "...";`)
+    pub fn php_namespace(&self) -> Option<String> {
+        self.string_option("php_namespace")
+    }
+
+    /// Module of generated Ruby classes (`option ruby_package = "...";`)
+    pub fn ruby_package(&self) -> Option<String> {
+        self.string_option("ruby_package")
+    }
+
+    /// Prefix of generated Swift types (`option swift_prefix = "...";`)
+    pub fn swift_prefix(&self) -> Option<String> {
+        self.string_option("swift_prefix")
+    }
+
+    /// Presence of a field of `message`
+    ///
+    /// proto2 and proto3 use the field's label. With editions, the `features.field_presence`
+    /// option of the field is used, then that of the message and the file, defaulting to
+    /// `EXPLICIT` as in edition 2023. Singular message fields, including well-known types
+    /// other than `NullValue`, and fields of a oneof always have explicit presence. Types
+    /// imported from other files can't be resolved here, so they follow the label.
+    pub fn field_presence(&self, message: &Message, field: &Field) -> FieldPresence {
+        if field.rule.variant == RuleVariant::Repeated {
+            return FieldPresence::Implicit;
+        }
+        match self.labeled_presence(message, field) {
+            FieldPresence::Implicit
+                if field.oneof_index.is_some() || self.is_message_field(message, field) =>
+            {
+                FieldPresence::Explicit
+            }
+            presence => presence,
+        }
+    }
+
+    fn is_message_field(&self, message: &Message, field: &Field) -> bool {
+        match &field.typ {
+            FieldType::Group(..) => true,
+            FieldType::MessageOrEnum(name) => match field.well_known_type() {
+                Some(typ) => typ != WellKnownType::NullValue,
+                None => validate::is_declared_message(self, message, name.as_ref()),
+            },
+            _ => false,
+        }
+    }
+
+    fn labeled_presence(&self, message: &Message, field: &Field) -> FieldPresence {
+        match self.syntax {
+            Syntax::Editions => field
+                .option(FIELD_PRESENCE_FEATURE)
+                .map(|opt| opt.value.fragment.0)
+                .or_else(|| decl_option(&message.options, FIELD_PRESENCE_FEATURE))
+                .or_else(|| decl_option(&self.options, FIELD_PRESENCE_FEATURE))
+                .and_then(FieldPresence::from_feature)
+                .unwrap_or(FieldPresence::Explicit),
+            Syntax::Proto2 | Syntax::Proto3 => match field.rule.variant {
+                RuleVariant::Required => FieldPresence::LegacyRequired,
+                RuleVariant::Optional if field.rule.position.is_some() => FieldPresence::Explicit,
+                _ => FieldPresence::Implicit,
+            },
+        }
+    }
+}
+
+/// Reads a built-in boolean option, the last one winning if it's set more than once
+fn bool_decl_option(options: &[DeclOption], name: &str) -> Option<bool> {
+    options
+        .iter()
+        .rev()
+        .find(|opt| matches!(&opt.name, DeclOptionName::BuiltIn(n) if n.as_ref() == name))
+        .and_then(|opt| match opt.proto_value() {
+            Some(ProtoValue::Bool(value)) => Some(value),
+            _ => None,
+        })
+}
+
+fn collect_messages<'m, 'a>(
+    prefix: &str,
+    messages: &'m [Message<'a>],
+    out: &mut Vec<(String, &'m Message<'a>)>,
+) {
+    for message in messages {
+        let name = match &message.name {
+            Some(name) if prefix.is_empty() => name.as_ref().to_string(),
+            Some(name) => format!("{}.{}", prefix, name.as_ref()),
+            None => continue,
+        };
+        out.push((name.clone(), message));
+        collect_messages(&name, &message.messages, out);
+    }
+}
+
+/// Value of the last built-in option with the given name
+fn decl_option<'a>(options: &[DeclOption<'a>], name: &str) -> Option<&'a str> {
+    options
+        .iter()
+        .rev()
+        .find(|opt| matches!(&opt.name, DeclOptionName::BuiltIn(n) if n.as_ref() == name))
+        .map(|opt| opt.value.fragment.0)
 }
 
 pub fn parse(proto_txt: &'_ str) -> Result<(Span<'_>, AbstractProto<'_>), ::nom::Err<Span<'_>>> {
     parser::parse(LocatedSpan::new(CompleteStr(proto_txt)))
 }
+
+/// Parses a whole proto file, failing at the first declaration which can't be read instead of
+/// returning the file up to it like `parse` does
+pub fn parse_complete(proto_txt: &'_ str) -> Result<AbstractProto<'_>, ParseError<'_>> {
+    let input = LocatedSpan::new(CompleteStr(proto_txt));
+    let (rest, proto) = parser::parse(input).map_err(|_| ParseError { rest: input })?;
+    let text = rest.fragment.0;
+    let unread = text.trim_start();
+    if unread.is_empty() {
+        return Ok(proto);
+    }
+    Err(ParseError {
+        rest: nom::Slice::slice(&rest, text.len() - unread.len()..),
+    })
+}
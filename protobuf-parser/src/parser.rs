@@ -1,5 +1,6 @@
 use super::*;
 use nom;
+use std::convert::TryFrom;
 use std::ops::RangeInclusive;
 use std::str;
 
@@ -11,35 +12,69 @@ named!(word(Span) -> Word,  do_parse!(
         word: take_while!(is_word) >>
         (Word {
             word,
+            synthetic: None,
         })
 ));
 
 named!(hex_integer(Span) -> Integer, do_parse!(
     position: position!()
-        >> tag!("0x")
-        >> num: map_res!(nom::hex_digit, |s: Span| {
-            i32::from_str_radix(s.fragment.as_ref(), 16)
-        })
+        >> literal: recognize!(preceded!(tag!("0x"), nom::hex_digit))
+        >> value: expr_res!(i32::from_str_radix(&literal.fragment.0[2..], 16))
         >> (Integer {
             position,
-            value: num
+            literal,
+            value
         })
 ));
 
+// Decimal integer, or octal if it starts with a `0`
 named!(integer(Span) -> Integer, do_parse!(
     position: position!()
-        >> num: map_res!(nom::digit, |s: Span| {
-            str::FromStr::from_str(s.fragment.as_ref())
+        >> literal: recognize!(nom::digit)
+        >> value: expr_res!(match literal.fragment.0 {
+            octal if octal.len() > 1 && octal.starts_with('0') => i32::from_str_radix(octal, 8),
+            decimal => decimal.parse(),
+        })
+        >> (Integer {
+            position,
+            literal,
+            value
         })
+));
+
+named!(int_literal(Span) -> Integer, alt!(hex_integer | integer));
+
+// An integer which may be negative, such as an enum value
+named!(signed_int_literal(Span) -> Integer, do_parse!(
+    position: position!()
+        >> literal: recognize!(pair!(opt!(tag!("-")), int_literal))
+        >> value: expr_opt!(signed_value(literal))
         >> (Integer {
             position,
-            value: num
+            literal,
+            value
         })
 ));
 
+fn signed_value(literal: Span) -> Option<i32> {
+    match literal.fragment.0.strip_prefix('-') {
+        Some(_) => int_literal(nom::Slice::slice(&literal, 1..))
+            .ok()
+            .and_then(|(_, int)| int.value.checked_neg()),
+        None => int_literal(literal).ok().map(|(_, int)| int.value),
+    }
+}
+
+// Rest of a line, without its newline, which is missing at the end of the file
+named!(line_rest(Span) -> Span, do_parse!(
+    text: take_till!(|c| c == '\n')
+        >> opt!(tag!("\n"))
+        >> (text)
+));
+
 named!(comment(Span) -> (), do_parse!(
     tag!("//")
-        >> take_until_and_consume!("\n")
+        >> line_rest
         >> ()
 ));
 
@@ -49,6 +84,44 @@ named!(block_comment(Span) -> (), do_parse!(
         >> ()
 ));
 
+// Text of a comment, without its markers and surrounding whitespace
+named!(comment_text(Span) -> Span, alt!(
+    do_parse!(tag!("//") >> text: line_rest >> (trim(text)))
+    | do_parse!(tag!("/*") >> text: take_until_and_consume!("*/") >> (trim(text)))
+));
+
+fn trim(span: Span) -> Span {
+    let text = span.fragment.0;
+    let start = text.len() - text.trim_start().len();
+    let end = text.trim_end().len().max(start);
+    nom::Slice::slice(&span, start..end)
+}
+
+fn is_inline_space(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+// Comments before a declaration, skipping any whitespace around them, and whether the
+// whitespace before the first of them has a blank line
+named!(leading_comments(Span) -> (Vec<Span>, bool), map!(
+    many0!(alt!(
+        map!(nom::multispace, |space| Err(space.fragment.0.matches('\n').count() > 1))
+        | map!(comment_text, Ok)
+    )),
+    |items: Vec<Result<Span, bool>>| {
+        let blank_line = items.first() == Some(&Err(true));
+        let comments = items.into_iter().filter_map(Result::ok).collect();
+        (comments, blank_line)
+    }
+));
+
+// A comment after a declaration on the same line
+named!(trailing_comment(Span) -> Span, do_parse!(
+    take_while!(is_inline_space)
+        >> text: comment_text
+        >> (text)
+));
+
 // word break: multispace or comment
 named!(br(Span) -> (), do_parse!(
     alt!(map!(nom::multispace, |_| ())
@@ -57,28 +130,42 @@ named!(br(Span) -> (), do_parse!(
         >> ()
 ));
 
-named!(syntax(Span) -> Syntax, do_parse!(
-    tag!("syntax")
-        >> many0!(br)
-        >> tag!("=")
-        >> many0!(br)
-        >> proto: alt!(tag!("\"proto2\"") => { |_| Syntax::Proto2 } |
-                       tag!("\"proto3\"") => { |_| Syntax::Proto3 })
-        >> many0!(br)
-        >> tag!(";")
-        >> (proto)
+named!(syntax(Span) -> Syntax, alt!(
+    do_parse!(
+        tag!("syntax")
+            >> many0!(br)
+            >> tag!("=")
+            >> many0!(br)
+            >> proto: alt!(tag!("\"proto2\"") => { |_| Syntax::Proto2 } |
+                           tag!("'proto2'") => { |_| Syntax::Proto2 } |
+                           tag!("\"proto3\"") => { |_| Syntax::Proto3 } |
+                           tag!("'proto3'") => { |_| Syntax::Proto3 })
+            >> many0!(br)
+            >> tag!(";")
+            >> (proto)
+    )
+    | do_parse!(
+        tag!("edition")
+            >> many0!(br)
+            >> tag!("=")
+            >> many0!(br)
+            >> quoted_string
+            >> many0!(br)
+            >> tag!(";")
+            >> (Syntax::Editions)
     )
-);
+));
 
 named!(import(Span) -> Word, do_parse!(
     tag!("import")
         >> many1!(br)
+        >> opt!(terminated!(alt!(tag!("public") | tag!("weak")), many0!(br)))
         >> tag!("\"")
         >> path: take_until!("\"")
         >> tag!("\"")
         >> many0!(br)
         >> tag!(";")
-        >> (Word { word: path })
+        >> (Word { word: path, synthetic: None })
 ));
 
 named!(package(Span) -> Word, do_parse!(
@@ -91,12 +178,15 @@ named!(package(Span) -> Word, do_parse!(
 ));
 
 named!(num_range(Span) -> RangeInclusive<i32>, do_parse!(
-    from_: integer
+    from_: int_literal
         >> many1!(br)
         >> tag!("to")
         >> many1!(br)
-        >> to_: integer
-        >> (from_.value..=to_.value)
+        >> to_: alt!(
+            tag!("max") => { |_| i32::MAX }
+            | int_literal => { |i: Integer| i.value }
+        )
+        >> (from_.value..=to_)
 ));
 
 named!(reserved_nums(Span) -> Vec<RangeInclusive<i32>>, do_parse!(
@@ -109,7 +199,7 @@ named!(reserved_nums(Span) -> Vec<RangeInclusive<i32>>, do_parse!(
                       >> (())
             ),
             alt!(num_range
-                 | integer => { |i: Integer| i.value..=i.value })
+                 | int_literal => { |i: Integer| i.value..=i.value })
         )
         >> many0!(br)
         >> tag!(";")
@@ -131,23 +221,76 @@ named!(reserved_names(Span) -> Vec<Word>, do_parse!(
         >> (names)
 ));
 
+// A custom option name such as `(sql).type` or `(custom.opt)`
+named!(custom_option_name(Span) -> Word, do_parse!(
+    name: recognize!(tuple!(tag!("("), word, tag!(")"), opt!(word)))
+        >> (Word {
+            word: name,
+            synthetic: None,
+        })
+));
+
+// Consumes a bracket option value up to the `,` or `]` ending it, which strings and aggregate
+// values can contain.
+fn bracket_option_value(input: Span) -> nom::IResult<Span, Span> {
+    let text = input.fragment.0;
+    let mut depth = 0;
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with('"') || rest.starts_with('\'') {
+            let (_, string) = quoted_string(nom::Slice::slice(&input, i..))?;
+            i += string.fragment.0.len();
+            continue;
+        }
+        let c = rest.chars().next().unwrap();
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' | ']' if depth == 0 && i > 0 => return Ok(nom::InputTake::take_split(&input, i)),
+            _ => {}
+        }
+        i += c.len_utf8();
+    }
+    Err(nom::Err::Error(error_position!(
+        input,
+        nom::ErrorKind::Custom(0)
+    )))
+}
+
 // formerly key_val
 named!(bracket_option(Span) -> BracketOption, do_parse!(
-    tag!("[")
-        >> many0!(br)
-        >> key: word
+    key: alt!(custom_option_name | word)
         >> many0!(br)
         >> tag!("=")
         >> many0!(br)
-        >> value: is_not!("]")
-        >> tag!("]")
-        >> many0!(br)
+        >> value: map!(bracket_option_value, trim)
         >> (BracketOption {
             key,
             value
         })
 ));
 
+// A bracket option list such as `[deprecated = true, (sql).type = "text"]`
+named!(bracket_options(Span) -> Vec<BracketOption>, do_parse!(
+    tag!("[")
+        >> many0!(br)
+        >> options: separated_nonempty_list!(
+            tuple!(many0!(br), tag!(","), many0!(br)),
+            bracket_option
+        )
+        >> many0!(br)
+        >> tag!("]")
+        >> many0!(br)
+        >> (options)
+));
+
+// Options of every bracket list after a field or enum value
+named!(all_bracket_options(Span) -> Vec<BracketOption>, map!(
+    many0!(bracket_options),
+    |lists: Vec<Vec<BracketOption>>| lists.into_iter().flatten().collect()
+));
+
 named!(rule(Span) -> Rule, do_parse!(
     position: position!()
         >> variant: alt!(tag!("optional") => { |_| RuleVariant::Optional } |
@@ -159,6 +302,20 @@ named!(rule(Span) -> Rule, do_parse!(
         })
 ));
 
+// A rule keyword only starts a field when a type and a name follow it, so types and fields
+// named `optional`, `repeated` or `required` aren't taken for the rule
+named!(field_rule(Span) -> Rule, terminated!(
+    rule,
+    peek!(tuple!(
+        many1!(br),
+        field_type,
+        many1!(br),
+        take_while1!(is_word),
+        many0!(br),
+        tag!("=")
+    ))
+));
+
 named!(map_field(Span) -> MapKVPair, do_parse!(
     tag!("map")
         >> position: position!()
@@ -178,46 +335,53 @@ named!(map_field(Span) -> MapKVPair, do_parse!(
         })
 ));
 
+// Classifies a type identifier, so it only has to be parsed once
+fn named_field_type(name: Word) -> FieldType {
+    match SCALAR_TYPES
+        .iter()
+        .find(|(keyword, _)| *keyword == name.as_ref())
+    {
+        Some((_, typ)) => typ.clone(),
+        None if name.as_ref() == "group" => FieldType::Group(Vec::new()),
+        None => FieldType::MessageOrEnum(name),
+    }
+}
+
 named!(field_type(Span) -> FieldType, do_parse!(
     ftype: alt!(
-        tag!("int32") => { |_| FieldType::Int32 }
-        | tag!("int64") => { |_| FieldType::Int64 }
-        | tag!("uint32") => { |_| FieldType::Uint32 }
-        | tag!("uint64") => { |_| FieldType::Uint64 }
-        | tag!("sint32") => { |_| FieldType::Sint32 }
-        | tag!("sint64") => { |_| FieldType::Sint64 }
-        | tag!("fixed32") => { |_| FieldType::Fixed32 }
-        | tag!("sfixed32") => { |_| FieldType::Sfixed32 }
-        | tag!("fixed64") => { |_| FieldType::Fixed64 }
-        | tag!("sfixed64") => { |_| FieldType::Sfixed64 }
-        | tag!("bool") => { |_| FieldType::Bool }
-        | tag!("string") => { |_| FieldType::String }
-        | tag!("bytes") => { |_| FieldType::Bytes }
-        | tag!("float") => { |_| FieldType::Float }
-        | tag!("double") => { |_| FieldType::Double }
-        | tag!("group") => { |_| FieldType::Group(Vec::new()) }
-        | map_field => { |kv| FieldType::Map(Box::new(kv)) }
-        | word => { |w| FieldType::MessageOrEnum(w) }
+        map_field => { |kv| FieldType::Map(Box::new(kv)) }
+        | word => { named_field_type }
     )
         >> (ftype)
 ));
 
 named!(fields_in_braces(Span) -> Vec<Field>, do_parse!(
     tag!("{")
-        >> many0!(br)
-        >> fields: separated_list!(br, message_field)
+        >> fields: many0!(message_field)
         >> many0!(br)
         >> tag!("}")
         >> (fields)
 ));
 
+// Fields of a oneof, which can also declare options
+named!(oneof_fields(Span) -> Vec<Field>, do_parse!(
+    tag!("{")
+        >> fields: many0!(alt!(
+            message_field => { Some }
+            | preceded!(many0!(br), option) => { |_| None }
+        ))
+        >> many0!(br)
+        >> tag!("}")
+        >> (fields.into_iter().flatten().collect())
+));
+
 named!(one_of(Span) -> OneOf, do_parse!(
     tag!("oneof")
         >> position: position!()
         >> many1!(br)
         >> name: word
         >> many0!(br)
-        >> fields: fields_in_braces
+        >> fields: oneof_fields
         >> many0!(br)
         >> (OneOf {
             position,
@@ -234,13 +398,42 @@ named!(group_fields_or_semicolon(Span) -> Option<Vec<Field>>, do_parse!(
         >> (res)
 ));
 
+named!(boolean(Span) -> bool, alt!(
+    tag!("true") => { |_| true }
+    | tag!("false") => { |_| false }
+));
+
+fn bool_option<'a>(opt: &BracketOption<'a>) -> Result<bool, OptionValueError<'a>> {
+    match boolean(opt.value) {
+        Ok((rest, value)) if rest.fragment.0.is_empty() => Ok(value),
+        _ => Err(OptionValueError {
+            name: opt.key.clone(),
+            value: opt.value,
+            expected: "a boolean",
+        }),
+    }
+}
+
+// The value of the boolean option `key`, failing the field if it isn't a boolean
+fn field_bool_option<'a>(
+    options: &[BracketOption<'a>],
+    key: &str,
+) -> Result<Option<bool>, OptionValueError<'a>> {
+    options
+        .iter()
+        .find(|opt| opt.key.as_ref() == key)
+        .map(bool_option)
+        .transpose()
+}
+
 // TODO(blt) This must be extended to support custom options. These are normal
 // fields but with a slightly different syntax, like:
 //
 //    option (my_option) = "Hello world!";
 
 named!(message_field(Span) -> Field, do_parse!(
-    rule: opt!(rule)
+    leading: leading_comments
+        >> rule: opt!(field_rule)
         >> many0!(br)
         >> typ: field_type
         >> many1!(br)
@@ -248,11 +441,14 @@ named!(message_field(Span) -> Field, do_parse!(
         >> many0!(br)
         >> tag!("=")
         >> many0!(br)
-        >> number: integer
+        >> number: int_literal
         >> many0!(br)
-        >> bracket_options: many0!(bracket_option)
+        >> bracket_options: all_bracket_options
         >> many0!(br)
+        >> packed: expr_res!(field_bool_option(&bracket_options, "packed"))
+        >> deprecated: expr_res!(field_bool_option(&bracket_options, "deprecated"))
         >> group_fields: group_fields_or_semicolon
+        >> trailing: opt!(trailing_comment)
         >> ({
             let typ = match (typ, group_fields) {
                 (FieldType::Group(..), Some(group_fields)) => FieldType::Group(group_fields),
@@ -268,22 +464,19 @@ named!(message_field(Span) -> Field, do_parse!(
                 default: bracket_options
                     .iter()
                     .find(|opt| opt.key.as_ref() == "default")
-                    .map(|opt| Word { word: opt.value }),
-                packed: bracket_options
-                    .iter()
-                    .find(|opt| opt.key.as_ref() == "packed")
-                    .map(|opt| {
-                        // TODO(blt): we should actually extend the parser to be
-                        // able to parse a boolean at parse time, rather than
-                        // crash deep here
-                        str::FromStr::from_str(opt.value.fragment.as_ref()).expect("Cannot parse Packed value")
-                    }),
-                deprecated: bracket_options
-                    .iter()
-                    .find(|opt| opt.key.as_ref() == "deprecated")
-                    .map_or(false, |opt| {
-                        str::FromStr::from_str(opt.value.fragment.as_ref()).expect("Cannot parse Deprecated value")
+                    .map(|opt| Word {
+                        word: opt.value,
+                        synthetic: None,
                     }),
+                packed,
+                deprecated: deprecated.unwrap_or(false),
+                comments: Comments {
+                    leading: leading.0,
+                    trailing,
+                    blank_line_before: leading.1,
+                },
+                options: bracket_options,
+                oneof_index: None,
             }
         })
 ));
@@ -295,51 +488,84 @@ enum MessageEvent<'a> {
     ReservedNums(Vec<RangeInclusive<i32>>),
     ReservedNames(Vec<Word<'a>>),
     OneOf(OneOf<'a>),
+    Extensions(Vec<Extension<'a>>),
+    Option(DeclOption<'a>),
     Ignore,
 }
 
+// Extension ranges such as `extensions 100 to max;`, which aren't kept
+named!(extension_ranges(Span) -> (), do_parse!(
+    tag!("extensions")
+        >> many1!(br)
+        >> take_until_and_consume!(";")
+        >> ()
+));
+
 named!(message_event(Span) -> MessageEvent, do_parse!(
-    res: alt!(reserved_nums => { |r| MessageEvent::ReservedNums(r) }
-              | reserved_names => { |r| MessageEvent::ReservedNames(r) }
-              | message_field => { |f| MessageEvent::Field(f) }
-              | message => { |m| MessageEvent::Message(m) }
-              | enumerator => { |e| MessageEvent::Enumeration(e) }
-              | one_of => { |o| MessageEvent::OneOf(o) }
+    res: alt!(reserved_nums => { MessageEvent::ReservedNums }
+              | reserved_names => { MessageEvent::ReservedNames }
+              | extension_ranges => { |_| MessageEvent::Ignore }
+              | message_field => { MessageEvent::Field }
+              | message => { MessageEvent::Message }
+              | enumerator => { MessageEvent::Enumeration }
+              | one_of => { MessageEvent::OneOf }
+              | extensions => { MessageEvent::Extensions }
+              | option => { MessageEvent::Option }
+              | tag!(";") => { |_| MessageEvent::Ignore }
               | br => { |_| MessageEvent::Ignore })
         >> (res)
 ));
 
-named!(message_events(Span) -> (Word, Vec<MessageEvent>), do_parse!(
-    tag!("message")
+// Parses a message into its name, events and the positions of its `message` keyword and
+// closing brace
+named!(message_events(Span) -> ((Vec<Span>, bool), Span, Word, Vec<MessageEvent>, Span), do_parse!(
+    leading: leading_comments
+        >> start: position!()
+        >> tag!("message")
         >> many1!(br)
         >> name: word
         >> many0!(br)
         >> tag!("{")
-        >> many0!(br)
         >> events: many0!(message_event)
         >> many0!(br)
+        >> end: position!()
         >> tag!("}")
-        >> many0!(br)
-        >> many0!(tag!(";"))
-        >> ((name, events))
+        // Comments after the message are left for the next declaration. Whitespace is only
+        // skipped before a `;`, so a blank line after the message is left for it too
+        >> opt!(do_parse!(many0!(nom::multispace) >> many1!(tag!(";")) >> ()))
+        >> ((leading, start, name, events, end))
 ));
 
 named!(message(Span) -> Message, do_parse!(
     res: map!(
         message_events,
-        |(name, events): (Word, Vec<MessageEvent>)| {
+        |(leading, start, name, events, end): ((Vec<Span>, bool), Span, Word, Vec<MessageEvent>, Span)| {
             let mut msg = Message {
                 name: Some(name),
+                position: Some(start),
+                end_position: Some(end),
+                comments: Comments {
+                    leading: leading.0,
+                    trailing: None,
+                    blank_line_before: leading.1,
+                },
                 ..Message::default()
             };
             for e in events {
                 match e {
                     MessageEvent::Field(f) => msg.fields.push(f),
-                    MessageEvent::ReservedNums(r) => msg.reserved_nums = r,
-                    MessageEvent::ReservedNames(r) => msg.reserved_names = r,
+                    MessageEvent::ReservedNums(r) => msg.reserved_nums.extend(r),
+                    MessageEvent::ReservedNames(r) => msg.reserved_names.extend(r),
                     MessageEvent::Message(m) => msg.messages.push(m),
                     MessageEvent::Enumeration(e) => msg.enums.push(e),
-                    MessageEvent::OneOf(o) => msg.oneofs.push(o),
+                    MessageEvent::OneOf(mut o) => {
+                        for field in &mut o.fields {
+                            field.oneof_index = Some(msg.oneofs.len());
+                        }
+                        msg.oneofs.push(o)
+                    }
+                    MessageEvent::Extensions(e) => msg.extensions.extend(e),
+                    MessageEvent::Option(o) => msg.options.push(o),
                     MessageEvent::Ignore => (),
                 }
             }
@@ -363,36 +589,63 @@ named!(extensions(Span) -> Vec<Extension>, do_parse!(
             }).collect())
 ));
 
+// Whitespace and comments before an enum value are left to its leading comments
 named!(enum_value(Span) -> EnumValue, do_parse!(
-    name: word
+    leading: leading_comments
+        >> name: word
         >> many0!(br)
         >> tag!("=")
         >> many0!(br)
-        >> number: alt!(hex_integer | integer)
+        >> number: signed_int_literal
         >> many0!(br)
+        >> options: all_bracket_options
         >> tag!(";")
-        >> many0!(br)
+        >> trailing: opt!(trailing_comment)
         >> (EnumValue {
             name,
             number,
+            comments: Comments {
+                leading: leading.0,
+                trailing,
+                blank_line_before: leading.1,
+            },
+            options,
         })
 ));
 
+// Options and reserved values of an enum, which aren't kept
+named!(enum_ignored(Span) -> (), alt!(
+    option => { |_| () }
+    | reserved_nums => { |_| () }
+    | reserved_names => { |_| () }
+    | tag!(";") => { |_| () }
+    | br
+));
+
 named!(enumerator(Span) -> Enumeration, do_parse!(
-    tag!("enum")
+    leading: leading_comments
+        >> tag!("enum")
         >> many1!(br)
         >> name: word
         >> many0!(br)
         >> tag!("{")
-        >> many0!(br)
-        >> values: many0!(enum_value)
+        >> values: many0!(alt!(
+            enum_value => { Some }
+            | enum_ignored => { |_| None }
+        ))
         >> many0!(br)
         >> tag!("}")
-        >> many0!(br)
-        >> many0!(tag!(";"))
+        // Whitespace is only skipped before a `;`, so a blank line after the declaration is
+        // left for the next one
+        >> opt!(do_parse!(many0!(nom::multispace) >> many1!(tag!(";")) >> ()))
         >> (Enumeration {
             name,
-            values,
+            values: values.into_iter().flatten().collect(),
+            comments: Comments {
+                leading: leading.0,
+                trailing: None,
+                blank_line_before: leading.1,
+            },
         })
 ));
 
@@ -412,6 +665,206 @@ named!(decl_option_builtin_name(Span) -> DeclOptionName, do_parse!(
         >> (DeclOptionName::BuiltIn(name))
 ));
 
+/// A quoted string literal including its quotes, which may contain escaped quotes and `;`
+fn quoted_string(input: Span) -> nom::IResult<Span, Span> {
+    let text = input.fragment.0;
+    let quote = match text.chars().next() {
+        Some(c @ '"') | Some(c @ '\'') => c,
+        _ => {
+            return Err(nom::Err::Error(error_position!(
+                input,
+                nom::ErrorKind::Custom(0)
+            )))
+        }
+    };
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return Ok(nom::InputTake::take_split(&input, i + 1));
+        }
+    }
+    Err(nom::Err::Error(error_position!(
+        input,
+        nom::ErrorKind::Custom(0)
+    )))
+}
+
+named!(pub(crate) string_literals(Span) -> Vec<Span>, do_parse!(
+    first: quoted_string
+        >> rest: many0!(do_parse!(many0!(br) >> string: quoted_string >> (string)))
+        >> ({
+            let mut strings = vec![first];
+            strings.extend(rest);
+            strings
+        })
+));
+
+fn is_number_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '-')
+}
+
+// A scalar value: strings, then numbers, then identifiers, of which `true` and `false` are
+// booleans
+named!(pub(crate) proto_value(Span) -> ProtoValue, alt!(
+    map!(string_literals, |strings: Vec<Span>| {
+        ProtoValue::Str(strings.iter().map(|string| {
+            let string = string.fragment.0;
+            unescape(&string[1..string.len() - 1])
+        }).collect())
+    })
+    | map_opt!(
+        recognize!(pair!(opt!(one_of!("+-")), take_while1!(is_number_char))),
+        |number: Span| number_value(number.fragment.0)
+    )
+    | map_opt!(word, ident_value)
+));
+
+fn ident_value(word: Word) -> Option<ProtoValue> {
+    match word.as_ref() {
+        // Numbers that couldn't be read, such as integers out of range
+        word if word.is_empty() || word.starts_with(|c: char| c.is_ascii_digit()) => None,
+        "true" => Some(ProtoValue::Bool(true)),
+        "false" => Some(ProtoValue::Bool(false)),
+        _ => Some(ProtoValue::Ident(word)),
+    }
+}
+
+/// Parses a whole option value, or `None` if it isn't a scalar
+pub(crate) fn parse_proto_value(value: Span) -> Option<ProtoValue> {
+    match proto_value(value) {
+        Ok((rest, value)) if rest.fragment.0.trim().is_empty() => Some(value),
+        _ => None,
+    }
+}
+
+/// Reads an integer in decimal, hex or octal, or a float. Integers outside of the `i64` range
+/// are `None` rather than a rounded float.
+fn number_value(text: &str) -> Option<ProtoValue<'static>> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let magnitude = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        u64::from_str_radix(hex, 16)
+    } else if digits.len() > 1
+        && digits.starts_with('0')
+        && digits.bytes().all(|b| b.is_ascii_digit())
+    {
+        u64::from_str_radix(&digits[1..], 8)
+    } else if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        digits.parse()
+    } else {
+        let sign = if negative { -1.0 } else { 1.0 };
+        return if digits.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+            || matches!(digits, "inf" | "nan")
+        {
+            let float: f64 = digits.parse().ok()?;
+            Some(ProtoValue::Float(sign * float))
+        } else {
+            None
+        };
+    };
+    let magnitude = magnitude.ok()?;
+    if negative {
+        i64::try_from(-i128::from(magnitude))
+            .ok()
+            .map(ProtoValue::Int)
+    } else {
+        Some(
+            i64::try_from(magnitude)
+                .map(ProtoValue::Int)
+                .unwrap_or(ProtoValue::UInt(magnitude)),
+        )
+    }
+}
+
+/// Decodes the escape sequences of a string literal's contents, such as `\n`, `\x41`, `\101`
+/// and `\u00e9`
+fn unescape(text: &str) -> String {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some('a') => bytes.push(0x07),
+            Some('b') => bytes.push(0x08),
+            Some('f') => bytes.push(0x0c),
+            Some('v') => bytes.push(0x0b),
+            Some(c @ 'x') | Some(c @ 'X') => {
+                let mut value = 0;
+                let mut len = 0;
+                while let Some(digit) = chars
+                    .peek()
+                    .and_then(|d| d.to_digit(16))
+                    .filter(|_| len < 2)
+                {
+                    value = value * 16 + digit;
+                    len += 1;
+                    chars.next();
+                }
+                if len == 0 {
+                    bytes.extend_from_slice(&[b'\\', c as u8]);
+                } else {
+                    bytes.push(value as u8);
+                }
+            }
+            Some(c @ 'u') | Some(c @ 'U') => {
+                let len = if c == 'u' { 4 } else { 8 };
+                let hex: String = chars.clone().take(len).collect();
+                match u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == len && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .and_then(char::from_u32)
+                {
+                    Some(decoded) => {
+                        let mut buf = [0; 4];
+                        bytes.extend_from_slice(decoded.encode_utf8(&mut buf).as_bytes());
+                        chars.nth(len - 1);
+                    }
+                    None => bytes.extend_from_slice(&[b'\\', c as u8]),
+                }
+            }
+            Some(c @ '0'..='7') => {
+                let mut value = c.to_digit(8).unwrap();
+                let mut len = 1;
+                while let Some(digit) = chars.peek().and_then(|d| d.to_digit(8)).filter(|_| len < 3)
+                {
+                    value = value * 8 + digit;
+                    len += 1;
+                    chars.next();
+                }
+                bytes.push(value as u8);
+            }
+            Some(c) => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+            None => bytes.push(b'\\'),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Drops trailing whitespace from an option value, keeping its position
+fn trim_end(value: Span) -> Span {
+    let len = value.fragment.0.trim_end().len();
+    nom::Slice::slice(&value, ..len)
+}
+
 named!(option(Span) -> DeclOption, do_parse!(
     tag!("option")
         >> many1!(br)
@@ -419,7 +872,14 @@ named!(option(Span) -> DeclOption, do_parse!(
         >> many0!(br)
         >> tag!("=")
         >> many0!(br)
-        >> value: take_until!(";")
+        >> value: map!(
+            alt!(
+                recognize!(string_literals)
+                    | recognize!(preceded!(tag!("{"), braced_body))
+                    | take_until!(";")
+            ),
+            trim_end
+        )
         >> many0!(br)
         >> many0!(tag!(";"))
         >> (DeclOption {
@@ -428,14 +888,180 @@ named!(option(Span) -> DeclOption, do_parse!(
         })
 ));
 
-named!(service_ignore(Span) -> (), do_parse!(
+/// Fields of an aggregate option value such as `{ get: "/v1/users/{id}" }`, in order.
+///
+/// The `:` after a field name and `,` or `;` between fields are optional, as in the protobuf
+/// text format. String values are unquoted, other values are kept as written.
+pub(crate) fn aggregate_fields(value: Span) -> Option<Vec<(String, String)>> {
+    let text = value.fragment.0.trim();
+    let body = text.strip_prefix('{')?.strip_suffix('}')?;
+    let mut fields = Vec::new();
+    let mut rest = body;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',' || c == ';');
+        if rest.is_empty() {
+            return Some(fields);
+        }
+
+        let name_len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if name_len == 0 {
+            return None;
+        }
+        let name = &rest[..name_len];
+        rest = rest[name_len..].trim_start();
+        rest = rest.strip_prefix(':').unwrap_or(rest).trim_start();
+
+        let span = Span::new(CompleteStr(rest));
+        let (value, len) = if let Ok((remaining, strings)) = string_literals(span) {
+            let value = strings
+                .iter()
+                .map(|string| {
+                    let string = string.fragment.0;
+                    &string[1..string.len() - 1]
+                })
+                .collect();
+            (value, rest.len() - remaining.fragment.0.len())
+        } else if rest.starts_with('{') {
+            let (remaining, _) = braced_body(nom::Slice::slice(&span, 1..)).ok()?;
+            let len = rest.len() - remaining.fragment.0.len();
+            (rest[..len].to_string(), len)
+        } else if rest.starts_with('[') {
+            let len = rest.find(']')? + 1;
+            (rest[..len].to_string(), len)
+        } else {
+            let len = rest
+                .find(|c: char| c.is_whitespace() || c == ',' || c == ';')
+                .unwrap_or(rest.len());
+            if len == 0 {
+                return None;
+            }
+            (rest[..len].to_string(), len)
+        };
+        fields.push((name.to_string(), value));
+        rest = &rest[len..];
+    }
+}
+
+// Consumes everything up to and including the `}` matching an already consumed `{`,
+// skipping braces in nested blocks, strings and comments.
+fn braced_body(input: Span) -> nom::IResult<Span, Span> {
+    let text = input.fragment.0;
+    let mut depth = 1;
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with("//") {
+            i += rest.find('\n').unwrap_or(rest.len());
+        } else if rest.starts_with("/*") {
+            i += rest.find("*/").map_or(rest.len(), |end| end + 2);
+        } else if rest.starts_with('"') || rest.starts_with('\'') {
+            let (_, string) = quoted_string(nom::Slice::slice(&input, i..))?;
+            i += string.fragment.0.len();
+        } else {
+            let c = rest.chars().next().unwrap();
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(nom::InputTake::take_split(&input, i + 1));
+                    }
+                }
+                _ => {}
+            }
+            i += c.len_utf8();
+        }
+    }
+    Err(nom::Err::Error(error_position!(
+        input,
+        nom::ErrorKind::Custom(0)
+    )))
+}
+
+// The request or response type of an rpc, such as `(stream Event)`, and whether it's a stream
+named!(rpc_type(Span) -> (bool, Word), do_parse!(
+    tag!("(")
+        >> many0!(br)
+        >> stream: opt!(terminated!(tag!("stream"), many1!(br)))
+        >> typ: word
+        >> many0!(br)
+        >> tag!(")")
+        >> ((stream.is_some(), typ))
+));
+
+// The options of an rpc with a body, skipping anything else in it
+named!(rpc_body(Span) -> Vec<DeclOption>, do_parse!(
+    tag!("{")
+        >> options: many0!(alt!(
+            option => { Some }
+            | br => { |_| None }
+            | tag!(";") => { |_| None }
+        ))
+        >> tag!("}")
+        >> (options.into_iter().flatten().collect())
+));
+
+named!(rpc(Span) -> Rpc, do_parse!(
+    tag!("rpc")
+        >> many1!(br)
+        >> name: word
+        >> many0!(br)
+        >> input: rpc_type
+        >> many0!(br)
+        >> tag!("returns")
+        >> many0!(br)
+        >> output: rpc_type
+        >> many0!(br)
+        >> options: alt!(
+            tag!(";") => { |_| Vec::new() }
+            | rpc_body
+        )
+        >> (Rpc {
+            name,
+            input_type: input.1,
+            output_type: output.1,
+            client_streaming: input.0,
+            server_streaming: output.0,
+            options,
+        })
+));
+
+enum ServiceEvent<'a> {
+    Rpc(Rpc<'a>),
+    Option(DeclOption<'a>),
+    Ignore,
+}
+
+named!(service(Span) -> Service, do_parse!(
     tag!("service")
         >> many1!(br)
-        >> word
+        >> name: word
         >> many0!(br)
         >> tag!("{")
-        >> take_until_and_consume!("}")
-        >> ()
+        >> events: many0!(alt!(
+            rpc => { ServiceEvent::Rpc }
+            | option => { ServiceEvent::Option }
+            | br => { |_| ServiceEvent::Ignore }
+            | tag!(";") => { |_| ServiceEvent::Ignore }
+        ))
+        >> tag!("}")
+        >> ({
+            let mut service = Service {
+                name,
+                rpcs: Vec::new(),
+                options: Vec::new(),
+            };
+            for event in events {
+                match event {
+                    ServiceEvent::Rpc(rpc) => service.rpcs.push(rpc),
+                    ServiceEvent::Option(option) => service.options.push(option),
+                    ServiceEvent::Ignore => (),
+                }
+            }
+            service
+        })
 ));
 
 #[derive(Debug, Clone)]
@@ -443,23 +1069,25 @@ pub enum Event<'a> {
     Syntax(Syntax),
     Import(Word<'a>),
     Package(Word<'a>),
-    Message(Message<'a>),
+    Message(Box<Message<'a>>),
     Enum(Enumeration<'a>),
     DeclOption(DeclOption<'a>),
     Extensions(Vec<Extension<'a>>),
+    Service(Service<'a>),
     Ignore,
 }
 
 named!(event(Span) -> Event, do_parse!(
     res: alt!(
-        syntax => { |s| Event::Syntax(s) }
-        | import => { |i| Event::Import(i) }
-        | package => { |p| Event::Package(p) }
-        | message => { |m| Event::Message(m) }
-        | enumerator => { |e| Event::Enum(e) }
-        | extensions => { |e| Event::Extensions(e) }
-        | option => { |o| Event::DeclOption(o) }
-        | service_ignore => { |_| Event::Ignore }
+        syntax => { Event::Syntax }
+        | import => { Event::Import }
+        | package => { Event::Package }
+        | message => { |m| Event::Message(Box::new(m)) }
+        | enumerator => { Event::Enum }
+        | extensions => { Event::Extensions }
+        | option => { Event::DeclOption }
+        | service => { Event::Service }
+        | tag!(";") => { |_| Event::Ignore }
         | br => { |_| Event::Ignore })
         >> (res)
 ));
@@ -471,13 +1099,17 @@ named!(pub parse(Span) -> AbstractProto, do_parse!(
             // TODO(blt) provide some validation here. For instance, we can
             // confirm that the package isn't set multiple times.
             match event {
-                Event::Syntax(s) => desc.syntax = s,
+                Event::Syntax(s) => {
+                    desc.syntax = s;
+                    desc.syntax_declared = true;
+                }
                 Event::Import(i) => desc.import_paths.push(i),
                 Event::Package(p) => desc.package = Some(p),
-                Event::Message(m) => desc.messages.push(m),
+                Event::Message(m) => desc.messages.push(*m),
                 Event::Enum(e) => desc.enums.push(e),
                 Event::Extensions(e) => desc.extensions.extend(e),
                 Event::DeclOption(d) => desc.options.push(d),
+                Event::Service(s) => desc.services.push(s),
                 Event::Ignore => (),
             }
         }
@@ -503,7 +1135,8 @@ mod test {
                     offset: 0,
                     line: 1,
                     fragment: CompleteStr("Scenar.io_Inf12o")
-                }
+                },
+                synthetic: None,
             }
         );
         assert_eq!(
@@ -530,6 +1163,11 @@ mod test {
                     line: 1,
                     fragment: CompleteStr("")
                 },
+                literal: LocatedSpan {
+                    offset: 0,
+                    line: 1,
+                    fragment: CompleteStr("0x1AEF")
+                },
                 value: 6895,
             }
         );
@@ -557,6 +1195,11 @@ mod test {
                     line: 1,
                     fragment: CompleteStr("")
                 },
+                literal: LocatedSpan {
+                    offset: 0,
+                    line: 1,
+                    fragment: CompleteStr("123456789")
+                },
                 value: 123456789,
             }
         );
@@ -570,6 +1213,55 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_integer_literal() {
+        let parse_int = |input| int_literal(Span::new(CompleteStr(input))).unwrap().1;
+        let hex = parse_int("0x10");
+        let octal = parse_int("020");
+        let decimal = parse_int("16");
+        assert_eq!((hex.value(), octal.value(), decimal.value()), (16, 16, 16));
+        assert_eq!(
+            (hex.literal(), octal.literal(), decimal.literal()),
+            ("0x10", "020", "16")
+        );
+        assert_eq!(hex, decimal);
+        assert_eq!(octal, decimal);
+        assert_eq!(parse_int("0").value(), 0);
+
+        let (_, msg) = message(Span::new(CompleteStr("message A { int32 a = 0x10; }"))).unwrap();
+        assert_eq!(msg.fields[0].number.value(), 16);
+        assert_eq!(msg.fields[0].number.literal(), "0x10");
+    }
+
+    #[test]
+    fn test_field_type_parse() {
+        let parse_type = |input| field_type(Span::new(CompleteStr(input))).unwrap().1;
+        assert_eq!(parse_type("int32"), FieldType::Int32);
+        assert_eq!(parse_type("sfixed64"), FieldType::Sfixed64);
+        assert_eq!(parse_type("group"), FieldType::Group(Vec::new()));
+        match parse_type("stringList") {
+            FieldType::MessageOrEnum(name) => assert_eq!(name.as_ref(), "stringList"),
+            typ => panic!("expected message type, got {:?}", typ),
+        }
+        match parse_type("map<string, int32>") {
+            FieldType::Map(kv) => {
+                assert_eq!(kv.key, FieldType::String);
+                assert_eq!(kv.value, FieldType::Int32);
+            }
+            typ => panic!("expected map type, got {:?}", typ),
+        }
+        match parse_type("map<string, Address>") {
+            FieldType::Map(kv) => {
+                assert_eq!(kv.key, FieldType::String);
+                assert_eq!(
+                    kv.value_type_name().map(|name| name.as_ref()),
+                    Some("Address")
+                );
+            }
+            typ => panic!("expected map type, got {:?}", typ),
+        }
+    }
+
     #[test]
     fn test_message() {
         let input = Span::new(CompleteStr(
@@ -612,6 +1304,435 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_bool_option_error_location() {
+        let source = "message A {\n  repeated int32 a = 1 [packed = yes];\n}";
+        let input = Span::new(CompleteStr(source));
+        let bracket = source.find('[').unwrap();
+        let (_, opts) = bracket_options(nom::Slice::slice(&input, bracket..)).unwrap();
+        let err = bool_option(&opts[0]).unwrap_err();
+        assert_eq!(err.value.fragment, CompleteStr("yes"));
+        assert_eq!(
+            err.to_string(),
+            "invalid value 'yes' for option 'packed' at line 2, column 34: expected a boolean"
+        );
+    }
+
+    #[test]
+    fn test_invalid_bool_option() {
+        assert!(!boolean(Span::new(CompleteStr("false"))).unwrap().1);
+        assert!(boolean(Span::new(CompleteStr("yes"))).is_err());
+
+        let field = |text| message_field(Span::new(CompleteStr(text)));
+        assert_eq!(
+            field("repeated int32 a = 1 [packed = true];")
+                .unwrap()
+                .1
+                .packed,
+            Some(true)
+        );
+        assert!(field("repeated int32 a = 1 [packed = yes];").is_err());
+        assert!(field("int32 a = 1 [deprecated = ture];").is_err());
+
+        // The message which fails is left unread
+        let (rest, proto) = parse(Span::new(CompleteStr(
+            "message A {\n  repeated int32 a = 1 [packed = yes];\n}",
+        )))
+        .unwrap();
+        assert!(proto.messages.is_empty());
+        assert_eq!(rest.line, 1);
+        let (rest, _) = parse(Span::new(CompleteStr(
+            "message A {\n  repeated int32 a = 1 [packed = false];\n}\n",
+        )))
+        .unwrap();
+        assert!(rest.fragment.is_empty());
+    }
+
+    #[test]
+    fn test_parse_complete() {
+        let proto = ::parse_complete(
+            r#"syntax = "proto2";
+import public "a.proto";
+import weak "b.proto";
+enum Status {
+  option allow_alias = true;
+  UNKNOWN = -1;
+  ACTIVE = 1;
+  ENABLED = 1;
+  reserved 5 to max;
+  reserved "DELETED";
+};
+message User {
+  extensions 100 to max;
+  reserved 2;
+  oneof contact {
+    option (validate.required) = true;
+    string email = 1;
+  }
+  ;
+}"#,
+        )
+        .unwrap();
+        assert_eq!(proto.import_paths.len(), 2);
+        let numbers: Vec<_> = proto.enums[0]
+            .values
+            .iter()
+            .map(|value| (value.number.value(), value.number.literal()))
+            .collect();
+        assert_eq!(numbers, vec![(-1, "-1"), (1, "1"), (1, "1")]);
+        assert_eq!(proto.messages[0].oneofs[0].fields.len(), 1);
+
+        let err = ::parse_complete(
+            "message A {}\n\nmessage B {\n  repeated int32 b = 1 [packed = yes];\n}\nmessage C {}",
+        )
+        .unwrap_err();
+        assert_eq!(err.line(), 3);
+        assert_eq!(err.to_string(), "could not parse 'message B {' at line 3");
+
+        // A service that can't be read fails the file instead of being left out of `services`
+        let err = ::parse_complete(
+            "message A {}
+service Users {
+  rpc Get (A) returns (A) [deprecated = true];
+}",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "could not parse 'service Users {' at line 2"
+        );
+    }
+
+    #[test]
+    fn test_nested_extend() {
+        let input = Span::new(CompleteStr(
+            r#"message Foo {
+    optional int32 a = 1;
+    extend Bar {
+        optional Foo foo = 100;
+        optional string name = 101;
+    }
+    optional int32 b = 2;
+}"#,
+        ));
+        let (_, msg) = message(input).unwrap();
+        assert_eq!(msg.fields.len(), 2);
+        assert_eq!(msg.extensions.len(), 2);
+        assert_eq!(msg.extensions[0].extendee.as_ref(), "Bar");
+        assert_eq!(msg.extensions[0].field.name.as_ref(), "foo");
+        assert_eq!(msg.extensions[1].field.name.as_ref(), "name");
+    }
+
+    #[test]
+    fn test_json_name() {
+        let input = Span::new(CompleteStr(
+            r#"message User {
+    int64 user_id = 1;
+    string name = 2;
+    string email_address = 3 [json_name = "mail"];
+}
+"#,
+        ));
+        let (_, proto) = parse(input).unwrap();
+        let names: Vec<_> = proto.messages[0]
+            .fields
+            .iter()
+            .map(|field| field.json_name())
+            .collect();
+        assert_eq!(names, vec!["userId", "name", "mail"]);
+    }
+
+    #[test]
+    fn test_materialize_map_entries() {
+        let input = Span::new(CompleteStr(
+            r#"syntax = "proto2";
+message Item {
+    option deprecated = true;
+    map<string, int32> stock_levels = 1;
+    message Inner {
+        map<int64, Item> items = 1;
+    }
+}
+"#,
+        ));
+        let (_, mut proto) = parse(input).unwrap();
+        assert_eq!(proto.messages[0].options.len(), 1);
+        assert_eq!(proto.messages[0].messages.len(), 1);
+
+        proto.materialize_map_entries();
+        let item = &proto.messages[0];
+        assert_eq!(item.messages.len(), 2);
+        let entry = &item.messages[1];
+        assert_eq!(entry.name.as_ref().unwrap().as_ref(), "StockLevelsEntry");
+        assert!(entry.is_map_entry());
+        assert!(!item.is_map_entry());
+        assert_eq!(entry.fields[0].name.as_ref(), "key");
+        assert_eq!(entry.fields[0].typ, FieldType::String);
+        assert_eq!(entry.fields[0].number.value, 1);
+        assert_eq!(entry.fields[1].name.as_ref(), "value");
+        assert_eq!(entry.fields[1].typ, FieldType::Int32);
+        assert_eq!(entry.fields[1].rule.variant, RuleVariant::Optional);
+
+        let inner_entry = &item.messages[0].messages[0];
+        assert_eq!(inner_entry.name.as_ref().unwrap().as_ref(), "ItemsEntry");
+        assert!(proto.validate().is_empty());
+
+        // Running it again doesn't add the entries twice
+        proto.materialize_map_entries();
+        assert_eq!(proto.messages[0].messages.len(), 2);
+    }
+
+    #[test]
+    fn test_custom_bracket_option() {
+        let input = Span::new(CompleteStr(
+            r#"optional int64 price = 1 [(sql).type = "numeric(10,2)" ];"#,
+        ));
+        let (_, field) = message_field(input).unwrap();
+        let opt = field.option("(sql).type").unwrap();
+        assert_eq!(opt.value().fragment.0, r#""numeric(10,2)""#);
+        assert_eq!(opt.string_value(), Some("numeric(10,2)"));
+        assert!(field.option("packed").is_none());
+    }
+
+    #[test]
+    fn test_mixed_bracket_options() {
+        let input = Span::new(CompleteStr(
+            r#"optional string code = 1 [deprecated = true, (custom.opt) = "x, y]",
+                (sql).type = "varchar(8)", default = "none"];"#,
+        ));
+        let (_, field) = message_field(input).unwrap();
+        let options: Vec<_> = field
+            .options
+            .iter()
+            .map(|opt| (opt.key().as_ref(), opt.value().fragment.0))
+            .collect();
+        assert_eq!(
+            options,
+            vec![
+                ("deprecated", "true"),
+                ("(custom.opt)", r#""x, y]""#),
+                ("(sql).type", r#""varchar(8)""#),
+                ("default", r#""none""#),
+            ]
+        );
+        assert!(field.deprecated);
+        assert_eq!(field.default.as_ref().unwrap().as_ref(), r#""none""#);
+        assert_eq!(
+            field.option("(custom.opt)").unwrap().string_value(),
+            Some("x, y]")
+        );
+
+        let input = Span::new(CompleteStr(
+            "repeated int32 ids = 2 [packed = true] [(custom.opt) = { a: 1, b: [2] }];",
+        ));
+        let (_, field) = message_field(input).unwrap();
+        assert_eq!(field.packed, Some(true));
+        assert_eq!(
+            field.option("(custom.opt)").unwrap().value().fragment.0,
+            "{ a: 1, b: [2] }"
+        );
+    }
+
+    #[test]
+    fn test_blank_line_before() {
+        let input = Span::new(CompleteStr(
+            r#"message User {
+  int64 id = 1;
+  string name = 2;
+
+  // Contact details
+  string email = 3;
+  message Address {}
+
+  enum Kind {
+    KIND_UNKNOWN = 0;
+
+    KIND_ADMIN = 1;
+  }
+  // Phone number
+
+  string phone = 4;
+}"#,
+        ));
+        let (_, msg) = message(input).unwrap();
+        let blank_lines: Vec<_> = msg
+            .fields
+            .iter()
+            .map(|field| field.comments.blank_line_before)
+            .collect();
+        assert_eq!(blank_lines, vec![false, false, true, false]);
+        assert_eq!(
+            msg.fields[2].comments.leading[0].fragment.0,
+            "Contact details"
+        );
+        assert!(!msg.messages[0].comments.blank_line_before);
+        assert!(msg.enums[0].comments.blank_line_before);
+        assert!(!msg.enums[0].values[0].comments.blank_line_before);
+        assert!(msg.enums[0].values[1].comments.blank_line_before);
+    }
+
+    #[test]
+    fn test_oneof_index() {
+        let input = Span::new(CompleteStr(
+            r#"message Contact {
+  int64 id = 1;
+  oneof address {
+    string email = 2;
+    string phone = 3;
+  }
+  oneof avatar {
+    string url = 4;
+  }
+}"#,
+        ));
+        let (_, msg) = message(input).unwrap();
+        let indexes: Vec<_> = msg
+            .all_fields()
+            .map(|field| (field.name.as_ref(), field.oneof_index))
+            .collect();
+        assert_eq!(
+            indexes,
+            vec![
+                ("id", None),
+                ("email", Some(0)),
+                ("phone", Some(0)),
+                ("url", Some(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reserved_single_range() {
+        for (source, expected) in &[
+            ("reserved 2 to 10;", vec![2..=10]),
+            ("reserved 5;", vec![5..=5]),
+            ("reserved 2 to 10 ;", vec![2..=10]),
+            ("reserved 5\n;", vec![5..=5]),
+            (
+                "reserved 4, 15, 17 to 20, 30;",
+                vec![4..=4, 15..=15, 17..=20, 30..=30],
+            ),
+        ] {
+            let (rest, nums) = reserved_nums(Span::new(CompleteStr(source))).unwrap();
+            assert!(rest.fragment.is_empty(), "{}", source);
+            assert_eq!(&nums, expected, "{}", source);
+        }
+
+        let input = Span::new(CompleteStr(
+            "message Sample {\n  reserved 2 to 10;\n  reserved 12;\n  uint64 age = 1;\n}",
+        ));
+        let (_, msg) = message(input).unwrap();
+        assert_eq!(msg.reserved_nums, vec![2..=10, 12..=12]);
+        assert_eq!(msg.fields.len(), 1);
+    }
+
+    #[test]
+    fn test_well_known_types() {
+        for typ in WellKnownType::ALL {
+            let source = format!("google.protobuf.{} a = 1;", typ.name());
+            let (_, field) = message_field(Span::new(CompleteStr(&source))).unwrap();
+            assert_eq!(field.well_known_type(), Some(*typ));
+        }
+
+        let (_, field) =
+            message_field(Span::new(CompleteStr(".google.protobuf.Duration a = 1;"))).unwrap();
+        assert_eq!(field.well_known_type(), Some(WellKnownType::Duration));
+
+        for source in &[
+            "shop.Timestamp a = 1;",
+            "google.protobuf.Custom a = 1;",
+            "string a = 1;",
+        ] {
+            let (_, field) = message_field(Span::new(CompleteStr(source))).unwrap();
+            assert!(!field.is_well_known_type());
+        }
+    }
+
+    #[test]
+    fn test_comments_without_spaces() {
+        let input = Span::new(CompleteStr(
+            "syntax = \"proto3\";//syntax\nmessage User {//user\n  int32 id=1;//id\n  string name=2;/*name*/\n}//end",
+        ));
+        let (rest, proto) = parse(input).unwrap();
+        assert!(rest.fragment.is_empty());
+        let trailing: Vec<_> = proto.messages[0]
+            .fields
+            .iter()
+            .map(|field| field.comments.trailing.map(|comment| comment.fragment.0))
+            .collect();
+        assert_eq!(trailing, vec![Some("id"), Some("name")]);
+    }
+
+    #[test]
+    fn test_field_comments() {
+        let input = Span::new(CompleteStr(
+            r#"message User {
+    // The user id
+    // Never reused
+    int64 id = 1; // the user id
+    /* Display name */
+    string name = 2;
+    oneof contact {
+string email = 3; // primary
+string phone = 4;
+    }
+}
+"#,
+        ));
+        let (_, msg) = message(input).unwrap();
+        let comments = |field: &Field| {
+            (
+                field
+                    .comments
+                    .leading
+                    .iter()
+                    .map(|comment| comment.fragment.0.to_string())
+                    .collect::<Vec<_>>(),
+                field
+                    .comments
+                    .trailing
+                    .map(|comment| comment.fragment.0.to_string()),
+            )
+        };
+        assert_eq!(
+            comments(&msg.fields[0]),
+            (
+                vec!["The user id".to_string(), "Never reused".to_string()],
+                Some("the user id".to_string())
+            )
+        );
+        assert_eq!(
+            comments(&msg.fields[1]),
+            (vec!["Display name".to_string()], None)
+        );
+        assert_eq!(
+            comments(&msg.oneofs[0].fields[0]),
+            (vec![], Some("primary".to_string()))
+        );
+        assert_eq!(comments(&msg.oneofs[0].fields[1]), (vec![], None));
+        assert_eq!(msg.fields[0].comments.trailing.unwrap().line, 4);
+    }
+
+    #[test]
+    fn test_message_line_range() {
+        let input = Span::new(CompleteStr(
+            r#"syntax = "proto2";
+
+message Foo {
+    optional int32 a = 1;
+    message Bar {
+        optional string b = 1;
+    }
+}
+"#,
+        ));
+        let (_, proto) = parse(input).unwrap();
+        let msg = &proto.messages[0];
+        assert_eq!(msg.line_range(), 3..=8);
+        assert_eq!(msg.messages[0].line_range(), 5..=7);
+        assert_eq!(Message::default().line_range(), 0..=0);
+    }
+
     // #[test]
     // fn test_enum() {
     //     let msg = r#"enum PairingStatus {
@@ -645,7 +1766,8 @@ mod test {
                         offset: 7,
                         line: 1,
                         fragment: CompleteStr("optimize_for")
-                    }
+                    },
+                    synthetic: None,
                 }),
                 value: LocatedSpan {
                     offset: 22,
@@ -664,6 +1786,26 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_adjacent_string_option() {
+        let input = Span::new(CompleteStr(
+            r#"option (description) = "foo"
+    // continued
+    "bar";
+message User {}"#,
+        ));
+        let (remainder, opt) = option(input).unwrap();
+        assert_eq!(opt.string_value().as_deref(), Some("foobar"));
+        assert_eq!(opt.value().line, 1);
+        assert!(opt.value().fragment.0.ends_with(r#""bar""#));
+        assert_eq!(remainder.fragment.0.trim(), "message User {}");
+
+        let (_, opt) = option(Span::new(CompleteStr(r#"option go_package = "a;b";"#))).unwrap();
+        assert_eq!(opt.string_value().as_deref(), Some("a;b"));
+        let (_, opt) = option(Span::new(CompleteStr("option optimize_for = SPEED;"))).unwrap();
+        assert_eq!(opt.string_value(), None);
+    }
+
     #[test]
     fn test_custom_option() {
         let input = Span::new(CompleteStr(r#"option (unity.optimize_for) = lolSPEED;"#));
@@ -680,7 +1822,8 @@ mod test {
                         offset: 8,
                         line: 1,
                         fragment: CompleteStr("unity.optimize_for")
-                    }
+                    },
+                    synthetic: None,
                 }),
                 value: LocatedSpan {
                     offset: 30,
@@ -699,6 +1842,574 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_options_on_one_line() {
+        let input = Span::new(CompleteStr(
+            r#"option a = 1; option b = 2 ;option go_package = "example.com/x;x";"#,
+        ));
+        let (rest, proto) = parse(input).unwrap();
+        assert!(rest.fragment.is_empty());
+        let options: Vec<_> = proto
+            .options
+            .iter()
+            .map(|opt| match opt.name() {
+                DeclOptionName::BuiltIn(name) => (name.as_ref(), opt.value().fragment.0),
+                DeclOptionName::Custom(name) => (name.as_ref(), opt.value().fragment.0),
+            })
+            .collect();
+        assert_eq!(
+            options,
+            vec![
+                ("a", "1"),
+                ("b", "2"),
+                ("go_package", r#""example.com/x;x""#)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bool_file_options() {
+        let input = Span::new(CompleteStr(
+            r#"syntax = "proto3";
+option deprecated = true;
+option java_multiple_files = yes;
+option optimize_for = SPEED;
+"#,
+        ));
+        let (_, proto) = parse(input).unwrap();
+        assert!(proto.is_deprecated());
+        assert_eq!(proto.bool_option("deprecated"), Some(true));
+        assert_eq!(proto.bool_option("java_multiple_files"), None);
+        assert!(!proto.java_multiple_files());
+        assert_eq!(proto.bool_option("cc_enable_arenas"), None);
+    }
+
+    #[test]
+    fn test_language_file_options() {
+        let input = Span::new(CompleteStr(
+            r#"syntax = "proto3";
+option cc_enable_arenas = true;
+option objc_class_prefix = "SHP";
+option csharp_namespace = "Shop.V1";
+option php_namespace = "Shop\V1";
+option ruby_package = "Shop::V1";
+option swift_prefix = 42;
+option (custom.lang) = "Shop";
+"#,
+        ));
+        let (_, proto) = parse(input).unwrap();
+        assert!(proto.cc_enable_arenas());
+        assert_eq!(proto.objc_class_prefix().as_deref(), Some("SHP"));
+        assert_eq!(proto.csharp_namespace().as_deref(), Some("Shop.V1"));
+        assert_eq!(proto.php_namespace().as_deref(), Some("Shop\\V1"));
+        assert_eq!(proto.ruby_package().as_deref(), Some("Shop::V1"));
+        // Not a string
+        assert_eq!(proto.swift_prefix(), None);
+        // Custom options are kept in the option list
+        assert_eq!(proto.options.len(), 7);
+        assert_eq!(proto.string_option("lang"), None);
+    }
+
+    #[test]
+    fn test_single_quoted_syntax() {
+        let (_, proto) = parse(Span::new(CompleteStr(
+            "syntax = 'proto3';\nmessage User { int64 id = 1; }",
+        )))
+        .unwrap();
+        assert_eq!(proto.syntax, Syntax::Proto3);
+        assert!(proto.syntax_declared);
+        assert_eq!(proto.messages.len(), 1);
+        let (_, proto) = parse(Span::new(CompleteStr("syntax = 'proto2';"))).unwrap();
+        assert_eq!(proto.syntax, Syntax::Proto2);
+        assert!(proto.syntax_declared);
+    }
+
+    #[test]
+    fn test_edition_field_presence() {
+        let input = Span::new(CompleteStr(
+            r#"edition = "2023";
+option features.field_presence = IMPLICIT;
+message User {
+  int64 id = 1;
+  string nickname = 2 [features.field_presence = EXPLICIT];
+  repeated string tags = 3;
+}
+message Order {
+  option features.field_presence = LEGACY_REQUIRED;
+  int64 id = 1;
+}
+"#,
+        ));
+        let (_, proto) = parse(input).unwrap();
+        assert!(matches!(proto.syntax, Syntax::Editions));
+        assert!(proto.syntax_declared);
+        let (_, undeclared) = parse(Span::new(CompleteStr("message User {}"))).unwrap();
+        assert_eq!(undeclared.syntax, Syntax::Proto2);
+        assert!(!undeclared.syntax_declared);
+        let presence = |proto: &AbstractProto, message: &Message| -> Vec<_> {
+            message
+                .fields
+                .iter()
+                .map(|field| proto.field_presence(message, field))
+                .collect()
+        };
+        assert_eq!(
+            presence(&proto, &proto.messages[0]),
+            vec![
+                FieldPresence::Implicit,
+                FieldPresence::Explicit,
+                FieldPresence::Implicit
+            ]
+        );
+        assert_eq!(
+            presence(&proto, &proto.messages[1]),
+            vec![FieldPresence::LegacyRequired]
+        );
+
+        let input = Span::new(CompleteStr(
+            "syntax = \"proto3\";\nmessage User { optional string a = 1; string b = 2; }",
+        ));
+        let (_, proto) = parse(input).unwrap();
+        assert_eq!(
+            presence(&proto, &proto.messages[0]),
+            vec![FieldPresence::Explicit, FieldPresence::Implicit]
+        );
+
+        // Message fields and oneof members track presence without a label
+        let input = Span::new(CompleteStr(
+            r#"syntax = "proto3";
+message User {
+  message Profile {}
+  enum Role { ROLE_UNKNOWN = 0; }
+  google.protobuf.Timestamp created_at = 1;
+  google.protobuf.NullValue nothing = 2;
+  Profile profile = 3;
+  Role role = 4;
+  imported.Address address = 5;
+  oneof contact { string email = 6; }
+}"#,
+        ));
+        let (_, proto) = parse(input).unwrap();
+        let message = &proto.messages[0];
+        let presence: Vec<_> = message
+            .all_fields()
+            .map(|field| proto.field_presence(message, field))
+            .collect();
+        assert_eq!(
+            presence,
+            vec![
+                FieldPresence::Explicit,
+                FieldPresence::Implicit,
+                FieldPresence::Explicit,
+                FieldPresence::Implicit,
+                FieldPresence::Implicit,
+                FieldPresence::Explicit,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_service_with_option_block() {
+        let input = Span::new(CompleteStr(
+            r#"syntax = "proto3";
+service Users {
+  /* returns a user } */
+  rpc GetUser (GetUserRequest) returns (User) {
+    option (google.api.http) = { get: "/v1/users/{id}" };
+  }
+}
+message User {
+  int64 id = 1;
+}
+enum Role {
+  ADMIN = 0;
+}
+"#,
+        ));
+        let (rest, proto) = parse(input).unwrap();
+        assert!(rest.fragment.is_empty());
+        assert_eq!(proto.messages.len(), 1);
+        assert_eq!(proto.messages[0].name.as_ref().unwrap().as_ref(), "User");
+        assert_eq!(proto.enums.len(), 1);
+    }
+
+    #[test]
+    fn test_proto_value() {
+        let value = |text| parse_proto_value(Span::new(CompleteStr(text)));
+        assert_eq!(value("17"), Some(ProtoValue::Int(17)));
+        assert_eq!(value("-0x1F"), Some(ProtoValue::Int(-31)));
+        assert_eq!(value("017"), Some(ProtoValue::Int(15)));
+        assert_eq!(value("1.5e-3"), Some(ProtoValue::Float(0.0015)));
+        assert_eq!(value("-inf"), Some(ProtoValue::Float(f64::NEG_INFINITY)));
+        assert_eq!(value("true"), Some(ProtoValue::Bool(true)));
+        assert_eq!(
+            value(r#""a\tb\x41\101\"" 'c'"#),
+            Some(ProtoValue::Str("a\tbAA\"c".to_string()))
+        );
+        assert_eq!(
+            value(r#""\u00e9\U0001F600\u12""#),
+            Some(ProtoValue::Str("\u{e9}\u{1F600}\\u12".to_string()))
+        );
+        assert_eq!(
+            value("-9223372036854775808"),
+            Some(ProtoValue::Int(i64::MIN))
+        );
+        assert_eq!(
+            value("9223372036854775807"),
+            Some(ProtoValue::Int(i64::MAX))
+        );
+        assert_eq!(
+            value("18446744073709551615"),
+            Some(ProtoValue::UInt(u64::MAX))
+        );
+        assert_eq!(value("-9223372036854775809"), None);
+        assert_eq!(value("0x10000000000000000"), None);
+        match value("SPEED") {
+            Some(ProtoValue::Ident(word)) => assert_eq!(word.as_ref(), "SPEED"),
+            other => panic!("expected an identifier, got {:?}", other),
+        }
+        assert_eq!(value("{ a: 1 }"), None);
+        assert_eq!(value("1 2"), None);
+
+        let (_, msg) = message(Span::new(CompleteStr(
+            r#"message Retry {
+  optional int32 attempts = 1 [default = 17];
+  optional string name = 2 [default = "a\nb", (sql).type = "text"];
+}"#,
+        )))
+        .unwrap();
+        assert_eq!(msg.fields[0].default_value(), Some(ProtoValue::Int(17)));
+        assert_eq!(
+            msg.fields[1].default_value(),
+            Some(ProtoValue::Str("a\nb".to_string()))
+        );
+        assert_eq!(
+            msg.fields[1].option("(sql).type").unwrap().proto_value(),
+            Some(ProtoValue::Str("text".to_string()))
+        );
+
+        let (_, proto) = parse(Span::new(CompleteStr("option optimize_for = SPEED;"))).unwrap();
+        match proto.options[0].proto_value() {
+            Some(ProtoValue::Ident(word)) => assert_eq!(word.as_ref(), "SPEED"),
+            other => panic!("expected an identifier, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_service_rpcs() {
+        let input = Span::new(CompleteStr(
+            r#"syntax = "proto3";
+service Events {
+  option deprecated = true;
+  // Lists events
+  rpc List (ListRequest) returns (ListResponse);
+  rpc Watch(WatchRequest) returns (stream Event) {}
+  rpc Upload ( stream Event ) returns ( UploadResponse ) {
+    option (google.api.http) = { post: "/v1/events" body: "*" };
+    option idempotency_level = IDEMPOTENT;
+  }
+  rpc Chat (stream .chat.Message) returns (stream .chat.Message);
+}
+message Event {}"#,
+        ));
+        let (rest, proto) = parse(input).unwrap();
+        assert!(rest.fragment.is_empty());
+        assert_eq!(proto.messages.len(), 1);
+        assert_eq!(proto.services.len(), 1);
+
+        let service = &proto.services[0];
+        assert_eq!(service.name.as_ref(), "Events");
+        assert_eq!(service.options.len(), 1);
+        let rpcs: Vec<_> = service
+            .rpcs
+            .iter()
+            .map(|rpc| {
+                (
+                    rpc.name.as_ref(),
+                    rpc.input_type.as_ref(),
+                    rpc.client_streaming,
+                    rpc.output_type.as_ref(),
+                    rpc.server_streaming,
+                )
+            })
+            .collect();
+        assert_eq!(
+            rpcs,
+            vec![
+                ("List", "ListRequest", false, "ListResponse", false),
+                ("Watch", "WatchRequest", false, "Event", true),
+                ("Upload", "Event", true, "UploadResponse", false),
+                ("Chat", ".chat.Message", true, ".chat.Message", true),
+            ]
+        );
+        assert!(service.rpcs[1].options.is_empty());
+        let options = &service.rpcs[2].options;
+        assert_eq!(options.len(), 2);
+        assert!(
+            matches!(options[0].name(), DeclOptionName::Custom(name) if name.as_ref() == "google.api.http")
+        );
+        assert_eq!(
+            options[0].aggregate_fields().unwrap(),
+            vec![
+                ("post".to_string(), "/v1/events".to_string()),
+                ("body".to_string(), "*".to_string()),
+            ]
+        );
+        assert_eq!(options[1].value().fragment.0, "IDEMPOTENT");
+    }
+
+    #[test]
+    fn test_aggregate_option() {
+        let input = Span::new(CompleteStr(
+            r#"option (google.api.http) = {
+  get: "/v1/users/{id}";
+  body: "*"
+  additional_bindings { post: "/v1/users:batch" }
+};
+message User {}"#,
+        ));
+        let (rest, proto) = parse(input).unwrap();
+        assert!(rest.fragment.is_empty());
+        assert_eq!(proto.messages.len(), 1);
+        assert_eq!(
+            proto.options[0].aggregate_fields().unwrap(),
+            vec![
+                ("get".to_string(), "/v1/users/{id}".to_string()),
+                ("body".to_string(), "*".to_string()),
+                (
+                    "additional_bindings".to_string(),
+                    r#"{ post: "/v1/users:batch" }"#.to_string()
+                ),
+            ]
+        );
+        assert_eq!(proto.options[0].string_value(), None);
+    }
+
+    #[test]
+    fn test_message_and_enum_comments() {
+        let input = Span::new(CompleteStr(
+            r#"syntax = "proto3";
+// A user
+/* of the shop */
+message User {
+  // Nested
+  message Address {}
+}
+// Roles
+enum Role {
+  // No access
+  NONE = 0;
+  ADMIN = 1; // everything
+}"#,
+        ));
+        let (rest, proto) = parse(input).unwrap();
+        assert!(rest.fragment.is_empty());
+        let user = &proto.messages[0];
+        assert_eq!(
+            user.comments.text(),
+            Some("A user\nof the shop".to_string())
+        );
+        assert_eq!(user.messages[0].comments.text(), Some("Nested".to_string()));
+        let role = &proto.enums[0];
+        assert_eq!(role.comments.text(), Some("Roles".to_string()));
+        assert_eq!(
+            role.values[0].comments.text(),
+            Some("No access".to_string())
+        );
+        assert_eq!(
+            role.values[1].comments.text(),
+            Some("everything".to_string())
+        );
+        assert_eq!(role.values[1].number.value(), 1);
+    }
+
+    #[test]
+    fn test_deprecated_options() {
+        let input = Span::new(CompleteStr(
+            r#"syntax = "proto3";
+option deprecated = true;
+message Old {
+  option deprecated = true;
+  int64 id = 1 [deprecated = true];
+}
+message New {
+  int64 id = 1;
+}
+enum Role {
+  NONE = 0;
+  GUEST = 1 [deprecated = true];
+}"#,
+        ));
+        let (rest, proto) = parse(input).unwrap();
+        assert!(rest.fragment.is_empty());
+        assert!(proto.is_deprecated());
+        assert!(proto.messages[0].is_deprecated());
+        assert!(proto.messages[0].fields[0].deprecated);
+        assert!(!proto.messages[1].is_deprecated());
+        let deprecated: Vec<_> = proto.enums[0]
+            .values
+            .iter()
+            .map(EnumValue::is_deprecated)
+            .collect();
+        assert_eq!(deprecated, vec![false, true]);
+    }
+
+    #[test]
+    fn test_scalar_types() {
+        assert_eq!(SCALAR_TYPES.len(), 15);
+        for (keyword, typ) in SCALAR_TYPES {
+            let input = format!("{} value = 1;", keyword);
+            let (_, field) = message_field(Span::new(CompleteStr(&input))).unwrap();
+            assert_eq!(&field.typ, typ);
+        }
+        assert_eq!(
+            SCALAR_TYPES
+                .iter()
+                .find(|(keyword, _)| *keyword == "sfixed64")
+                .map(|(_, typ)| typ),
+            Some(&FieldType::Sfixed64)
+        );
+
+        let (_, field) = message_field(Span::new(CompleteStr("Int32 value = 1;"))).unwrap();
+        assert!(matches!(field.typ, FieldType::MessageOrEnum(_)));
+    }
+
+    #[test]
+    fn test_field_lookup() {
+        let input = Span::new(CompleteStr(
+            r#"message User {
+                int64 id = 1;
+                oneof contact {
+                    string email = 2;
+                }
+            }"#,
+        ));
+        let (_, msg) = message(input).unwrap();
+        assert_eq!(msg.field_by_name("id").unwrap().number.value(), 1);
+        assert_eq!(msg.field_by_name("email").unwrap().number.value(), 2);
+        assert!(msg.field_by_name("phone").is_none());
+        assert_eq!(msg.field_by_number(1).unwrap().name.as_ref(), "id");
+        assert_eq!(msg.field_by_number(2).unwrap().name.as_ref(), "email");
+        assert!(msg.field_by_number(3).is_none());
+    }
+
+    #[test]
+    fn test_unused_numbers() {
+        let input = Span::new(CompleteStr(
+            r#"message Gaps {
+                int64 id = 1;
+                string name = 2;
+                string email = 4;
+                oneof contact {
+                    string phone = 9;
+                }
+                reserved 6, 7, 20 to 30;
+            }
+            message Empty {}
+            message Extended {
+                int64 id = 1;
+                string note = 20001;
+                reserved 2 to 18999, 20000;
+            }"#,
+        ));
+        let (_, proto) = parse(input).unwrap();
+        assert_eq!(
+            proto.messages[0].unused_numbers(),
+            vec![3..=3, 5..=5, 8..=8]
+        );
+        assert_eq!(proto.messages[1].unused_numbers(), vec![]);
+        // The implementation reserved range is not a gap
+        assert_eq!(proto.messages[2].unused_numbers(), vec![]);
+    }
+
+    #[test]
+    fn test_rule_keyword_collisions() {
+        let field = |input: &str| {
+            let (_, field) = message_field(Span::new(CompleteStr(input))).unwrap();
+            let typ = match &field.typ {
+                FieldType::MessageOrEnum(name) => name.as_ref().to_string(),
+                typ => format!("{:?}", typ),
+            };
+            (field.rule.variant, typ, field.name.as_ref().to_string())
+        };
+        let optional = RuleVariant::Optional;
+
+        assert_eq!(
+            field("repeated repeated items = 1;"),
+            (
+                RuleVariant::Repeated,
+                "repeated".to_string(),
+                "items".to_string()
+            )
+        );
+        assert_eq!(
+            field("required int32 required = 1;"),
+            (
+                RuleVariant::Required,
+                "Int32".to_string(),
+                "required".to_string()
+            )
+        );
+        // Without a type after it, the keyword is the type
+        assert_eq!(
+            field("optional x = 1;"),
+            (optional, "optional".to_string(), "x".to_string())
+        );
+        assert_eq!(
+            field("optional optional = 1;"),
+            (optional, "optional".to_string(), "optional".to_string())
+        );
+        assert_eq!(
+            field("optionalFlags flags = 1;"),
+            (optional, "optionalFlags".to_string(), "flags".to_string())
+        );
+        assert_eq!(
+            field("repeated optional.Item items = 1;"),
+            (
+                RuleVariant::Repeated,
+                "optional.Item".to_string(),
+                "items".to_string()
+            )
+        );
+
+        let (_, field) = message_field(Span::new(CompleteStr("optional x = 1;"))).unwrap();
+        assert_eq!(field.rule.position, None);
+    }
+
+    #[test]
+    fn test_keyword_field_names() {
+        let input = Span::new(CompleteStr(
+            r#"syntax = "proto3";
+message Event {
+  string message = 1;
+  string enum = 2;
+  optional string service = 3;
+  message Details {
+    repeated string message = 1;
+  }
+  enum Kind {
+    KIND_UNSPECIFIED = 0;
+  }
+  int32 rpc = 4;
+}
+service Events {}"#,
+        ));
+        let (_, proto) = parse(input).unwrap();
+        let event = &proto.messages[0];
+        let fields: Vec<_> = event
+            .fields
+            .iter()
+            .map(|field| (field.name.as_ref(), field.number.value()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![("message", 1), ("enum", 2), ("service", 3), ("rpc", 4)]
+        );
+        assert_eq!(event.messages.len(), 1);
+        assert_eq!(event.messages[0].fields[0].name.as_ref(), "message");
+        assert_eq!(event.enums.len(), 1);
+    }
+
     // #[test]
     // fn test_import() {
     //     let msg = r#"syntax = "proto3";
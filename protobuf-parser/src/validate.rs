@@ -0,0 +1,723 @@
+//! Validation of a parsed `AbstractProto`
+//!
+//! The parser is lenient and accepts some files `protoc` would reject. These checks report such
+//! problems without failing the parse.
+
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+use super::*;
+
+/// How serious a `Diagnostic` is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The file is valid, but likely not what was intended
+    Warning,
+    /// The file would be rejected by `protoc`
+    Error,
+}
+
+/// Names of the checks `validate` runs, each reported as the `rule` of a `Diagnostic`
+pub const LINT_RULES: &[&str] = &[
+    MISSING_RULE_PROTO2,
+    PROTO3_REQUIRED,
+    DUPLICATE_TAG,
+    RESERVED_VIOLATION,
+    MAP_KEY_TYPE,
+    FIELD_NUMBER_RANGE,
+    ENUM_ZERO_VALUE,
+    PACKED_OPTION,
+    ENUM_DEFAULT,
+    ONEOF_MAP,
+    DUPLICATE_ENUM_VALUE,
+];
+
+const MISSING_RULE_PROTO2: &str = "missing-rule-proto2";
+const PROTO3_REQUIRED: &str = "proto3-required";
+const DUPLICATE_TAG: &str = "duplicate-tag";
+const RESERVED_VIOLATION: &str = "reserved-violation";
+const MAP_KEY_TYPE: &str = "map-key-type";
+const FIELD_NUMBER_RANGE: &str = "field-number-range";
+const ENUM_ZERO_VALUE: &str = "enum-zero-value";
+const PACKED_OPTION: &str = "packed-option";
+const ENUM_DEFAULT: &str = "enum-default";
+const ONEOF_MAP: &str = "oneof-map";
+const DUPLICATE_ENUM_VALUE: &str = "duplicate-enum-value";
+
+/// Field numbers reserved for the protobuf implementation
+pub(crate) const IMPLEMENTATION_RESERVED: RangeInclusive<i32> = 19000..=19999;
+
+/// A problem found while validating a proto file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic<'a> {
+    /// Name of the check which found the problem, one of `LINT_RULES`
+    pub rule: &'static str,
+    /// How serious the problem is
+    pub severity: Severity,
+    /// Where the problem is in the proto file
+    pub position: Span<'a>,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl<'a> Diagnostic<'a> {
+    fn warning(rule: &'static str, position: Span<'a>, message: String) -> Self {
+        Diagnostic {
+            rule,
+            severity: Severity::Warning,
+            position,
+            message,
+        }
+    }
+
+    fn error(rule: &'static str, position: Span<'a>, message: String) -> Self {
+        Diagnostic {
+            rule,
+            severity: Severity::Error,
+            position,
+            message,
+        }
+    }
+
+    /// Line of the problem in the proto file (1-based)
+    pub fn line(&self) -> u32 {
+        self.position.line
+    }
+}
+
+impl<'a> fmt::Display for Diagnostic<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line(), self.message)
+    }
+}
+
+pub(crate) fn validate<'a>(proto: &AbstractProto<'a>) -> Vec<Diagnostic<'a>> {
+    let mut diagnostics = Vec::new();
+    let package = proto
+        .package
+        .as_ref()
+        .map(|package| package.as_ref())
+        .unwrap_or("");
+    for message in &proto.messages {
+        validate_message(proto, package, message, &mut diagnostics);
+    }
+    for enumeration in &proto.enums {
+        validate_enum(proto, enumeration, &mut diagnostics);
+    }
+    for extension in &proto.extensions {
+        validate_field(proto, package, &extension.field, &mut diagnostics);
+    }
+    diagnostics
+}
+
+/// Validates a message declared inside `scope`, its package or fully qualified parent message.
+fn validate_message<'a>(
+    proto: &AbstractProto<'a>,
+    scope: &str,
+    message: &Message<'a>,
+    diagnostics: &mut Vec<Diagnostic<'a>>,
+) {
+    let scope = match &message.name {
+        Some(name) => qualify(scope, name.as_ref()),
+        None => scope.to_string(),
+    };
+    for field in &message.fields {
+        validate_field(proto, &scope, field, diagnostics);
+    }
+
+    for oneof in &message.oneofs {
+        for field in &oneof.fields {
+            if matches!(field.typ, FieldType::Map(_)) {
+                diagnostics.push(Diagnostic::error(
+                    ONEOF_MAP,
+                    field.name.word,
+                    format!(
+                        "map field '{}' is declared in oneof '{}', but map fields can't be in a oneof",
+                        field.name.as_ref(),
+                        oneof.name.as_ref()
+                    ),
+                ));
+            }
+        }
+    }
+
+    let mut numbers = HashMap::new();
+    for field in message.all_fields() {
+        let number = field.number.value;
+        if let Some(first) = numbers.insert(number, field) {
+            diagnostics.push(Diagnostic::error(
+                DUPLICATE_TAG,
+                field.number.position,
+                format!(
+                    "field '{}' uses number {}, which is already used by field '{}'",
+                    field.name.as_ref(),
+                    number,
+                    first.name.as_ref()
+                ),
+            ));
+        }
+        if number == 0 {
+            diagnostics.push(Diagnostic::warning(
+                FIELD_NUMBER_RANGE,
+                field.number.position,
+                format!(
+                    "field '{}' uses number 0, but field numbers start at 1",
+                    field.name.as_ref()
+                ),
+            ));
+        } else if IMPLEMENTATION_RESERVED.contains(&number) {
+            diagnostics.push(Diagnostic::error(
+                FIELD_NUMBER_RANGE,
+                field.number.position,
+                format!(
+                    "field '{}' uses number {}, but {} to {} are reserved for the protobuf implementation",
+                    field.name.as_ref(),
+                    number,
+                    IMPLEMENTATION_RESERVED.start(),
+                    IMPLEMENTATION_RESERVED.end()
+                ),
+            ));
+        }
+        if message
+            .reserved_nums
+            .iter()
+            .any(|range| range.contains(&number))
+        {
+            diagnostics.push(Diagnostic::error(
+                RESERVED_VIOLATION,
+                field.number.position,
+                format!(
+                    "field '{}' uses reserved number {}",
+                    field.name.as_ref(),
+                    number
+                ),
+            ));
+        }
+        if message
+            .reserved_names
+            .iter()
+            .any(|name| name.as_ref() == field.name.as_ref())
+        {
+            diagnostics.push(Diagnostic::error(
+                RESERVED_VIOLATION,
+                field.name.word,
+                format!("field '{}' uses a reserved name", field.name.as_ref()),
+            ));
+        }
+    }
+
+    for extension in &message.extensions {
+        validate_field(proto, &scope, &extension.field, diagnostics);
+    }
+    for enumeration in &message.enums {
+        validate_enum(proto, enumeration, diagnostics);
+    }
+    // Generated map entries have no rules in the file
+    for nested in message
+        .messages
+        .iter()
+        .filter(|nested| !nested.is_map_entry())
+    {
+        validate_message(proto, &scope, nested, diagnostics);
+    }
+}
+
+fn validate_enum<'a>(
+    proto: &AbstractProto<'a>,
+    enumeration: &Enumeration<'a>,
+    diagnostics: &mut Vec<Diagnostic<'a>>,
+) {
+    // Unlike numbers, names can't be shared even with `allow_alias`
+    let mut names = HashMap::new();
+    for value in &enumeration.values {
+        if let Some(first) = names.insert(value.name.as_ref(), value) {
+            diagnostics.push(Diagnostic::error(
+                DUPLICATE_ENUM_VALUE,
+                value.name.word,
+                format!(
+                    "enum '{}' declares value {} again, which is already declared on line {}",
+                    enumeration.name.as_ref(),
+                    value.name.as_ref(),
+                    first.name.word.line
+                ),
+            ));
+        }
+    }
+
+    // proto3 uses the first value as the default, so it must be 0
+    if !matches!(proto.syntax, Syntax::Proto3) {
+        return;
+    }
+    let message = match enumeration.values.first() {
+        Some(first) if first.number.value == 0 => return,
+        Some(first) => format!(
+            "enum '{}' starts with {} = {}, but the first value of a proto3 enum must be 0",
+            enumeration.name.as_ref(),
+            first.name.as_ref(),
+            first.number.value
+        ),
+        None => format!(
+            "enum '{}' has no values, but proto3 enums must start with a value of 0",
+            enumeration.name.as_ref()
+        ),
+    };
+    diagnostics.push(Diagnostic::error(
+        ENUM_ZERO_VALUE,
+        enumeration.name.word,
+        message,
+    ));
+}
+
+/// Validates a field of a message or extension declared inside `scope`.
+fn validate_field<'a>(
+    proto: &AbstractProto<'a>,
+    scope: &str,
+    field: &Field<'a>,
+    diagnostics: &mut Vec<Diagnostic<'a>>,
+) {
+    // Map fields can't have a rule
+    let needs_rule =
+        matches!(proto.syntax, Syntax::Proto2) && !matches!(field.typ, FieldType::Map(_));
+    if needs_rule && field.rule.position.is_none() {
+        diagnostics.push(Diagnostic::warning(
+            MISSING_RULE_PROTO2,
+            field.name.word,
+            format!(
+                "field '{}' has no rule, but proto2 requires one of optional, required or repeated",
+                field.name.as_ref()
+            ),
+        ));
+    }
+
+    if let (Syntax::Proto3, RuleVariant::Required, Some(position)) =
+        (proto.syntax, field.rule.variant, field.rule.position)
+    {
+        diagnostics.push(Diagnostic::error(
+            PROTO3_REQUIRED,
+            position,
+            format!(
+                "field '{}' is required, but proto3 does not support required fields",
+                field.name.as_ref()
+            ),
+        ));
+    }
+
+    if let Some(packed) = field.option("packed") {
+        let problem = if field.rule.variant != RuleVariant::Repeated {
+            Some("it is not repeated")
+        } else if !is_packable(proto, &field.typ) {
+            Some("only numeric, bool and enum types can be packed")
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            diagnostics.push(Diagnostic::error(
+                PACKED_OPTION,
+                packed.key().word,
+                format!(
+                    "field '{}' has the packed option, but {}",
+                    field.name.as_ref(),
+                    problem
+                ),
+            ));
+        }
+    }
+
+    if let (FieldType::MessageOrEnum(name), Some(default)) = (&field.typ, &field.default) {
+        let types = declared_types(proto);
+        let enumeration = resolve_type(&types, scope, name.as_ref())
+            .copied()
+            .flatten();
+        if enumeration.is_some_and(|enumeration| {
+            !enumeration
+                .values
+                .iter()
+                .any(|value| value.name.as_ref() == default.as_ref())
+        }) {
+            diagnostics.push(Diagnostic::error(
+                ENUM_DEFAULT,
+                default.word,
+                format!(
+                    "field '{}' has default {}, which is not a value of enum '{}'",
+                    field.name.as_ref(),
+                    default.as_ref(),
+                    name.as_ref()
+                ),
+            ));
+        }
+    }
+
+    if let FieldType::Map(map) = &field.typ {
+        if !map.has_valid_key() {
+            diagnostics.push(Diagnostic::error(
+                MAP_KEY_TYPE,
+                map.position,
+                format!(
+                    "map field '{}' has key type {:?}, but map keys must be an integral or string type",
+                    field.name.as_ref(),
+                    map.key
+                ),
+            ));
+        }
+    }
+
+    if let FieldType::Group(fields) = &field.typ {
+        for field in fields {
+            validate_field(proto, scope, field, diagnostics);
+        }
+    }
+}
+
+/// Checks if repeated values of a type can use the packed encoding. Named types are assumed to
+/// be enums unless a message with that name is declared in the file.
+fn is_packable(proto: &AbstractProto, typ: &FieldType) -> bool {
+    match typ {
+        FieldType::String | FieldType::Bytes | FieldType::Map(_) | FieldType::Group(_) => false,
+        FieldType::MessageOrEnum(name) => {
+            let name = name.as_ref().rsplit('.').next().unwrap_or_default();
+            !has_message(&proto.messages, name)
+        }
+        _ => true,
+    }
+}
+
+/// Messages and enums declared in the file, by fully qualified name, with the enums' values.
+fn declared_types<'p, 'a>(
+    proto: &'p AbstractProto<'a>,
+) -> HashMap<String, Option<&'p Enumeration<'a>>> {
+    let package = proto
+        .package
+        .as_ref()
+        .map(|package| package.as_ref())
+        .unwrap_or("");
+    let mut types = HashMap::new();
+    for enumeration in &proto.enums {
+        types.insert(
+            qualify(package, enumeration.name.as_ref()),
+            Some(enumeration),
+        );
+    }
+    for (name, message) in proto.all_messages() {
+        let name = qualify(package, &name);
+        for enumeration in &message.enums {
+            types.insert(qualify(&name, enumeration.name.as_ref()), Some(enumeration));
+        }
+        types.insert(name, None);
+    }
+    types
+}
+
+/// Resolves a type name referenced from `scope` like protoc, searching from the innermost
+/// scope outwards. A nested path such as `Outer.Inner` is only looked up in the innermost scope
+/// declaring `Outer`. Types from imported files are unknown, so they resolve to `None`.
+fn resolve_type<'t, T>(types: &'t HashMap<String, T>, scope: &str, name: &str) -> Option<&'t T> {
+    if let Some(name) = name.strip_prefix('.') {
+        return types.get(name);
+    }
+
+    let first = name.split('.').next().unwrap_or(name);
+    let mut scope = scope;
+    loop {
+        if let Some(typ) = types.get(&qualify(scope, name)) {
+            return Some(typ);
+        }
+        if types.contains_key(&qualify(scope, first)) || scope.is_empty() {
+            return None;
+        }
+        scope = scope.rfind('.').map(|i| &scope[..i]).unwrap_or("");
+    }
+}
+
+/// Checks if a type referenced by a field of `message` is a message declared in the file
+pub(crate) fn is_declared_message(proto: &AbstractProto, message: &Message, name: &str) -> bool {
+    let package = proto
+        .package
+        .as_ref()
+        .map(|package| package.as_ref())
+        .unwrap_or("");
+    let scope = match proto
+        .all_messages()
+        .find(|(_, declared)| std::ptr::eq(*declared, message))
+    {
+        Some((name, _)) => qualify(package, &name),
+        None => return false,
+    };
+    matches!(
+        resolve_type(&declared_types(proto), &scope, name),
+        Some(None)
+    )
+}
+
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+fn has_message(messages: &[Message], name: &str) -> bool {
+    messages.iter().any(|message| {
+        message.name.as_ref().map(|n| n.as_ref()) == Some(name)
+            || has_message(&message.messages, name)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_proto2_missing_rule() {
+        let proto = r#"syntax = "proto2";
+message Person {
+  optional string name = 1;
+  int32 id = 2;
+  map<string, string> labels = 3;
+  oneof contact {
+    string email = 4;
+  }
+}"#;
+        let (_, proto) = ::parse(proto).unwrap();
+        let diagnostics = proto.validate();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].line(), 4);
+        assert!(diagnostics[0].message.contains("'id'"));
+    }
+
+    #[test]
+    fn test_proto3_missing_rule() {
+        let proto = r#"syntax = "proto3";
+message Person {
+  int32 id = 1;
+}"#;
+        let (_, proto) = ::parse(proto).unwrap();
+        assert!(proto.validate().is_empty());
+    }
+
+    #[test]
+    fn test_map_key_type() {
+        let proto = r#"syntax = "proto3";
+message Prices {
+  map<string, int32> by_name = 1;
+  map<double, string> by_amount = 2;
+}"#;
+        let (_, proto) = ::parse(proto).unwrap();
+        let diagnostics = proto.validate();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].line(), 4);
+        assert!(diagnostics[0].message.contains("'by_amount'"));
+    }
+
+    #[test]
+    fn test_oneof_map() {
+        let proto = r#"syntax = "proto3";
+message Prices {
+  map<string, int32> by_name = 1;
+  oneof source {
+    string url = 2;
+    map<string, int32> by_id = 3;
+  }
+}"#;
+        let (_, proto) = ::parse(proto).unwrap();
+        let diagnostics = proto.validate();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].rule, ONEOF_MAP);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].line(), 6);
+        assert_eq!(diagnostics[0].position.get_column(), 24);
+        assert_eq!(
+            diagnostics[0].message,
+            "map field 'by_id' is declared in oneof 'source', but map fields can't be in a oneof"
+        );
+    }
+
+    #[test]
+    fn test_field_numbers() {
+        let proto = r#"syntax = "proto3";
+message Person {
+  reserved 5, 10 to 12;
+  reserved "email";
+  int32 id = 1;
+  string name = 1;
+  string email = 2;
+  oneof contact {
+    string phone = 11;
+  }
+  required string nickname = 3;
+}"#;
+        let (_, proto) = ::parse(proto).unwrap();
+        let rules: Vec<_> = proto
+            .validate()
+            .iter()
+            .map(|diagnostic| (diagnostic.rule, diagnostic.line()))
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                (PROTO3_REQUIRED, 11),
+                (DUPLICATE_TAG, 6),
+                (RESERVED_VIOLATION, 7),
+                (RESERVED_VIOLATION, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_field_number_range() {
+        let proto = r#"syntax = "proto3";
+message Person {
+  int32 id = 1;
+  string name = 19500;
+  string email = 0;
+  string phone = 20000;
+}"#;
+        let (_, proto) = ::parse(proto).unwrap();
+        let diagnostics = proto.validate();
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.rule, diagnostic.severity, diagnostic.line()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (FIELD_NUMBER_RANGE, Severity::Error, 4),
+                (FIELD_NUMBER_RANGE, Severity::Warning, 5),
+            ]
+        );
+        assert!(diagnostics[0].message.contains("'name'"));
+    }
+
+    #[test]
+    fn test_enum_zero_value() {
+        let proto = r#"syntax = "proto3";
+enum Status {
+  ACTIVE = 1;
+  UNKNOWN = 0;
+}
+enum Role {
+  ROLE_UNSPECIFIED = 0;
+  ADMIN = 1;
+}
+message User {
+  enum Kind {
+    PERSON = 1;
+  }
+}"#;
+        let (_, proto) = ::parse(proto).unwrap();
+        let found: Vec<_> = proto
+            .validate()
+            .iter()
+            .map(|diagnostic| (diagnostic.rule, diagnostic.line()))
+            .collect();
+        assert_eq!(found, vec![(ENUM_ZERO_VALUE, 11), (ENUM_ZERO_VALUE, 2)]);
+
+        let proto = r#"syntax = "proto2";
+enum Status {
+  ACTIVE = 1;
+}"#;
+        let (_, proto) = ::parse(proto).unwrap();
+        assert!(proto.validate().is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_enum_value() {
+        let proto = r#"syntax = "proto2";
+enum Status {
+  ACTIVE = 0;
+  ENABLED = 0;
+  ACTIVE = 1;
+}"#;
+        let (_, proto) = ::parse(proto).unwrap();
+        let diagnostics = proto.validate();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].rule, DUPLICATE_ENUM_VALUE);
+        assert_eq!(diagnostics[0].line(), 5);
+        assert_eq!(
+            diagnostics[0].message,
+            "enum 'Status' declares value ACTIVE again, which is already declared on line 3"
+        );
+    }
+
+    #[test]
+    fn test_packed_option() {
+        let proto = r#"syntax = "proto2";
+enum Status {
+  ACTIVE = 0;
+}
+message Address {}
+message Person {
+  repeated int32 ids = 1 [packed = true];
+  repeated Status statuses = 2 [packed = true];
+  repeated string names = 3 [packed = true];
+  optional int32 age = 4 [packed = true];
+  repeated Address addresses = 5 [packed = false];
+}"#;
+        let (_, proto) = ::parse(proto).unwrap();
+        let diagnostics = proto.validate();
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.rule, diagnostic.line()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![(PACKED_OPTION, 9), (PACKED_OPTION, 10), (PACKED_OPTION, 11)]
+        );
+        assert!(diagnostics[1].message.contains("not repeated"));
+    }
+
+    #[test]
+    fn test_enum_default() {
+        let proto = r#"syntax = "proto2";
+enum Status {
+  ACTIVE = 0;
+  DISABLED = 1;
+}
+message User {
+  enum Role {
+    MEMBER = 0;
+    ADMIN = 1;
+  }
+  optional Status status = 1 [default = DISABLED];
+  optional Role role = 2 [default = OWNER];
+  optional other.Kind kind = 3 [default = ANYTHING];
+  optional Team.Role team_role = 4 [default = MEMBER];
+}
+message Team {
+  enum Role {
+    OWNER = 0;
+  }
+  optional Role role = 1 [default = ADMIN];
+  optional User.Role member_role = 2 [default = ADMIN];
+}"#;
+        let (_, proto) = ::parse(proto).unwrap();
+        let diagnostics: Vec<_> = proto
+            .validate()
+            .into_iter()
+            .filter(|diagnostic| diagnostic.rule == ENUM_DEFAULT)
+            .map(|diagnostic| (diagnostic.line(), diagnostic.message))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (
+                    12,
+                    "field 'role' has default OWNER, which is not a value of enum 'Role'"
+                        .to_string()
+                ),
+                (
+                    14,
+                    "field 'team_role' has default MEMBER, which is not a value of enum 'Team.Role'"
+                        .to_string()
+                ),
+                (
+                    20,
+                    "field 'role' has default ADMIN, which is not a value of enum 'Role'"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+}
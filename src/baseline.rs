@@ -0,0 +1,134 @@
+//! Known mismatches loaded with `--baseline`, which are left out of the output and the counts so
+//! only new mismatches fail. `--update-baseline` writes the mismatches found back to the file.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    io::ErrorKind,
+    path::Path,
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+
+use crate::{log::*, report::strip_ansi, write_file_atomic};
+
+/// Mismatches accepted by the baseline file, and the ones found while verifying.
+///
+/// Mismatches are identified by the message or table they are about and their warning, such as
+/// `shop.Order: field 'total' has type 'Int64' which not match database type 'Integer'`.
+#[derive(Debug, Default)]
+pub struct Baseline {
+    known: HashSet<String>,
+    found: Mutex<BTreeSet<String>>,
+}
+
+impl Baseline {
+    /// Loads a baseline file, which is a JSON array of mismatches. A missing file is an empty
+    /// baseline, so `--update-baseline` can create it.
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let content = match tokio::fs::read_to_string(path).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Baseline::default()),
+            Err(err) => return Err(err).context("could not read baseline file"),
+        };
+        Baseline::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let known: Vec<String> =
+            serde_json::from_str(content).context("could not parse baseline file")?;
+        Ok(Baseline {
+            known: known.into_iter().collect(),
+            found: Default::default(),
+        })
+    }
+
+    /// Records a mismatch of `scope`, warning about it unless it's in the baseline. Returns the
+    /// number of mismatches to count, which is `0` for a known one.
+    pub fn check(&self, scope: &str, warning: String) -> usize {
+        let key = format!("{}: {}", scope, strip_ansi(&warning));
+        let known = self.known.contains(&key);
+        self.found.lock().unwrap().insert(key);
+        if known {
+            debug!("{} (in baseline)", warning);
+            0
+        } else {
+            warn!("{}", warning);
+            1
+        }
+    }
+
+    /// Mismatches found so far, sorted.
+    pub fn found(&self) -> Vec<String> {
+        self.found.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Writes the mismatches found as the new baseline, replacing the old one in one go.
+    pub async fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let content = serde_json::to_string_pretty(&self.found())?;
+        write_file_atomic(path.as_ref(), &(content + "\n"))
+            .await
+            .context("could not write baseline file")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use colorful::Colorful;
+
+    use super::*;
+
+    #[test]
+    fn test_baseline() {
+        let baseline =
+            Baseline::parse(r#"["shop.Order: field 'total' is marked as NULL in database"]"#)
+                .unwrap();
+
+        let known = format!("field 'total' is marked as {} in database", "NULL".bold());
+        assert_eq!(baseline.check("shop.Order", known.clone()), 0);
+        // The same warning about another message is new
+        assert_eq!(baseline.check("shop.Invoice", known), 1);
+        assert_eq!(
+            baseline.check(
+                "shop.Order",
+                "missing field in database table: note".to_string()
+            ),
+            1
+        );
+
+        assert_eq!(
+            baseline.found(),
+            vec![
+                "shop.Invoice: field 'total' is marked as NULL in database",
+                "shop.Order: field 'total' is marked as NULL in database",
+                "shop.Order: missing field in database table: note",
+            ]
+        );
+        assert!(Baseline::parse("{}").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_baseline() {
+        let path =
+            std::env::temp_dir().join(format!("protosql-baseline-{}.json", std::process::id()));
+        let baseline = Baseline::default();
+        baseline.check(
+            "shop.Order",
+            "missing field in database table: note".to_string(),
+        );
+        baseline.write(&path).await.unwrap();
+
+        let written = Baseline::load(&path).await.unwrap();
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        assert!(!Path::new(&tmp_path).exists());
+        tokio::fs::remove_file(&path).await.unwrap();
+        assert_eq!(
+            written.check(
+                "shop.Order",
+                "missing field in database table: note".to_string()
+            ),
+            0
+        );
+    }
+}
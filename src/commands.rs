@@ -1,9 +1,14 @@
-use clap::{AppSettings, Clap};
+use std::env;
+
+use anyhow::{bail, Context, Result};
+use clap::{AppSettings, ArgEnum, Clap};
+use protobuf_parser::Syntax;
 
 /// Validate protobuf messages with postgres tables.
 /// If --dir is specified, each proto file will be read in the directory,
 /// with the assumption that there is a proto message with the same name as the file name (as CameCase).
 /// The cli will then check for a table with that same message name (as snake_case).
+/// If the file has no such message, the first message with a table of its name is used instead.
 #[derive(Clap, Debug)]
 #[clap(
     name = "protosql",
@@ -11,10 +16,19 @@ use clap::{AppSettings, Clap};
     author = "Ari Seyhun <ariseyhun@live.com.au>"
 )]
 #[clap(setting = AppSettings::ColoredHelp)]
+#[clap(setting = AppSettings::SubcommandsNegateReqs)]
 pub struct Protosql {
-    /// Postgres database URI
-    #[clap(short, long)]
-    pub uri: String,
+    /// Postgres database URI. `${VAR}` references are expanded from the environment
+    #[clap(short, long, required_unless_present_any = &["uri-env", "schema-sql"])]
+    pub uri: Option<String>,
+
+    /// Environment variable to read the database URI from, such as DATABASE_URL
+    #[clap(long, conflicts_with = "uri")]
+    pub uri_env: Option<String>,
+
+    /// Config file with per-table overrides, such as a different database URI
+    #[clap(short, long, global = true)]
+    pub config: Option<String>,
 
     /// Postgres schema. Uses proto's package field if omitted, or 'public' if no package was found in the proto file
     #[clap(short, long)]
@@ -25,17 +39,189 @@ pub struct Protosql {
     pub table: Option<String>,
 
     /// Directory of proto files
-    #[clap(short, long)]
+    #[clap(short, long, global = true)]
     pub dir: Option<String>,
 
-    /// Proto file
-    #[clap(short, long)]
+    /// Proto file, or an http(s) URL to fetch it from
+    #[clap(short, long, global = true)]
     pub file: Option<String>,
 
+    /// Directory of proto files making up the package, so types from sibling files resolve
+    /// without being imported
+    #[clap(long)]
+    pub package_dir: Option<String>,
+
+    /// Directory to search for imported proto files, can be given multiple times
+    #[clap(short = 'I', long, number_of_values = 1)]
+    pub include_path: Vec<String>,
+
+    /// Syntax to validate the proto files as, whatever they declare. Files without a `syntax`
+    /// declaration are otherwise proto2
+    #[clap(long, arg_enum)]
+    pub assume_syntax: Option<AssumedSyntax>,
+
+    /// Comma separated bytes fields which store UUIDs, as `field` or `Message.field`.
+    /// These are checked against `uuid` columns instead of `bytea`
+    #[clap(long, use_delimiter = true)]
+    pub bytes_uuid_fields: Vec<String>,
+
+    /// Comma separated 32-bit integer fields with small values, as `field` or `Message.field`.
+    /// These can also be stored in `smallint` columns
+    #[clap(long, use_delimiter = true)]
+    pub smallint_fields: Vec<String>,
+
+    /// `pg_dump --schema-only` file to read the tables from, instead of connecting to a database
+    #[clap(
+        long,
+        conflicts_with_all = &[
+            "uri",
+            "uri-env",
+            "columns-query",
+            "explain-discovery",
+            "check-collation",
+            "check-json-shape",
+            "check-pk",
+            "check-indexes",
+            "check-relations",
+            "consistent-snapshot",
+        ]
+    )]
+    pub schema_sql: Option<String>,
+
+    /// Query returning (name, type, nullable) rows to use as the table columns instead of
+    /// discovering them. `$1` and `$2` are bound to the schema and table name
+    #[clap(long)]
+    pub columns_query: Option<String>,
+
+    /// Don't report table columns which have no field in the proto message
+    #[clap(long)]
+    pub ignore_extra_columns: bool,
+
+    /// Suggest the closest column for each field without one, and the closest field for each
+    /// column without one, to spot renames. Doesn't change which tables pass
+    #[clap(long)]
+    pub suggest_matches: bool,
+
+    /// Fail unless the fields and the table columns match one to one, reporting the fields
+    /// without a column and the columns without a field
+    #[clap(long, conflicts_with = "ignore-extra-columns")]
+    pub exact: bool,
+
+    /// Comma separated columns to verify, leaving out every other field and column
+    #[clap(long, use_delimiter = true)]
+    pub columns: Vec<String>,
+
+    /// Comma separated int64 fields which store Unix timestamps, as `field`, `Message.field` or
+    /// a pattern such as `*_at`. These can be stored as `bigint` or `timestamptz`
+    #[clap(long, use_delimiter = true)]
+    pub epoch_fields: Vec<String>,
+
+    /// Column type --epoch-fields are expected to use, warning about columns using the other one
+    #[clap(long, arg_enum)]
+    pub epoch_column_type: Option<EpochColumnType>,
+
+    /// Comma separated fields which are relationships stored in another table, such as a
+    /// junction table, so they have no column. Uses the same patterns as --epoch-fields
+    #[clap(long, use_delimiter = true)]
+    pub relation_fields: Vec<String>,
+
+    /// Check that fields with a `[default = ...]` have a column with the same default. Such
+    /// columns may be NOT NULL even if the field is optional
+    #[clap(long)]
+    pub check_defaults: bool,
+
+    /// Report proto3 `optional` fields whose column is NOT NULL without a default as inserts
+    /// which fail when the field is unset, instead of a plain nullability mismatch
+    #[clap(long)]
+    pub strict_optional: bool,
+
+    /// Warn about fields using deprecated messages, and deprecated fields whose column has no
+    /// comment marking it deprecated
+    #[clap(long)]
+    pub warn_deprecated: bool,
+
+    /// Warn about field numbers below the highest one which are neither used nor reserved,
+    /// which may be fields removed without reserving their number, and about fields declared
+    /// after a field with a higher number
+    #[clap(long)]
+    pub warn_tag_gaps: bool,
+
+    /// Warn about scalar fields numbered 16 or higher, which take two bytes to encode, while
+    /// numbers 1 to 15 are free
+    #[clap(long)]
+    pub warn_tag_efficiency: bool,
+
+    /// Warn about primary key columns which have no field, and composite keys whose fields are
+    /// declared in a different order than the key
+    #[clap(long)]
+    pub check_pk: bool,
+
+    /// Warn about fields annotated with `[(db).indexed = true]` whose column isn't the first
+    /// column of an index
+    #[clap(long)]
+    pub check_indexes: bool,
+
+    /// With --check-indexes, also warn about indexed columns whose field has no annotation
+    #[clap(long, requires = "check-indexes")]
+    pub warn_unannotated_indexes: bool,
+
+    /// Verify repeated message fields as child tables named after the message, such as `order`
+    /// for `repeated Order orders`, with a foreign key referencing the table, instead of columns.
+    /// The child tables are only checked in a database, not with --columns-query or --schema-sql
+    #[clap(long)]
+    pub check_relations: bool,
+
+    /// Integer field holding the row id, warned about if its column is not serial, identity or
+    /// otherwise backed by a sequence, since clients would have to supply ids
+    #[clap(long, default_value = "id")]
+    pub id_field: String,
+
+    /// Run every discovery query of a database in one read-only repeatable read transaction, so
+    /// schema changes made while validating don't give inconsistent results. Losing the
+    /// connection fails the run instead of continuing in a new snapshot
+    #[clap(long)]
+    pub consistent_snapshot: bool,
+
+    /// Print the queries used to discover the table columns before running them
+    #[clap(long)]
+    pub explain_discovery: bool,
+
+    /// Check that string columns use a deterministic collation in a UTF8 database. Case
+    /// insensitive collations can make distinct strings compare equal
+    #[clap(long)]
+    pub check_collation: bool,
+
+    /// Collation string columns are expected to use with --check-collation, instead of any
+    /// deterministic collation
+    #[clap(long, requires = "check-collation")]
+    pub expected_collation: Option<String>,
+
+    /// Check sampled values of json and jsonb columns storing messages against the JSON Schema
+    /// of the message, reporting fields the message doesn't declare and values of the wrong type
+    #[clap(long)]
+    pub check_json_shape: bool,
+
+    /// Also verify nested messages, each against a table named after its path, such as
+    /// `user_address` for `User.Address`
+    #[clap(long)]
+    pub include_nested: bool,
+
+    /// With --dir, also report tables in the checked schemas which have no proto file
+    #[clap(long)]
+    pub check_orphan_tables: bool,
+
+    /// With --dir, how many proto files are verified at the same time
+    #[clap(short, long, default_value = "4")]
+    pub jobs: usize,
+
     /// Message name to check against database table
     #[clap(short, long)]
     pub message: Option<String>,
 
+    /// Write the report to a file, printing only a summary
+    #[clap(short, long)]
+    pub output: Option<String>,
+
     /// Print more information
     #[clap(short, long)]
     pub verbose: bool,
@@ -43,4 +229,145 @@ pub struct Protosql {
     /// Only print errors and warnings
     #[clap(short, long)]
     pub quiet: bool,
+
+    /// Stop printing warnings after this many, ending with how many were left out. The exit
+    /// code and summary still count every mismatch
+    #[clap(long)]
+    pub max_errors: Option<usize>,
+
+    /// JSON file of known mismatches, which are not reported so only new mismatches fail
+    #[clap(long)]
+    pub baseline: Option<String>,
+
+    /// Write the mismatches found to the --baseline file, accepting them
+    #[clap(long, requires = "baseline")]
+    pub update_baseline: bool,
+
+    /// Output format. With json, log lines are written to stderr so stdout is only JSON
+    #[clap(long, arg_enum, global = true, default_value = "text")]
+    pub format: Format,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    /// Only print a single summary line with the number of files passed and failed, and the
+    /// total number of mismatches
+    #[clap(long)]
+    pub summary_only: bool,
+
+    /// Only print the total number of mismatches, for monitoring schema drift
+    #[clap(long, conflicts_with = "summary-only")]
+    pub drift_metric: bool,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+/// Syntax given with --assume-syntax
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum AssumedSyntax {
+    Proto2,
+    Proto3,
+    Editions,
+}
+
+impl AssumedSyntax {
+    pub fn syntax(self) -> Syntax {
+        match self {
+            AssumedSyntax::Proto2 => Syntax::Proto2,
+            AssumedSyntax::Proto3 => Syntax::Proto3,
+            AssumedSyntax::Editions => Syntax::Editions,
+        }
+    }
+}
+
+/// Column type Unix timestamp fields are stored as
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum EpochColumnType {
+    Bigint,
+    Timestamptz,
+}
+
+#[derive(Clap, Debug)]
+pub enum Command {
+    /// Check proto files for problems, without connecting to a database
+    Lint(Lint),
+    /// Compare two versions of a directory of proto files for changes which break wire
+    /// compatibility
+    Compat(Compat),
+    /// List the messages in the proto files with their field counts, without connecting to a
+    /// database
+    List,
+    /// Print the comments of messages, fields, enums and enum values, for generating API
+    /// documentation, without connecting to a database
+    Docs,
+    /// Format proto files with consistent indentation and spacing
+    Fmt(Fmt),
+    /// List the HTTP routes of rpc methods with a `google.api.http` annotation, without
+    /// connecting to a database
+    Routes,
+    /// Print the messages and enums with an edge for each message or enum typed field
+    Graph(Graph),
+    /// Verify a message against columns given on the command line, without a database
+    VerifyAgainst(VerifyAgainst),
+}
+
+#[derive(Clap, Debug)]
+pub struct Lint {
+    /// Set the level of a lint rule, such as `duplicate-tag=off`. Levels are error, warn and off
+    #[clap(long = "rule", number_of_values = 1)]
+    pub rules: Vec<String>,
+}
+
+#[derive(Clap, Debug)]
+pub struct Fmt {
+    /// Write the formatted files back instead of printing them
+    #[clap(long)]
+    pub write: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct Graph {
+    /// Print a Graphviz DOT diagram instead of the edges
+    #[clap(long)]
+    pub dot: bool,
+}
+
+#[derive(Clap, Debug)]
+pub struct VerifyAgainst {
+    /// Message to verify
+    #[clap(short, long)]
+    pub message: String,
+
+    /// Comma separated columns as `name:type:null` or `name:type:notnull`, such as
+    /// `id:bigint:notnull,name:varchar:null`
+    #[clap(long)]
+    pub columns: String,
+}
+
+#[derive(Clap, Debug)]
+pub struct Compat {
+    /// Directory with the previous version of the proto files
+    #[clap(long)]
+    pub old: String,
+
+    /// Directory with the new version of the proto files, paired with the old ones by file name
+    #[clap(long)]
+    pub new: String,
+}
+
+impl Protosql {
+    /// The database URI from `--uri`, or the variable named by `--uri-env`.
+    pub fn database_uri(&self) -> Result<String> {
+        match (&self.uri, &self.uri_env) {
+            (Some(uri), _) => Ok(uri.clone()),
+            (None, Some(var)) => env::var(var).with_context(|| {
+                format!("environment variable {} given by --uri-env is not set", var)
+            }),
+            (None, None) => bail!("no --uri or --uri-env specified"),
+        }
+    }
 }
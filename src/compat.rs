@@ -0,0 +1,231 @@
+//! `protosql compat`, which compares two versions of a directory of proto files for changes
+//! which break wire compatibility.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use protobuf_parser::{parse_complete, AbstractProto, FieldType, Message, RuleVariant};
+use serde::Serialize;
+
+use crate::{
+    commands::{Compat, Format, Protosql},
+    log::*,
+    read_proto_dir, read_proto_source,
+};
+
+/// A change to a message which breaks reading data written with the old proto files.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BreakingChange {
+    pub file: String,
+    pub message: String,
+    pub description: String,
+}
+
+/// Compares the proto files in `--old` and `--new`, returning `false` if any change breaks wire
+/// compatibility.
+pub async fn compat(opts: &Protosql, compat: &Compat) -> Result<bool> {
+    let old_files = proto_dir_files(&compat.old).await?;
+    let new_files = proto_dir_files(&compat.new).await?;
+
+    let mut changes = Vec::new();
+    for (name, old_path) in &old_files {
+        let new_path = match new_files.get(name) {
+            Some(path) => path,
+            None => {
+                info!("{} was removed", name);
+                continue;
+            }
+        };
+        let old_source = read_proto_source(old_path).await?;
+        let new_source = read_proto_source(new_path).await?;
+        let old = parse_complete(&old_source)
+            .map_err(|err| anyhow!("could not parse proto file {}: {}", old_path.display(), err))?;
+        let new = parse_complete(&new_source)
+            .map_err(|err| anyhow!("could not parse proto file {}: {}", new_path.display(), err))?;
+        changes.extend(compare_protos(name, &old, &new));
+    }
+
+    match opts.format {
+        Format::Text => {
+            for change in &changes {
+                error!(
+                    "{}: message {}: {}",
+                    change.file, change.message, change.description
+                );
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string_pretty(&changes)?),
+    }
+
+    Ok(changes.is_empty())
+}
+
+/// Proto files in a directory, by file name.
+async fn proto_dir_files(dir: impl AsRef<Path>) -> Result<BTreeMap<String, PathBuf>> {
+    let mut entries = read_proto_dir(dir).await?;
+    let mut files = BTreeMap::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "proto") {
+            let name = entry.file_name().to_string_lossy().to_string();
+            files.insert(name, path);
+        }
+    }
+    Ok(files)
+}
+
+/// Breaking changes between two versions of a proto file, matching messages by their full name
+/// and fields by number.
+pub fn compare_protos(file: &str, old: &AbstractProto, new: &AbstractProto) -> Vec<BreakingChange> {
+    let new_messages: Vec<_> = new.all_messages().collect();
+
+    let mut changes = Vec::new();
+    for (name, old_message) in old.all_messages() {
+        if let Some((_, new_message)) = new_messages.iter().find(|(new_name, _)| *new_name == name)
+        {
+            changes.extend(compare_messages(old_message, new_message).into_iter().map(
+                |description| BreakingChange {
+                    file: file.to_string(),
+                    message: name.clone(),
+                    description,
+                },
+            ));
+        }
+    }
+    changes
+}
+
+fn compare_messages(old: &Message, new: &Message) -> Vec<String> {
+    let mut changes = Vec::new();
+    for old_field in old.all_fields() {
+        let number = old_field.number.value();
+        let new_field = match new.field_by_number(number) {
+            Some(field) => field,
+            None => {
+                if old_field.rule.variant == RuleVariant::Required {
+                    changes.push(format!(
+                        "required field '{}' ({}) was removed",
+                        old_field.name.as_ref(),
+                        number
+                    ));
+                }
+                continue;
+            }
+        };
+
+        let old_type = type_name(&old_field.typ);
+        let new_type = type_name(&new_field.typ);
+        if old_type != new_type {
+            if old_field.name.as_ref() == new_field.name.as_ref() {
+                changes.push(format!(
+                    "field '{}' ({}) changed type from {} to {}",
+                    old_field.name.as_ref(),
+                    number,
+                    old_type,
+                    new_type
+                ));
+            } else {
+                changes.push(format!(
+                    "number {} was used by '{}' ({}) and is now used by '{}' ({})",
+                    number,
+                    old_field.name.as_ref(),
+                    old_type,
+                    new_field.name.as_ref(),
+                    new_type
+                ));
+            }
+        }
+        if old_field.rule.variant != new_field.rule.variant {
+            changes.push(format!(
+                "field '{}' ({}) changed rule from {} to {}",
+                old_field.name.as_ref(),
+                number,
+                rule_name(old_field.rule.variant),
+                rule_name(new_field.rule.variant)
+            ));
+        }
+    }
+    changes
+}
+
+/// Type as written in a proto file, such as `int32` or `map<string, User>`.
+pub fn type_name(typ: &FieldType) -> String {
+    match typ {
+        FieldType::MessageOrEnum(name) => name.as_ref().to_string(),
+        FieldType::Map(map) => format!("map<{}, {}>", type_name(map.key()), type_name(map.value())),
+        FieldType::Group(_) => "group".to_string(),
+        typ => format!("{:?}", typ).to_lowercase(),
+    }
+}
+
+fn rule_name(rule: RuleVariant) -> String {
+    format!("{:?}", rule).to_lowercase()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn changes(old: &str, new: &str) -> Vec<String> {
+        let old = parse_complete(old).unwrap();
+        let new = parse_complete(new).unwrap();
+        compare_protos("user.proto", &old, &new)
+            .into_iter()
+            .map(|change| format!("{}: {}", change.message, change.description))
+            .collect()
+    }
+
+    #[test]
+    fn test_compatible_change() {
+        let old = r#"syntax = "proto2";
+message User {
+  required int64 id = 1;
+  optional string name = 2;
+  optional string email = 3;
+}"#;
+        // Renaming fields, removing optional fields and adding fields keep the wire format
+        let new = r#"syntax = "proto2";
+message User {
+  required int64 id = 1;
+  optional string full_name = 2;
+  optional bool active = 4;
+}"#;
+        assert!(changes(old, new).is_empty());
+    }
+
+    #[test]
+    fn test_breaking_changes() {
+        let old = r#"syntax = "proto2";
+message User {
+  required int64 id = 1;
+  optional string name = 2;
+  optional string email = 3;
+  repeated string tags = 4;
+  message Address {
+    optional string city = 1;
+  }
+}"#;
+        let new = r#"syntax = "proto2";
+message User {
+  optional int32 name = 2;
+  optional int64 created_at = 3;
+  optional string tags = 4;
+  message Address {
+    optional bytes city = 1;
+  }
+}"#;
+        assert_eq!(
+            changes(old, new),
+            vec![
+                "User: required field 'id' (1) was removed",
+                "User: field 'name' (2) changed type from string to int32",
+                "User: number 3 was used by 'email' (string) and is now used by 'created_at' (int64)",
+                "User: field 'tags' (4) changed rule from repeated to optional",
+                "User.Address: field 'city' (1) changed type from string to bytes",
+            ]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::lint::RuleLevel;
+
+/// Configuration file, loaded with `--config`.
+///
+/// ```toml
+/// required_columns = ["created_at"]
+///
+/// [tables.users]
+/// uri = "postgresql:///users"
+///
+/// [tables.invoices]
+/// schema = "billing"
+///
+/// [types]
+/// string = ["text"]
+///
+/// [rules]
+/// duplicate-tag = "warn"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Columns every verified table must have
+    #[serde(default)]
+    pub required_columns: Vec<String>,
+    /// Per-table overrides, keyed by table name
+    #[serde(default)]
+    pub tables: HashMap<String, TableConfig>,
+    /// Column types accepted for a proto type on top of the built-in ones, keyed by proto type
+    /// name such as `string` or `google.protobuf.Timestamp`
+    #[serde(default)]
+    pub types: HashMap<String, Vec<String>>,
+    /// Lint rule levels, keyed by rule name
+    #[serde(default)]
+    pub rules: HashMap<String, RuleLevel>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TableConfig {
+    /// Database URI the table lives in, overriding `--uri`
+    pub uri: Option<String>,
+    /// Schema the table lives in, overriding `--schema`
+    pub schema: Option<String>,
+    /// Timestamp fields stored as separate date and time columns, keyed by field name
+    #[serde(default)]
+    pub split_timestamps: HashMap<String, SplitTimestamp>,
+}
+
+/// Columns a `google.protobuf.Timestamp` field is split into, such as
+/// `occurred_at = { date = "event_date", time = "event_time" }`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SplitTimestamp {
+    pub date: String,
+    pub time: String,
+}
+
+impl Config {
+    pub async fn load(path: impl AsRef<Path>) -> Result<Config> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .context("could not read config file")?;
+        Config::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Config> {
+        toml::from_str(content).context("could not parse config file")
+    }
+
+    /// Database URI for a table, falling back to `default` if the table has no override.
+    pub fn table_uri<'a>(&'a self, table: &str, default: &'a str) -> &'a str {
+        self.tables
+            .get(table)
+            .and_then(|table| table.uri.as_deref())
+            .unwrap_or(default)
+    }
+
+    /// Timestamp fields of a table stored as separate date and time columns.
+    pub fn split_timestamps(&self, table: &str) -> HashMap<String, SplitTimestamp> {
+        self.tables
+            .get(table)
+            .map(|table| table.split_timestamps.clone())
+            .unwrap_or_default()
+    }
+
+    /// Schema of a table, or `None` if the table has no override.
+    pub fn table_schema(&self, table: &str) -> Option<&str> {
+        self.tables.get(table)?.schema.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_table_uri() {
+        let config = Config::parse(
+            r#"
+            [tables.users]
+            uri = "postgresql:///accounts"
+
+            [tables.orders]
+            uri = "postgresql:///billing"
+
+            [tables.products]
+            "#,
+        )
+        .unwrap();
+
+        let default = "postgresql:///db";
+        assert_eq!(config.table_uri("users", default), "postgresql:///accounts");
+        assert_eq!(config.table_uri("orders", default), "postgresql:///billing");
+        assert_eq!(config.table_uri("products", default), default);
+        assert_eq!(config.table_uri("unknown", default), default);
+    }
+
+    #[test]
+    fn test_table_schema() {
+        let config = Config::parse(
+            r#"
+            [tables.invoices]
+            schema = "billing"
+
+            [tables.users]
+            uri = "postgresql:///accounts"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.table_schema("invoices"), Some("billing"));
+        assert_eq!(config.table_schema("users"), None);
+        assert_eq!(config.table_schema("unknown"), None);
+    }
+
+    #[test]
+    fn test_split_timestamps() {
+        let config = Config::parse(
+            r#"
+            [tables.events.split_timestamps]
+            occurred_at = { date = "event_date", time = "event_time" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.split_timestamps("events")["occurred_at"],
+            SplitTimestamp {
+                date: "event_date".to_string(),
+                time: "event_time".to_string(),
+            }
+        );
+        assert!(config.split_timestamps("users").is_empty());
+    }
+}
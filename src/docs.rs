@@ -0,0 +1,198 @@
+//! `protosql docs`, which collects the comments of messages, fields, enums and enum values for
+//! generating API documentation.
+
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use protobuf_parser::{parse_complete, Enumeration};
+use serde::Serialize;
+
+use crate::{
+    commands::{Format, Protosql},
+    compat::type_name,
+    proto_files, read_proto_source,
+};
+
+/// The documented declarations of a proto file.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FileDocs {
+    pub file: String,
+    pub messages: Vec<MessageDocs>,
+    pub enums: Vec<EnumDocs>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct MessageDocs {
+    /// Full name of the message, such as `User.Address`
+    pub name: String,
+    pub comment: Option<String>,
+    /// Fields, including the fields of oneofs
+    pub fields: Vec<FieldDocs>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FieldDocs {
+    pub name: String,
+    pub number: i32,
+    /// Type as written in the proto file, such as `map<string, int32>`
+    #[serde(rename = "type")]
+    pub typ: String,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct EnumDocs {
+    /// Full name of the enum, such as `User.Role`
+    pub name: String,
+    pub comment: Option<String>,
+    pub values: Vec<EnumValueDocs>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct EnumValueDocs {
+    pub name: String,
+    pub number: i32,
+    pub comment: Option<String>,
+}
+
+/// Prints the comments in the proto files given with `--file` or `--dir`.
+pub async fn docs(opts: &Protosql) -> Result<bool> {
+    let mut files = Vec::new();
+    for path in proto_files(opts).await? {
+        let source = read_proto_source(&path).await?;
+        files.push(file_docs(&path, &source)?);
+    }
+
+    match opts.format {
+        Format::Text => {
+            for file in &files {
+                print_text(file);
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string_pretty(&files)?),
+    }
+
+    Ok(true)
+}
+
+/// Documentation of a single proto file, nested messages and enums following their parent.
+pub fn file_docs(path: &Path, source: &str) -> Result<FileDocs> {
+    let proto =
+        parse_complete(source).map_err(|err| anyhow!("could not parse proto file: {}", err))?;
+
+    let mut enums: Vec<_> = proto
+        .enums
+        .iter()
+        .map(|enumeration| enum_docs(enumeration.name.as_ref().to_string(), enumeration))
+        .collect();
+    let mut messages = Vec::new();
+    for (name, message) in proto.all_messages() {
+        for enumeration in &message.enums {
+            let enum_name = format!("{}.{}", name, enumeration.name.as_ref());
+            enums.push(enum_docs(enum_name, enumeration));
+        }
+        messages.push(MessageDocs {
+            comment: message.comments.text(),
+            fields: message
+                .all_fields()
+                .map(|field| FieldDocs {
+                    name: field.name.as_ref().to_string(),
+                    number: field.number.value(),
+                    typ: type_name(&field.typ),
+                    comment: field.comments.text(),
+                })
+                .collect(),
+            name,
+        });
+    }
+
+    Ok(FileDocs {
+        file: path.to_string_lossy().to_string(),
+        messages,
+        enums,
+    })
+}
+
+fn enum_docs(name: String, enumeration: &Enumeration) -> EnumDocs {
+    EnumDocs {
+        name,
+        comment: enumeration.comments.text(),
+        values: enumeration
+            .values
+            .iter()
+            .map(|value| EnumValueDocs {
+                name: value.name.as_ref().to_string(),
+                number: value.number.value(),
+                comment: value.comments.text(),
+            })
+            .collect(),
+    }
+}
+
+/// Prints each declaration with its comment indented below it.
+fn print_text(file: &FileDocs) {
+    println!("{}", file.file);
+    let print_comment = |comment: &Option<String>, indent: &str| {
+        for line in comment.iter().flat_map(|comment| comment.lines()) {
+            println!("{}{}", indent, line);
+        }
+    };
+    for message in &file.messages {
+        println!("  message {}", message.name);
+        print_comment(&message.comment, "    ");
+        for field in &message.fields {
+            println!("    {} {} = {}", field.typ, field.name, field.number);
+            print_comment(&field.comment, "      ");
+        }
+    }
+    for enumeration in &file.enums {
+        println!("  enum {}", enumeration.name);
+        print_comment(&enumeration.comment, "    ");
+        for value in &enumeration.values {
+            println!("    {} = {}", value.name, value.number);
+            print_comment(&value.comment, "      ");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_field_comment_in_json() {
+        let source = r#"syntax = "proto3";
+// A user account
+message User {
+  // Never reused
+  int64 id = 1;
+  Role role = 2; // what the user can do
+  enum Role {
+    // Read only
+    VIEWER = 0;
+    ADMIN = 1; // everything
+  }
+}"#;
+        let docs = file_docs(Path::new("user.proto"), source).unwrap();
+        let json = serde_json::to_value(&docs).unwrap();
+
+        assert_eq!(json["messages"][0]["name"], "User");
+        assert_eq!(json["messages"][0]["comment"], "A user account");
+        assert_eq!(
+            json["messages"][0]["fields"][0],
+            serde_json::json!({
+                "name": "id",
+                "number": 1,
+                "type": "int64",
+                "comment": "Never reused",
+            })
+        );
+        assert_eq!(
+            json["messages"][0]["fields"][1]["comment"],
+            "what the user can do"
+        );
+        assert_eq!(json["enums"][0]["name"], "User.Role");
+        assert_eq!(json["enums"][0]["values"][0]["comment"], "Read only");
+        assert_eq!(json["enums"][0]["values"][1]["comment"], "everything");
+    }
+}
@@ -0,0 +1,536 @@
+//! `protosql fmt`, which re-emits proto files with consistent indentation and spacing.
+//!
+//! Formatting works on the source lines rather than the parsed file, so comments, services and
+//! the order of declarations are kept exactly as written. The parsed file decides where blank
+//! lines go between declarations.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, bail, Context, Result};
+use protobuf_parser::{parse_complete, AbstractProto, Comments, Message, Span};
+
+use crate::{
+    commands::{Fmt, Protosql},
+    http,
+    log::*,
+    proto_files, read_proto_source, write_file_atomic,
+};
+
+/// Indentation of each nesting level
+const INDENT: &str = "  ";
+
+/// Formats the proto files given with `--file` or `--dir`, printing them or writing them back
+/// with `--write`.
+pub async fn fmt(opts: &Protosql, fmt: &Fmt) -> Result<bool> {
+    for path in proto_files(opts).await? {
+        let source = read_proto_source(&path).await?;
+        let formatted = format_source(&source)?;
+        if !fmt.write {
+            print!("{}", formatted);
+        } else if path.to_str().is_some_and(http::is_url) {
+            bail!("cannot write to remote proto file {}", path.display());
+        } else if formatted != source {
+            write_file_atomic(&path, &formatted)
+                .await
+                .with_context(|| format!("could not write {}", path.display()))?;
+            info!("formatted {}", path.display());
+        }
+    }
+    Ok(true)
+}
+
+/// Formats a proto file, which must parse.
+///
+/// Every statement and opening brace ends its line, every line is indented by its nesting level,
+/// runs of whitespace are collapsed and `=` is surrounded by single spaces. A declaration keeps
+/// a single blank line before it, or before its leading comments, if it had one, and leading
+/// comments stay attached to their declaration. Elsewhere consecutive blank lines are collapsed
+/// into one. Aggregate option values such as `{ get: "/users" }` keep the lines they were
+/// written on.
+pub fn format_source(source: &str) -> Result<String> {
+    let proto =
+        parse_complete(source).map_err(|err| anyhow!("could not parse proto file: {}", err))?;
+    let layout = Layout::new(source, &proto);
+
+    let mut lines = Vec::new();
+    let mut depth = 0usize;
+    let mut state = LineState::default();
+    let mut blank = false;
+    for (number, raw) in (1..).zip(source.lines()) {
+        let line = raw.trim();
+        let continued = state.in_comment;
+        if line.is_empty() && !continued {
+            blank = true;
+            continue;
+        }
+        if !continued {
+            let blank_before = match layout.groups.get(&number) {
+                Some(blank_line_before) => *blank_line_before,
+                None => blank && !layout.attached.contains(&number),
+            };
+            if blank_before && lines.last().is_some_and(|last: &String| !last.is_empty()) {
+                lines.push(String::new());
+            }
+            blank = false;
+        }
+
+        for (n, (text, braces)) in format_line(line, &mut state).into_iter().enumerate() {
+            if continued && n == 0 {
+                // Continuation lines of block comments line up with the text after the `/*`,
+                // or with its `*` if they start with one
+                let align = if text.starts_with('*') { " " } else { "   " };
+                let indent = INDENT.repeat(state.comment_indent);
+                lines.push(match text.is_empty() {
+                    true => text,
+                    false => format!("{}{}{}", indent, align, text),
+                });
+            } else {
+                let indent = depth.saturating_sub(braces.leading_closes);
+                lines.push(format!("{}{}", INDENT.repeat(indent), text));
+                state.comment_indent = indent;
+            }
+            depth = (depth + braces.opens).saturating_sub(braces.closes);
+        }
+    }
+    while lines.last().is_some_and(|last| last.is_empty()) {
+        lines.pop();
+    }
+
+    let mut formatted = lines.join("\n");
+    formatted.push('\n');
+    Ok(formatted)
+}
+
+/// State carried from one source line to the next
+#[derive(Debug, Default)]
+struct LineState {
+    /// Whether the line starts inside a block comment
+    in_comment: bool,
+    /// Nesting level of the line the open block comment started on
+    comment_indent: usize,
+    /// Nesting of the aggregate option value the line is in, such as `{ get: "/users" }`
+    value_depth: usize,
+}
+
+/// Braces on a line, outside of strings and comments
+#[derive(Debug, Default)]
+struct Braces {
+    opens: usize,
+    closes: usize,
+    /// Closing braces before anything else on the line, which dedent the line itself
+    leading_closes: usize,
+}
+
+/// Normalizes the spacing of a trimmed line, keeping strings and comments as they are, and
+/// splits it after each statement and opening brace. Returns the resulting lines with their
+/// braces.
+fn format_line(line: &str, state: &mut LineState) -> Vec<(String, Braces)> {
+    let mut lines = Vec::new();
+    let mut out = String::new();
+    let mut braces = Braces::default();
+    let mut pending_space = false;
+    let mut leading = true;
+    // Set after a statement or opening brace, so anything but a comment starts a new line
+    let mut line_done = false;
+    let mut i = 0;
+    while i < line.len() {
+        let rest = &line[i..];
+        if state.in_comment {
+            match rest.find("*/") {
+                Some(end) => {
+                    out.push_str(&rest[..end + 2]);
+                    state.in_comment = false;
+                    // Code after a comment ending on a later line goes on a line of its own
+                    line_done = line_done || i == 0;
+                    i += end + 2;
+                    continue;
+                }
+                None => {
+                    out.push_str(rest);
+                    break;
+                }
+            }
+        }
+
+        let c = rest.chars().next().unwrap();
+        if c.is_whitespace() {
+            pending_space = true;
+            i += c.len_utf8();
+            continue;
+        }
+        let is_comment = rest.starts_with("//") || rest.starts_with("/*");
+        let empty_body = c == '}' && out.ends_with('{');
+        if (line_done && !is_comment && c != ';' && !empty_body)
+            || (c == '}' && !leading && !empty_body && state.value_depth == 0)
+        {
+            lines.push((std::mem::take(&mut out), std::mem::take(&mut braces)));
+            leading = true;
+            pending_space = false;
+        }
+        // A comment after a statement stays on its line, the statement after it doesn't
+        line_done &= is_comment;
+        if rest.starts_with("//") {
+            push_space(&mut out);
+            out.push_str(rest.trim_end());
+            break;
+        }
+
+        if c != '}' {
+            leading = false;
+        }
+        if rest.starts_with("/*") {
+            if pending_space {
+                push_space(&mut out);
+            }
+            state.in_comment = true;
+            out.push_str("/*");
+            i += 2;
+            pending_space = false;
+            continue;
+        }
+        if c == '"' || c == '\'' {
+            let len = string_len(rest, c);
+            if pending_space || ends_with_any(&out, &[',', '='][..]) {
+                push_space(&mut out);
+            }
+            out.push_str(&rest[..len]);
+            i += len;
+            pending_space = false;
+            continue;
+        }
+
+        // A brace opening an option value rather than a block, such as `= {` or `a: {`
+        let value_open =
+            c == '{' && (state.value_depth > 0 || ends_with_any(out.trim_end(), &['=', ':'][..]));
+        match c {
+            '=' => {
+                push_space(&mut out);
+                out.push('=');
+            }
+            ';' | ',' | ')' | ']' | '>' => out.push(c),
+            _ => {
+                let after_open = ends_with_any(&out, &['(', '[', '<'][..]);
+                let spaced = pending_space || ends_with_any(&out, &[',', '='][..]);
+                if c == '{' || (spaced && !after_open) {
+                    push_space(&mut out);
+                }
+                out.push(c);
+            }
+        }
+        match c {
+            '{' if value_open => {
+                state.value_depth += 1;
+                braces.opens += 1;
+            }
+            '{' => {
+                braces.opens += 1;
+                line_done = true;
+            }
+            '}' => {
+                if leading {
+                    braces.leading_closes += 1;
+                }
+                braces.closes += 1;
+                match state.value_depth {
+                    0 => line_done = true,
+                    _ => state.value_depth -= 1,
+                }
+            }
+            ';' if state.value_depth == 0 => line_done = true,
+            _ => {}
+        }
+        pending_space = false;
+        i += c.len_utf8();
+    }
+    lines.push((out, braces));
+    lines
+}
+
+/// Separates the next token with a single space, unless it starts the line.
+fn push_space(out: &mut String) {
+    if !out.is_empty() && !out.ends_with(' ') {
+        out.push(' ');
+    }
+}
+
+fn ends_with_any(out: &str, chars: &[char]) -> bool {
+    out.chars().last().is_some_and(|last| chars.contains(&last))
+}
+
+/// Length of the string literal at the start of `text`, including its quotes.
+fn string_len(text: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return i + 1;
+        }
+    }
+    text.len()
+}
+
+/// Where blank lines go around the declarations of a parsed file, by source line (1-based)
+#[derive(Default)]
+struct Layout {
+    /// The line each declaration's group, its leading comments or else the declaration itself,
+    /// starts on, and whether a blank line comes before it
+    groups: HashMap<u32, bool>,
+    /// Lines between leading comments and their declaration, where blank lines are dropped
+    attached: HashSet<u32>,
+}
+
+impl Layout {
+    fn new(source: &str, proto: &AbstractProto) -> Self {
+        let mut layout = Layout::default();
+        for message in &proto.messages {
+            layout.add_message(source, message);
+        }
+        for enumeration in &proto.enums {
+            layout.add(source, &enumeration.comments, enumeration.name.position());
+            for value in &enumeration.values {
+                layout.add(source, &value.comments, value.name.position());
+            }
+        }
+        for extension in &proto.extensions {
+            layout.add(
+                source,
+                &extension.field.comments,
+                extension.field.name.position(),
+            );
+        }
+        layout
+    }
+
+    fn add_message(&mut self, source: &str, message: &Message) {
+        if message.is_map_entry() {
+            return;
+        }
+        if let Some(name) = &message.name {
+            self.add(source, &message.comments, name.position());
+        }
+        for field in message.all_fields() {
+            self.add(source, &field.comments, field.name.position());
+        }
+        for extension in &message.extensions {
+            self.add(
+                source,
+                &extension.field.comments,
+                extension.field.name.position(),
+            );
+        }
+        for nested in &message.messages {
+            self.add_message(source, nested);
+        }
+        for enumeration in &message.enums {
+            self.add(source, &enumeration.comments, enumeration.name.position());
+            for value in &enumeration.values {
+                self.add(source, &value.comments, value.name.position());
+            }
+        }
+    }
+
+    fn add(&mut self, source: &str, comments: &Comments, declaration: Span) {
+        let declaration = declaration.line;
+        let start = match comments.leading.first() {
+            Some(first) => comment_lines(source, first).0,
+            None => declaration,
+        };
+        self.groups.insert(start, comments.blank_line_before);
+        if let Some(last) = comments.leading.last() {
+            let end = comment_lines(source, last).1;
+            self.attached.extend(end + 1..=declaration);
+        }
+    }
+}
+
+/// First and last line of a comment, from its `//` or `/*` to the end of its line or its `*/`,
+/// given the comment's text
+fn comment_lines(source: &str, text: &Span) -> (u32, u32) {
+    let before = &source[..text.offset];
+    let block_marker = before.rfind("/*");
+    let marker = before.rfind("//").max(block_marker);
+    let skipped = marker.map_or(0, |marker| before[marker..].matches('\n').count());
+
+    let after = &source[text.offset + text.fragment.0.len()..];
+    let rest = match after.find("*/") {
+        Some(end) if block_marker.is_some() && marker == block_marker => &after[..end],
+        _ => "",
+    };
+    let lines = text.fragment.0.matches('\n').count() + rest.matches('\n').count();
+    (text.line - skipped as u32, text.line + lines as u32)
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Clap;
+
+    use super::*;
+
+    #[test]
+    fn test_format_source() {
+        let source = r#"syntax="proto3";
+package   shop;
+
+
+message Order{
+int64 id=1; // the id
+    map<string,int32>   quantities = 2 [deprecated=true];
+        /* a block
+    comment */
+  message Line {
+      string   sku = 1;
+  }
+  oneof payment { string card = 3;
+  string iban = 4;
+  }
+}
+
+service Orders {
+  rpc Get(GetRequest) returns (Order) {
+    option (google.api.http) = { get: "/orders/{id}" };
+  }
+}
+"#;
+        let expected = r#"syntax = "proto3";
+package shop;
+
+message Order {
+  int64 id = 1; // the id
+  map<string, int32> quantities = 2 [deprecated = true];
+  /* a block
+     comment */
+  message Line {
+    string sku = 1;
+  }
+  oneof payment {
+    string card = 3;
+    string iban = 4;
+  }
+}
+
+service Orders {
+  rpc Get(GetRequest) returns (Order) {
+    option (google.api.http) = { get: "/orders/{id}" };
+  }
+}
+"#;
+        assert_eq!(format_source(source).unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_format_write() {
+        let dir = std::env::temp_dir().join(format!("protosql-fmt-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join("user.proto");
+        tokio::fs::write(&path, "message User{int64 id=1;}")
+            .await
+            .unwrap();
+
+        let opts = Protosql::parse_from([
+            "protosql",
+            "--uri",
+            "postgres://localhost",
+            "--file",
+            path.to_str().unwrap(),
+        ]);
+        assert!(fmt(&opts, &Fmt { write: true }).await.unwrap());
+        let formatted = tokio::fs::read_to_string(&path).await.unwrap();
+        let mut entries = tokio::fs::read_dir(&dir).await.unwrap();
+        let mut files = Vec::new();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            files.push(entry.file_name());
+        }
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        assert_eq!(formatted, "message User {\n  int64 id = 1;\n}\n");
+        assert_eq!(files, vec!["user.proto"]);
+    }
+
+    #[test]
+    fn test_format_idempotent() {
+        let source = r#"
+syntax = "proto2";
+// Leading comment
+message  User {
+    required int64 id = 1 ;
+    optional string name = 2 [default = "a  =  b"];
+    /*
+     * Nested
+     */
+    message Address { optional string city = 1; }
+    enum Kind { KIND_UNKNOWN = 0; KIND_ADMIN = 1; }
+}
+
+"#;
+        let once = format_source(source).unwrap();
+        assert_eq!(format_source(&once).unwrap(), once);
+        assert!(once.contains(r#"optional string name = 2 [default = "a  =  b"];"#));
+        assert!(once.contains("  /*\n   * Nested\n   */\n"));
+    }
+
+    #[test]
+    fn test_format_blank_line_groups() {
+        let source = r#"message User {
+int64 id = 1;
+string name = 2;
+
+
+
+// Contact details
+string email = 3;
+}
+"#;
+        let expected = r#"message User {
+  int64 id = 1;
+  string name = 2;
+
+  // Contact details
+  string email = 3;
+}
+"#;
+        let formatted = format_source(source).unwrap();
+        assert_eq!(formatted, expected);
+
+        let proto = parse_complete(&formatted).unwrap();
+        let blank_lines: Vec<_> = proto.messages[0]
+            .fields
+            .iter()
+            .map(|field| field.comments.blank_line_before)
+            .collect();
+        assert_eq!(blank_lines, vec![false, false, true]);
+    }
+
+    #[test]
+    fn test_format_attached_comments() {
+        let source = r#"message User {
+  int64 id = 1;
+  /*
+   * Display name
+   */
+
+  string name = 2;
+  // Contact details
+
+  // Primary email
+
+  string email = 3;
+}
+"#;
+        let expected = r#"message User {
+  int64 id = 1;
+  /*
+   * Display name
+   */
+  string name = 2;
+  // Contact details
+
+  // Primary email
+  string email = 3;
+}
+"#;
+        assert_eq!(format_source(source).unwrap(), expected);
+    }
+}
@@ -0,0 +1,238 @@
+//! `protosql graph`, which prints how the messages of proto files reference each other, as a
+//! Graphviz diagram with `--dot`.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use protobuf_parser::{parse_complete, AbstractProto, Field, FieldType, RuleVariant};
+use serde::Serialize;
+
+use crate::{
+    commands::{self, Format, Protosql},
+    proto_files, read_proto_source,
+    resolve::{qualify, TypeKind, TypeResolver},
+};
+
+/// Messages and enums, with an edge for each field referencing another type.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Node {
+    /// Fully qualified name, such as `shop.User.Address`
+    pub name: String,
+    pub kind: NodeKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+    Message,
+    Enum,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Edge {
+    /// Message declaring the field
+    pub from: String,
+    /// Type of the field, or the value type of a map
+    pub to: String,
+    pub field: String,
+    pub repeated: bool,
+    pub map: bool,
+}
+
+/// Prints the graph of the proto files given with `--file` or `--dir`.
+pub async fn graph(opts: &Protosql, cmd: &commands::Graph) -> Result<bool> {
+    let mut sources = Vec::new();
+    for path in proto_files(opts).await? {
+        sources.push(read_proto_source(&path).await?);
+    }
+    let protos = sources
+        .iter()
+        .map(|source| {
+            parse_complete(source).map_err(|err| anyhow!("could not parse proto file: {}", err))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut resolver = TypeResolver::default();
+    for proto in &protos {
+        resolver.add_proto(proto);
+        resolver.add_imports(proto, &opts.include_path).await?;
+    }
+
+    let graph = Graph::new(&protos, &resolver);
+    if cmd.dot {
+        print!("{}", graph.to_dot());
+        return Ok(true);
+    }
+    match opts.format {
+        Format::Text => {
+            for edge in &graph.edges {
+                println!("{} -> {} ({})", edge.from, edge.to, edge.field);
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string_pretty(&graph)?),
+    }
+
+    Ok(true)
+}
+
+impl Graph {
+    /// Builds the graph of the messages declared in `protos`, resolving field types with
+    /// `resolver`. Types which can't be resolved, such as well-known types, are left out.
+    pub fn new(protos: &[AbstractProto], resolver: &TypeResolver) -> Self {
+        let mut nodes = BTreeMap::new();
+        let mut edges = Vec::new();
+        for proto in protos {
+            let package = proto
+                .package
+                .as_ref()
+                .map(|package| package.as_ref())
+                .unwrap_or("");
+            for enumeration in &proto.enums {
+                nodes.insert(qualify(package, enumeration.name.as_ref()), NodeKind::Enum);
+            }
+            for (name, message) in proto.all_messages() {
+                let name = qualify(package, &name);
+                for enumeration in &message.enums {
+                    nodes.insert(qualify(&name, enumeration.name.as_ref()), NodeKind::Enum);
+                }
+                for field in message.all_fields() {
+                    let (to, kind) = match field_type_name(field)
+                        .and_then(|typ| resolver.resolve_name(&name, typ))
+                    {
+                        Some(resolved) => resolved,
+                        None => continue,
+                    };
+                    let kind = match kind {
+                        TypeKind::Message => NodeKind::Message,
+                        TypeKind::Enum => NodeKind::Enum,
+                    };
+                    nodes.entry(to.clone()).or_insert(kind);
+                    edges.push(Edge {
+                        from: name.clone(),
+                        to,
+                        field: field.name.as_ref().to_string(),
+                        repeated: field.rule.variant == RuleVariant::Repeated,
+                        map: matches!(field.typ, FieldType::Map(_)),
+                    });
+                }
+                nodes.insert(name, NodeKind::Message);
+            }
+        }
+
+        Graph {
+            nodes: nodes
+                .into_iter()
+                .map(|(name, kind)| Node { name, kind })
+                .collect(),
+            edges,
+        }
+    }
+
+    /// The graph in the Graphviz DOT language, with messages as boxes and enums as ellipses.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph protos {\n    node [shape=box];\n");
+        for node in &self.nodes {
+            let shape = match node.kind {
+                NodeKind::Message => "",
+                NodeKind::Enum => " [shape=ellipse]",
+            };
+            dot.push_str(&format!("    {}{};\n", quote(&node.name), shape));
+        }
+        for edge in &self.edges {
+            let label = if edge.map {
+                format!("{} (map)", edge.field)
+            } else if edge.repeated {
+                format!("{} (repeated)", edge.field)
+            } else {
+                edge.field.clone()
+            };
+            dot.push_str(&format!(
+                "    {} -> {} [label={}];\n",
+                quote(&edge.from),
+                quote(&edge.to),
+                quote(&label)
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Name of the message or enum a field references, the value type for maps.
+fn field_type_name<'f>(field: &'f Field) -> Option<&'f str> {
+    match &field.typ {
+        FieldType::MessageOrEnum(name) => Some(name.as_ref()),
+        FieldType::Map(map) => map.value_type_name().map(|name| name.as_ref()),
+        _ => None,
+    }
+}
+
+fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_graph() {
+        let source = r#"syntax = "proto3";
+package shop;
+enum Status {
+  ACTIVE = 0;
+}
+message User {
+  message Address {
+    string city = 1;
+  }
+  int64 id = 1;
+  Address address = 2;
+  repeated Order orders = 3;
+  map<string, Order> orders_by_code = 4;
+  Status status = 5;
+  google.protobuf.Timestamp created_at = 6;
+}
+message Order {
+  int64 id = 1;
+}"#;
+        let proto = parse_complete(source).unwrap();
+        let mut resolver = TypeResolver::default();
+        resolver.add_proto(&proto);
+        let graph = Graph::new(&[proto], &resolver);
+
+        let nodes: Vec<_> = graph
+            .nodes
+            .iter()
+            .map(|node| (node.name.as_str(), node.kind))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("shop.Order", NodeKind::Message),
+                ("shop.Status", NodeKind::Enum),
+                ("shop.User", NodeKind::Message),
+                ("shop.User.Address", NodeKind::Message),
+            ]
+        );
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph protos {\n"));
+        assert!(dot.contains("    \"shop.Status\" [shape=ellipse];\n"));
+        assert!(dot.contains("    \"shop.User\" -> \"shop.User.Address\" [label=\"address\"];\n"));
+        assert!(
+            dot.contains("    \"shop.User\" -> \"shop.Order\" [label=\"orders (repeated)\"];\n")
+        );
+        assert!(
+            dot.contains("    \"shop.User\" -> \"shop.Order\" [label=\"orders_by_code (map)\"];\n")
+        );
+        assert!(dot.contains("    \"shop.User\" -> \"shop.Status\" [label=\"status\"];\n"));
+        assert!(!dot.contains("Timestamp"));
+    }
+}
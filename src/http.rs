@@ -0,0 +1,134 @@
+//! Fetching proto files over HTTP(S), for `--file https://...`.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::{redirect::Policy, Client, StatusCode};
+
+/// How long fetching a proto file may take, including connecting
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Redirects followed before giving up
+const MAX_REDIRECTS: usize = 10;
+
+/// Largest proto file fetched, so a misconfigured URL can't fill up memory
+const MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+
+/// Checks if a `--file` argument is an `http` or `https` URL rather than a path.
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Fetches the body of a URL, following redirects and failing on anything other than a
+/// `200 OK` response.
+pub async fn fetch(url: &str) -> Result<Vec<u8>> {
+    fetch_with_limit(url, MAX_BODY_SIZE).await
+}
+
+async fn fetch_with_limit(url: &str, max_size: usize) -> Result<Vec<u8>> {
+    let client = Client::builder()
+        .timeout(TIMEOUT)
+        .redirect(Policy::limited(MAX_REDIRECTS))
+        .user_agent("protosql")
+        .build()?;
+    let mut response = client
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("could not fetch {}", url))?;
+    if response.status() != StatusCode::OK {
+        bail!(
+            "fetching {} failed with HTTP status {}",
+            url,
+            response.status().as_u16()
+        );
+    }
+
+    let too_large = || anyhow!("{} is larger than {} bytes", url, max_size);
+    if response
+        .content_length()
+        .is_some_and(|len| len > max_size as u64)
+    {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .with_context(|| format!("could not fetch {}", url))?
+    {
+        if body.len() + chunk.len() > max_size {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Serves `responses` in order, one per connection, returning the address listened on.
+    async fn serve(host: &str, responses: Vec<String>) -> Option<String> {
+        let listener = TcpListener::bind((host, 0)).await.ok()?;
+        let addr = listener.local_addr().ok()?;
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+        });
+        Some(addr.to_string())
+    }
+
+    fn response(status: &str, headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}",
+            status,
+            body.len(),
+            headers,
+            body
+        )
+    }
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let addr = serve(
+            "127.0.0.1",
+            vec![
+                response("301 Moved Permanently", "Location: /user.proto\r\n", ""),
+                response("200 OK", "", "message User {}"),
+                response("404 Not Found", "", ""),
+                response("200 OK", "", "message Large {}"),
+            ],
+        )
+        .await
+        .unwrap();
+        let url = format!("http://{}/old.proto", addr);
+        assert_eq!(fetch(&url).await.unwrap(), b"message User {}");
+
+        let err = fetch(&url).await.unwrap_err();
+        assert!(err.to_string().ends_with("failed with HTTP status 404"));
+
+        let err = fetch_with_limit(&url, 4).await.unwrap_err();
+        assert!(err.to_string().ends_with("is larger than 4 bytes"));
+
+        // Not every machine has IPv6 loopback
+        if let Some(addr) = serve("::1", vec![response("200 OK", "", "message V6 {}")]).await {
+            let url = format!("http://{}/user.proto", addr);
+            assert_eq!(fetch(&url).await.unwrap(), b"message V6 {}");
+        }
+
+        assert!(is_url("https://example.com/user.proto"));
+        assert!(!is_url("protos/user.proto"));
+    }
+}
@@ -0,0 +1,407 @@
+//! JSON Schemas of messages stored in `jsonb` columns, and checking stored values against them
+//! for `--check-json-shape`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use protobuf_parser::{AbstractProto, Enumeration, Field, FieldType, Message, RuleVariant};
+use serde_json::{json, Map, Value};
+
+use crate::resolve::{lookup, qualify};
+
+/// Messages and enums of a proto file, by name without the package, to build schemas from.
+pub struct JsonSchemas<'p, 'a> {
+    messages: HashMap<String, &'p Message<'a>>,
+    enums: HashMap<String, &'p Enumeration<'a>>,
+}
+
+impl<'p, 'a> JsonSchemas<'p, 'a> {
+    pub fn new(proto: &'p AbstractProto<'a>) -> Self {
+        let mut enums: HashMap<_, _> = proto
+            .enums
+            .iter()
+            .map(|enumeration| (enumeration.name.as_ref().to_string(), enumeration))
+            .collect();
+        let mut messages = HashMap::new();
+        for (name, message) in proto.all_messages() {
+            for enumeration in &message.enums {
+                enums.insert(qualify(&name, enumeration.name.as_ref()), enumeration);
+            }
+            messages.insert(name, message);
+        }
+        JsonSchemas { messages, enums }
+    }
+
+    /// JSON Schema of a message, such as `User.Address`, with the messages it uses as
+    /// definitions. Returns `None` if the message isn't declared in the file.
+    pub fn message_schema(&self, name: &str) -> Option<Value> {
+        self.messages.get(name)?;
+        let reference = json!({ "$ref": format!("#/definitions/{}", name) });
+        Some(self.with_definitions(reference, vec![name.to_string()]))
+    }
+
+    /// JSON Schema of the values a `jsonb` column stores for a message or group field of the
+    /// message `scope`, or `None` if the field isn't a message declared in the file.
+    pub fn column_schema(&self, scope: &str, field: &Field) -> Option<Value> {
+        let mut schema = match &field.typ {
+            typ @ FieldType::MessageOrEnum(name) if typ.well_known_type().is_none() => {
+                let (name, _) = lookup(&self.messages, scope, name.as_ref())?;
+                self.message_schema(&name)?
+            }
+            FieldType::Group(_) => {
+                let mut pending = Vec::new();
+                let schema = self.type_schema(scope, &field.typ, &mut pending);
+                self.with_definitions(schema, pending)
+            }
+            _ => return None,
+        };
+        if field.rule.variant == RuleVariant::Repeated {
+            let object = schema.as_object_mut()?;
+            let mut items = std::mem::take(object);
+            for key in &["$schema", "definitions"] {
+                if let Some(value) = items.remove(*key) {
+                    object.insert(key.to_string(), value);
+                }
+            }
+            object.insert("type".to_string(), json!("array"));
+            object.insert("items".to_string(), Value::Object(items));
+        }
+        Some(schema)
+    }
+
+    /// Adds the messages in `pending`, and the messages they use, as definitions of `schema`.
+    fn with_definitions(&self, schema: Value, mut pending: Vec<String>) -> Value {
+        let mut definitions = BTreeMap::new();
+        while let Some(name) = pending.pop() {
+            if definitions.contains_key(&name) {
+                continue;
+            }
+            let message = self.messages[&name];
+            let schema = self.object_schema(&name, message.all_fields(), &mut pending);
+            definitions.insert(name, schema);
+        }
+
+        let mut root = json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "definitions": definitions,
+        });
+        if let (Some(root), Value::Object(schema)) = (root.as_object_mut(), schema) {
+            root.extend(schema);
+        }
+        root
+    }
+
+    fn object_schema<'f>(
+        &self,
+        scope: &str,
+        fields: impl Iterator<Item = &'f Field<'f>>,
+        pending: &mut Vec<String>,
+    ) -> Value {
+        let mut properties = Map::new();
+        for field in fields {
+            let schema = self.field_schema(scope, field, pending);
+            match field.typ {
+                // A group's field is named after the group in lower case
+                FieldType::Group(_) => {
+                    properties.insert(field.name.as_ref().to_lowercase(), schema);
+                }
+                // JSON parsers accept both the lowerCamelCase JSON name and the proto name
+                _ => {
+                    properties.insert(field.json_name(), schema.clone());
+                    properties.insert(field.name.as_ref().to_string(), schema);
+                }
+            }
+        }
+        json!({
+            "type": "object",
+            "properties": properties,
+            "additionalProperties": false,
+        })
+    }
+
+    fn field_schema(&self, scope: &str, field: &Field, pending: &mut Vec<String>) -> Value {
+        let schema = self.type_schema(scope, &field.typ, pending);
+        if field.rule.variant == RuleVariant::Repeated {
+            json!({ "type": "array", "items": schema })
+        } else {
+            schema
+        }
+    }
+
+    /// Schema of a value following the proto3 JSON mapping, where 64-bit integers may be strings
+    /// and bytes are base64 strings. Types from other files accept any value.
+    fn type_schema(&self, scope: &str, typ: &FieldType, pending: &mut Vec<String>) -> Value {
+        match typ {
+            FieldType::Int32
+            | FieldType::Uint32
+            | FieldType::Sint32
+            | FieldType::Fixed32
+            | FieldType::Sfixed32 => json!({ "type": "integer" }),
+            FieldType::Int64
+            | FieldType::Uint64
+            | FieldType::Sint64
+            | FieldType::Fixed64
+            | FieldType::Sfixed64 => json!({ "type": ["integer", "string"] }),
+            FieldType::Bool => json!({ "type": "boolean" }),
+            FieldType::Double | FieldType::Float => json!({ "type": "number" }),
+            FieldType::String | FieldType::Bytes => json!({ "type": "string" }),
+            FieldType::Map(map) => json!({
+                "type": "object",
+                "additionalProperties": self.type_schema(scope, map.value(), pending),
+            }),
+            // Groups are nested messages declared inline, so their schema is too
+            FieldType::Group(fields) => self.object_schema(scope, fields.iter(), pending),
+            FieldType::MessageOrEnum(name) => {
+                if let Some((_, enumeration)) = lookup(&self.enums, scope, name.as_ref()) {
+                    let names: Vec<_> = enumeration
+                        .values
+                        .iter()
+                        .map(|value| value.name.as_ref())
+                        .collect();
+                    json!({ "enum": names })
+                } else if let Some((name, _)) = lookup(&self.messages, scope, name.as_ref()) {
+                    let reference = format!("#/definitions/{}", name);
+                    pending.push(name);
+                    json!({ "$ref": reference })
+                } else {
+                    json!({})
+                }
+            }
+        }
+    }
+}
+
+/// Checks a value against a schema made by `JsonSchemas`, returning where and how it differs.
+pub fn validate(schema: &Value, value: &Value) -> Vec<String> {
+    let mut problems = Vec::new();
+    validate_at(schema, schema, value, "$", &mut problems);
+    problems
+}
+
+fn validate_at(
+    root: &Value,
+    schema: &Value,
+    value: &Value,
+    path: &str,
+    problems: &mut Vec<String>,
+) {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.trim_start_matches("#/definitions/");
+        validate_at(root, &root["definitions"][name], value, path, problems);
+        return;
+    }
+    // Unset fields are written as null by some encoders
+    if value.is_null() {
+        return;
+    }
+
+    if let Some(names) = schema["enum"].as_array() {
+        if !names.contains(value) && !value.is_i64() {
+            problems.push(format!("{} is {}, which is not an enum value", path, value));
+        }
+        return;
+    }
+    let types: Vec<_> = match &schema["type"] {
+        Value::String(typ) => vec![typ.as_str()],
+        Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+        _ => return,
+    };
+    if !types.iter().any(|typ| is_type(value, typ)) {
+        problems.push(format!(
+            "{} is {}, but should be {}",
+            path,
+            value,
+            types.join(" or ")
+        ));
+        return;
+    }
+
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                let path = format!("{}.{}", path, key);
+                match (&schema["properties"][key], &schema["additionalProperties"]) {
+                    (Value::Null, Value::Bool(false)) => {
+                        problems.push(format!("{} is not a field of the message", path))
+                    }
+                    (Value::Null, Value::Null) => {}
+                    (Value::Null, additional) => {
+                        validate_at(root, additional, value, &path, problems)
+                    }
+                    (property, _) => validate_at(root, property, value, &path, problems),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                let path = format!("{}[{}]", path, i);
+                validate_at(root, &schema["items"], item, &path, problems);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_type(value: &Value, typ: &str) -> bool {
+    match typ {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use protobuf_parser::parse_complete;
+
+    #[test]
+    fn test_nested_message_schema() {
+        let proto = parse_complete(
+            r#"message User {
+                int64 id = 1;
+                Address address = 2;
+                message Address {
+                    string city = 1;
+                    repeated string lines = 2;
+                    Kind kind = 3;
+                    Geo geo = 4;
+                    enum Kind { HOME = 0; WORK = 1; }
+                }
+                message Geo {
+                    double lat = 1;
+                    map<string, int64> tags = 2;
+                }
+            }"#,
+        )
+        .unwrap();
+        let schemas = JsonSchemas::new(&proto);
+
+        assert_eq!(
+            schemas.message_schema("User.Address").unwrap(),
+            json!({
+                "$schema": "http://json-schema.org/draft-07/schema#",
+                "$ref": "#/definitions/User.Address",
+                "definitions": {
+                    "User.Address": {
+                        "type": "object",
+                        "properties": {
+                            "city": { "type": "string" },
+                            "lines": { "type": "array", "items": { "type": "string" } },
+                            "kind": { "enum": ["HOME", "WORK"] },
+                            "geo": { "$ref": "#/definitions/User.Geo" },
+                        },
+                        "additionalProperties": false,
+                    },
+                    "User.Geo": {
+                        "type": "object",
+                        "properties": {
+                            "lat": { "type": "number" },
+                            "tags": {
+                                "type": "object",
+                                "additionalProperties": { "type": ["integer", "string"] },
+                            },
+                        },
+                        "additionalProperties": false,
+                    },
+                },
+            })
+        );
+        assert_eq!(schemas.message_schema("Address"), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        let proto = parse_complete(
+            r#"message Address {
+                string city = 1;
+                repeated string lines = 2;
+                Point point = 3;
+                string postal_code = 4;
+            }
+            message Point {
+                double lat = 1;
+            }"#,
+        )
+        .unwrap();
+        let schema = JsonSchemas::new(&proto).message_schema("Address").unwrap();
+
+        let valid = json!({ "city": "Oslo", "lines": ["a"], "point": { "lat": 59.9 } });
+        assert!(validate(&schema, &valid).is_empty());
+        assert!(validate(&schema, &json!({ "city": null })).is_empty());
+        assert!(validate(&schema, &json!({ "postalCode": "0150" })).is_empty());
+        assert!(validate(&schema, &json!({ "postal_code": "0150" })).is_empty());
+
+        let invalid =
+            json!({ "city": 1, "lines": [true], "point": { "lng": 10.7 }, "zip": "0150" });
+        assert_eq!(
+            validate(&schema, &invalid),
+            vec![
+                "$.city is 1, but should be string",
+                "$.lines[0] is true, but should be string",
+                "$.point.lng is not a field of the message",
+                "$.zip is not a field of the message",
+            ]
+        );
+        assert_eq!(
+            validate(&schema, &json!([])),
+            vec!["$ is [], but should be object"]
+        );
+    }
+
+    #[test]
+    fn test_repeated_column_schema() {
+        let proto = parse_complete(
+            r#"package shop;
+            message Order {
+                repeated Line lines = 1;
+                google.protobuf.Timestamp created_at = 2;
+                message Line {
+                    string sku = 1;
+                }
+            }"#,
+        )
+        .unwrap();
+        let schemas = JsonSchemas::new(&proto);
+        let fields = &proto.messages[0].fields;
+
+        let schema = schemas.column_schema("Order", &fields[0]).unwrap();
+        assert!(validate(&schema, &json!([{ "sku": "a" }, { "sku": "b" }])).is_empty());
+        assert_eq!(
+            validate(&schema, &json!([{ "sku": 1 }])),
+            vec!["$[0].sku is 1, but should be string"]
+        );
+        assert_eq!(schemas.column_schema("Order", &fields[1]), None);
+    }
+
+    #[test]
+    fn test_group_column_schema() {
+        let proto = parse_complete(
+            r#"message SearchResponse {
+                repeated group Result = 1 {
+                    required string url = 2;
+                    optional group Snippet = 3 {
+                        optional string text = 4;
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let schemas = JsonSchemas::new(&proto);
+        let field = &proto.messages[0].fields[0];
+
+        let schema = schemas.column_schema("SearchResponse", field).unwrap();
+        let valid = json!([{ "url": "a", "snippet": { "text": "b" } }]);
+        assert!(validate(&schema, &valid).is_empty());
+        assert_eq!(
+            validate(&schema, &json!([{ "url": 1, "title": "c" }])),
+            vec![
+                "$[0].title is not a field of the message",
+                "$[0].url is 1, but should be string",
+            ]
+        );
+    }
+}
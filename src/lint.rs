@@ -0,0 +1,195 @@
+//! `protosql lint`, which checks proto files with the parser's validation rules
+//! without connecting to a database.
+
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use anyhow::{anyhow, bail, Result};
+use protobuf_parser::{parse_complete, Diagnostic, Severity, LINT_RULES};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    commands::{Format, Lint, Protosql},
+    config::Config,
+    log::*,
+    proto_files, read_proto_source,
+};
+
+/// Level a lint rule is reported at, set with `--rule name=level` or in the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleLevel {
+    Off,
+    Warn,
+    Error,
+}
+
+impl FromStr for RuleLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "off" => Ok(RuleLevel::Off),
+            "warn" => Ok(RuleLevel::Warn),
+            "error" => Ok(RuleLevel::Error),
+            _ => bail!("unknown rule level '{}', expected error, warn or off", s),
+        }
+    }
+}
+
+/// Levels of the lint rules, falling back to the severity the parser reports.
+#[derive(Debug, Default)]
+pub struct LintRules {
+    levels: HashMap<String, RuleLevel>,
+}
+
+impl LintRules {
+    /// Rule levels from the config file, overridden by `--rule name=level` arguments.
+    pub fn new(config: &Config, overrides: &[String]) -> Result<Self> {
+        let mut levels = config.rules.clone();
+        for rule in overrides {
+            let (name, level) = rule
+                .split_once('=')
+                .ok_or_else(|| anyhow!("invalid rule '{}', expected name=level", rule))?;
+            levels.insert(name.to_string(), level.parse()?);
+        }
+
+        for name in levels.keys() {
+            if !LINT_RULES.contains(&name.as_str()) {
+                bail!(
+                    "unknown lint rule '{}', expected one of {}",
+                    name,
+                    LINT_RULES.join(", ")
+                );
+            }
+        }
+
+        Ok(LintRules { levels })
+    }
+
+    pub fn level(&self, diagnostic: &Diagnostic) -> RuleLevel {
+        match self.levels.get(diagnostic.rule) {
+            Some(level) => *level,
+            None => match diagnostic.severity {
+                Severity::Warning => RuleLevel::Warn,
+                Severity::Error => RuleLevel::Error,
+            },
+        }
+    }
+}
+
+/// A diagnostic at the level its rule is configured at, for output.
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    pub file: String,
+    pub line: u32,
+    pub rule: &'static str,
+    pub level: RuleLevel,
+    pub message: String,
+}
+
+/// Lints the proto files given with `--file` or `--dir`, returning `false` if any rule at the
+/// error level was broken.
+pub async fn lint(opts: &Protosql, lint: &Lint, config: &Config) -> Result<bool> {
+    let rules = LintRules::new(config, &lint.rules)?;
+
+    let mut problems = Vec::new();
+    for path in proto_files(opts).await? {
+        let source = read_proto_source(&path).await?;
+        problems.extend(lint_source(&path, &source, &rules)?);
+    }
+
+    match opts.format {
+        Format::Text => {
+            for problem in &problems {
+                let line = format!(
+                    "{}: line {}: {} [{}]",
+                    problem.file, problem.line, problem.message, problem.rule
+                );
+                if problem.level == RuleLevel::Error {
+                    error!("{}", line);
+                } else {
+                    warn!("{}", line);
+                }
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string_pretty(&problems)?),
+    }
+
+    Ok(!problems
+        .iter()
+        .any(|problem| problem.level == RuleLevel::Error))
+}
+
+/// Problems found in a single proto file, leaving out rules which are turned off.
+pub fn lint_source(path: &Path, source: &str, rules: &LintRules) -> Result<Vec<Problem>> {
+    let proto =
+        parse_complete(source).map_err(|err| anyhow!("could not parse proto file: {}", err))?;
+    let problems = proto
+        .validate()
+        .into_iter()
+        .filter_map(|diagnostic| match rules.level(&diagnostic) {
+            RuleLevel::Off => None,
+            level => Some(Problem {
+                file: path.to_string_lossy().to_string(),
+                line: diagnostic.line(),
+                rule: diagnostic.rule,
+                level,
+                message: diagnostic.message,
+            }),
+        })
+        .collect();
+
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn passes(source: &str, overrides: &[&str]) -> bool {
+        let overrides: Vec<_> = overrides.iter().map(|rule| rule.to_string()).collect();
+        let rules = LintRules::new(&Config::default(), &overrides).unwrap();
+        let problems = lint_source(Path::new("user.proto"), source, &rules).unwrap();
+        !problems
+            .iter()
+            .any(|problem| problem.level == RuleLevel::Error)
+    }
+
+    #[test]
+    fn test_rule_levels() {
+        let source = r#"syntax = "proto3";
+message User {
+  int64 id = 1;
+  string name = 1;
+}"#;
+        assert!(!passes(source, &[]));
+        assert!(passes(source, &["duplicate-tag=warn"]));
+        assert!(passes(source, &["duplicate-tag=off"]));
+
+        let source = r#"syntax = "proto2";
+message User {
+  int64 id = 1;
+}"#;
+        assert!(passes(source, &[]));
+        assert!(!passes(source, &["missing-rule-proto2=error"]));
+
+        let config = Config::parse("[rules]\nmissing-rule-proto2 = \"error\"").unwrap();
+        let rules = LintRules::new(&config, &["missing-rule-proto2=off".to_string()]).unwrap();
+        let problems = lint_source(Path::new("user.proto"), source, &rules).unwrap();
+        assert!(problems.is_empty());
+
+        assert!(LintRules::new(&Config::default(), &["no-such-rule=off".to_string()]).is_err());
+        assert!(LintRules::new(&Config::default(), &["duplicate-tag=loud".to_string()]).is_err());
+
+        let source = r#"syntax = "proto3";
+message User {
+  repeated int64 ids = 1 [packed = yes];
+}"#;
+        let rules = LintRules::new(&Config::default(), &[]).unwrap();
+        let err = lint_source(Path::new("user.proto"), source, &rules).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "could not parse proto file: could not parse 'message User {' at line 2"
+        );
+    }
+}
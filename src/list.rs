@@ -0,0 +1,99 @@
+//! `protosql list`, which prints the messages the parser finds in proto files without
+//! connecting to a database.
+
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use protobuf_parser::parse_complete;
+use serde::Serialize;
+
+use crate::{
+    commands::{Format, Protosql},
+    proto_files, read_proto_source,
+};
+
+/// A message found in a proto file.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ListedMessage {
+    pub file: String,
+    /// Full name of the message, such as `User.Address`
+    pub name: String,
+    /// Number of fields, including the fields of oneofs
+    pub fields: usize,
+}
+
+/// Prints the messages in the proto files given with `--file` or `--dir`.
+pub async fn list(opts: &Protosql) -> Result<bool> {
+    let mut messages = Vec::new();
+    for path in proto_files(opts).await? {
+        let source = read_proto_source(&path).await?;
+        messages.extend(list_source(&path, &source)?);
+    }
+
+    match opts.format {
+        Format::Text => {
+            for message in &messages {
+                println!(
+                    "{}: {} ({} fields)",
+                    message.file, message.name, message.fields
+                );
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string_pretty(&messages)?),
+    }
+
+    Ok(true)
+}
+
+/// Messages in a single proto file, nested messages following their parent.
+pub fn list_source(path: &Path, source: &str) -> Result<Vec<ListedMessage>> {
+    let proto =
+        parse_complete(source).map_err(|err| anyhow!("could not parse proto file: {}", err))?;
+    Ok(proto
+        .all_messages()
+        .map(|(name, message)| ListedMessage {
+            file: path.to_string_lossy().to_string(),
+            name,
+            fields: message.all_fields().count(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_list_nested_messages() {
+        let source = r#"syntax = "proto3";
+message User {
+  int64 id = 1;
+  message Address {
+    string city = 1;
+    message Geo {
+      double lat = 1;
+      double lng = 2;
+    }
+  }
+  oneof contact {
+    string email = 2;
+    string phone = 3;
+  }
+}
+message Empty {}"#;
+        let messages: Vec<_> = list_source(Path::new("user.proto"), source)
+            .unwrap()
+            .into_iter()
+            .map(|message| (message.name, message.fields))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                ("User".to_string(), 3),
+                ("User.Address".to_string(), 1),
+                ("User.Address.Geo".to_string(), 2),
+                ("Empty".to_string(), 0),
+            ]
+        );
+    }
+}
@@ -1,23 +1,59 @@
+use std::sync::{Arc, Mutex};
+
 use clap::crate_name;
 use colorful::{core::color_string::CString, Colorful};
-pub use log::{debug, error, info, trace, warn, Level};
+pub use log::{debug, error, info, warn, Level, LevelFilter};
+
+use crate::{progress::Progress, report::Report};
 
 pub struct SimpleLogger {
-    max_level: Level,
+    max_level: LevelFilter,
+    report: Option<Report>,
+    progress: Progress,
+    limit: WarningLimit,
+    stderr: bool,
 }
 
 impl SimpleLogger {
     pub fn new() -> Self {
         Self {
-            max_level: Level::Info,
+            max_level: LevelFilter::Info,
+            report: None,
+            progress: Progress::default(),
+            limit: WarningLimit::default(),
+            stderr: false,
         }
     }
 
-    pub fn with_level(mut self, max_level: Level) -> Self {
+    pub fn with_level(mut self, max_level: LevelFilter) -> Self {
         self.max_level = max_level;
         self
     }
 
+    /// Writes log output to stderr instead of stdout, keeping stdout for machine readable output
+    pub fn with_stderr(mut self) -> Self {
+        self.stderr = true;
+        self
+    }
+
+    /// Writes log output into a report instead of stdout
+    pub fn with_report(mut self, report: Report) -> Self {
+        self.report = Some(report);
+        self
+    }
+
+    /// Keeps log lines from being drawn over by the progress line
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Leaves out warnings past the limit
+    pub fn with_limit(mut self, limit: WarningLimit) -> Self {
+        self.limit = limit;
+        self
+    }
+
     pub fn init(self) -> Result<(), ::log::SetLoggerError> {
         ::log::set_max_level(::log::LevelFilter::Debug);
         ::log::set_boxed_logger(Box::new(self))
@@ -32,17 +68,88 @@ impl ::log::Log for SimpleLogger {
 
     fn log(&self, record: &::log::Record) {
         if self.enabled(record.metadata()) {
-            let prefix: CString;
-            match record.level() {
-                Level::Debug => prefix = "debug".blue().bold(),
-                Level::Error => prefix = "error".red().bold(),
-                Level::Info => prefix = "info".light_blue().bold(),
-                Level::Trace => prefix = "info".magenta().bold(),
-                Level::Warn => prefix = "warn".yellow().bold(),
+            if record.level() == Level::Warn && !self.limit.allow() {
+                return;
+            }
+            let prefix: CString = match record.level() {
+                Level::Debug => "debug".blue().bold(),
+                Level::Error => "error".red().bold(),
+                Level::Info => "info".light_blue().bold(),
+                Level::Trace => "info".magenta().bold(),
+                Level::Warn => "warn".yellow().bold(),
+            };
+            let line = format!("{}: {}", prefix, record.args());
+            match &self.report {
+                Some(report) => report.push_line(&line),
+                None if self.stderr => self.progress.suspend(|| eprintln!("{}", line)),
+                None => self.progress.suspend(|| println!("{}", line)),
             }
-            println!("{}: {}", prefix, record.args());
         }
     }
 
     fn flush(&self) {}
 }
+
+/// Stops printing warnings after `--max-errors` of them, counting the ones left out.
+///
+/// Cloned handles share the same count.
+#[derive(Clone, Default)]
+pub struct WarningLimit {
+    max: Option<usize>,
+    state: Arc<Mutex<LimitState>>,
+}
+
+#[derive(Default)]
+struct LimitState {
+    count: usize,
+    finished: bool,
+}
+
+impl WarningLimit {
+    pub fn new(max: Option<usize>) -> Self {
+        WarningLimit {
+            max,
+            state: Default::default(),
+        }
+    }
+
+    /// Counts a warning, returning if it should be printed
+    fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.finished {
+            return true;
+        }
+        state.count += 1;
+        self.max.is_none_or(|max| state.count <= max)
+    }
+
+    /// Stops limiting warnings, returning the line telling how many were left out, if any
+    pub fn finish(&self) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        state.finished = true;
+        let suppressed = state.count.saturating_sub(self.max?);
+        (suppressed > 0).then(|| format!("... and {} more", suppressed))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_warning_limit() {
+        let limit = WarningLimit::new(Some(2));
+        let allowed: Vec<_> = (0..5).map(|_| limit.clone().allow()).collect();
+        assert_eq!(allowed, vec![true, true, false, false, false]);
+        assert_eq!(limit.finish(), Some("... and 3 more".to_string()));
+        // The line itself is printed once the limit is finished
+        assert!(limit.allow());
+
+        let unlimited = WarningLimit::default();
+        assert!((0..5).all(|_| unlimited.allow()));
+        assert_eq!(unlimited.finish(), None);
+        let limit = WarningLimit::new(Some(2));
+        limit.allow();
+        assert_eq!(limit.finish(), None);
+    }
+}
@@ -1,88 +1,515 @@
-use std::path::Path;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+    io::{self, IsTerminal},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use baseline::Baseline;
 use clap::Clap;
 use colorful::Colorful;
-use commands::Protosql;
-use heck::CamelCase;
-use protobuf_parser::{parse, AbstractProto, FieldType, Message, RuleVariant};
+use commands::{Command, EpochColumnType, Format, Protosql};
+use config::{Config, SplitTimestamp};
+use futures::{stream, Stream, StreamExt};
+use heck::{CamelCase, SnakeCase};
+use json_schema::JsonSchemas;
+use lint::{LintRules, RuleLevel};
+use progress::Progress;
+use protobuf_parser::{
+    parse_complete, AbstractProto, Field, FieldPresence, FieldType, Message, ProtoValue,
+    RuleVariant, Syntax, WellKnownType,
+};
+use report::Report;
+use resolve::{lookup, qualify, TypeKind, TypeResolver};
+use schema::{ColumnCollation, ForeignKey, Pools, TableIndex};
+use schema_sql::SqlSchema;
 use sea_schema::postgres::def::{ColumnInfo, ColumnType};
+use sqlx::PgPool;
 use tokio::fs::ReadDir;
+use types::{Compatibility, MappedTypeChecker, TypeChecker};
+use validator::MessageValidator;
 
 use crate::log::*;
 
+mod baseline;
 mod commands;
+mod compat;
+mod config;
+mod docs;
+mod format;
+mod graph;
+mod http;
+mod json_schema;
+mod lint;
+mod list;
 mod log;
+mod progress;
+mod report;
+mod resolve;
+mod routes;
 mod schema;
+mod schema_sql;
+mod suggest;
+mod types;
+mod validator;
+mod verify_against;
 
 #[tokio::main]
 async fn main() {
     let opts: Protosql = Protosql::parse();
-    let level = if opts.verbose {
-        Level::Debug
+    let level = log_level(&opts);
+    let report = opts.output.as_ref().map(|_| Report::default());
+    let progress = Progress::new(show_progress(&opts, io::stdout().is_terminal()));
+    let limit = WarningLimit::new(opts.max_errors);
+    let mut logger = SimpleLogger::new()
+        .with_level(level)
+        .with_progress(progress.clone())
+        .with_limit(limit.clone());
+    if let Some(report) = &report {
+        logger = logger.with_report(report.clone());
+    }
+    if opts.format == Format::Json {
+        logger = logger.with_stderr();
+    }
+    logger.init().unwrap();
+
+    if let Some(command) = &opts.command {
+        let result = match command {
+            Command::Lint(lint) => match load_config(&opts).await {
+                Ok(config) => lint::lint(&opts, lint, &config).await,
+                Err(err) => Err(err),
+            },
+            Command::Compat(compat) => compat::compat(&opts, compat).await,
+            Command::List => list::list(&opts).await,
+            Command::Docs => docs::docs(&opts).await,
+            Command::Fmt(fmt) => format::fmt(&opts, fmt).await,
+            Command::Graph(cmd) => graph::graph(&opts, cmd).await,
+            Command::Routes => routes::routes(&opts).await,
+            Command::VerifyAgainst(cmd) => verify_against::verify_against(&opts, cmd).await,
+        };
+        finish_warnings(&limit);
+        let code = match result {
+            Ok(true) => 0,
+            Ok(false) => 2,
+            Err(err) => {
+                error!("{}", err);
+                1
+            }
+        };
+        std::process::exit(code);
+    }
+
+    let result = try_main(&opts, &progress).await;
+    progress.finish();
+    finish_warnings(&limit);
+    if counts_only(&opts) {
+        write_report(report.as_ref(), &opts).await;
+        match result {
+            Ok(summary) => {
+                println!("{}", summary_line(&opts, &summary));
+                std::process::exit(if summary.is_valid() { 0 } else { 2 });
+            }
+            Err(err) if opts.drift_metric || opts.format == Format::Json => {
+                // Keep stdout free of anything but the metric
+                eprintln!("{}: {}", "error".red().bold(), err);
+                std::process::exit(1);
+            }
+            Err(err) => {
+                println!("{}: {}", "error".red().bold(), err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let (Ok(summary), Format::Json) = (&result, opts.format) {
+        println!("{}", summary.to_json());
+    }
+    let (code, summary) = match result {
+        Ok(summary) if summary.is_valid() => (0, "schemas are valid".to_string()),
+        Ok(_) => {
+            error!("found mismatch in schemas");
+            (2, "found mismatch in schemas".to_string())
+        }
+        Err(err) => {
+            error!("{}", err);
+            if level == LevelFilter::Debug {
+                err.chain()
+                    .skip(1)
+                    .for_each(|cause| eprintln!("   {}", format!("- {}", cause).dark_gray()));
+            }
+            (1, err.to_string())
+        }
+    };
+
+    if let Some(output) = &opts.output {
+        write_report(report.as_ref(), &opts).await;
+        let prefix = if code == 0 {
+            "info".light_blue().bold()
+        } else {
+            "error".red().bold()
+        };
+        let line = format!("{}: {}, report written to {}", prefix, summary, output);
+        match opts.format {
+            Format::Json => eprintln!("{}", line),
+            Format::Text => println!("{}", line),
+        }
+    }
+
+    std::process::exit(code);
+}
+
+/// Writes the log to the `--output` file, exiting with `1` if it can't be written.
+async fn write_report(report: Option<&Report>, opts: &Protosql) {
+    if let (Some(report), Some(output)) = (report, &opts.output) {
+        if let Err(err) = report.write(output).await {
+            eprintln!("{}: {}", "error".red().bold(), err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Log level from the verbosity flags. `--summary-only` and `--drift-metric` turn logging off
+/// entirely, unless it's written to a report with `--output`.
+fn log_level(opts: &Protosql) -> LevelFilter {
+    if counts_only(opts) && opts.output.is_none() {
+        LevelFilter::Off
+    } else if opts.verbose {
+        LevelFilter::Debug
     } else if opts.quiet {
-        Level::Warn
+        LevelFilter::Warn
     } else {
-        Level::Info
+        LevelFilter::Info
+    }
+}
+
+/// Checks if only a single line of counts is printed, with `--summary-only` or `--drift-metric`.
+fn counts_only(opts: &Protosql) -> bool {
+    opts.summary_only || opts.drift_metric
+}
+
+/// The line printed when only counts are printed. `--drift-metric` prints the bare number of
+/// mismatches so it can be fed into monitoring, and `--format json` prints the summary as JSON.
+fn summary_line(opts: &Protosql, summary: &Summary) -> String {
+    if opts.drift_metric {
+        summary.mismatches.to_string()
+    } else if opts.format == Format::Json {
+        summary.to_json().to_string()
+    } else {
+        summary.to_string()
+    }
+}
+
+/// Prints how many warnings were left out by `--max-errors`.
+fn finish_warnings(limit: &WarningLimit) {
+    if let Some(line) = limit.finish() {
+        warn!("{}", line);
+    }
+}
+
+/// Checks if a progress line is shown while verifying a directory. It is only shown on a
+/// terminal, and never in modes whose output is read by other programs.
+fn show_progress(opts: &Protosql, is_terminal: bool) -> bool {
+    is_terminal
+        && opts.dir.is_some()
+        && !opts.quiet
+        && opts.format == Format::Text
+        && !counts_only(opts)
+}
+
+/// Verifies the proto files, counting the files with schema mismatches.
+async fn try_main(opts: &Protosql, progress: &Progress) -> Result<Summary> {
+    let config = load_config(opts).await?;
+    let rules = LintRules::new(&config, &[])?;
+    let pools = if opts.consistent_snapshot {
+        Pools::with_snapshot()
+    } else {
+        Pools::default()
     };
-    SimpleLogger::new().with_level(level).init().unwrap();
+    let state = RunState::load(opts).await?;
+    let summary = verify_files(opts, &config, &rules, &pools, &state, progress).await;
+    pools.close().await;
+    let summary = summary?;
+    if let (Some(path), true) = (&opts.baseline, opts.update_baseline) {
+        state.baseline.write(path).await?;
+        info!("baseline written to {}", path);
+    }
+    Ok(summary)
+}
 
-    if let Err(err) = try_main(opts).await {
-        error!("{}", err);
-        if level == Level::Debug {
-            err.chain()
-                .skip(1)
-                .for_each(|cause| eprintln!("   {}", format!("- {}", cause).dark_gray()));
-        }
-        std::process::exit(1);
+/// State loaded once for a run and shared by every verified file
+#[derive(Default)]
+struct RunState {
+    /// Tables read from --schema-sql, used instead of connecting to the database
+    snapshot: Option<SqlSchema>,
+    /// Known mismatches given with --baseline
+    baseline: Baseline,
+}
+
+impl RunState {
+    async fn load(opts: &Protosql) -> Result<Self> {
+        let snapshot = match &opts.schema_sql {
+            Some(path) => Some(SqlSchema::load(path).await?),
+            None => None,
+        };
+        let baseline = match &opts.baseline {
+            Some(path) => Baseline::load(path).await?,
+            None => Baseline::default(),
+        };
+        Ok(RunState { snapshot, baseline })
     }
 }
 
-async fn try_main(opts: Protosql) -> Result<()> {
-    if let Some(dir) = &opts.dir {
-        let mut dirs = read_proto_dir(dir).await?;
-        while let Some(entry) = dirs.next_entry().await? {
-            let file = entry.path();
-            if !verify_file(&file, &opts).await? {
-                error!("found mismatch in schemas");
-                std::process::exit(2);
-            } else {
+async fn verify_files(
+    opts: &Protosql,
+    config: &Config,
+    rules: &LintRules,
+    pools: &Pools,
+    state: &RunState,
+    progress: &Progress,
+) -> Result<Summary> {
+    let mut summary = Summary::default();
+
+    if opts.dir.is_some() {
+        // Tables covered by a proto file, by the database they were checked in and schema
+        let mut covered_tables: BTreeMap<(Option<String>, String), HashSet<String>> =
+            BTreeMap::new();
+        {
+            let files = proto_files(opts).await?;
+            progress.set_total(files.len());
+            let results = verify_dir(files, opts, config, rules, pools, state, progress);
+            futures::pin_mut!(results);
+            while let Some(FileResult { path, result }) = results.next().await {
+                let verified = result?;
+                summary.add(&verified);
+                covered_tables
+                    .entry((verified.uri, verified.schema))
+                    .or_default()
+                    .insert(verified.table);
+                if verified.mismatches > 0 {
+                    // Keep going so the summary and the baseline cover every file
+                    if counts_only(opts) || opts.update_baseline {
+                        continue;
+                    }
+                    return Ok(summary);
+                }
                 info!(
                     "{}",
-                    format!("{} is valid", file.file_name().unwrap().to_string_lossy()).bold()
+                    format!("{} is valid", path.file_name().unwrap().to_string_lossy()).bold()
                 );
+                if opts.output.is_none() && !counts_only(opts) {
+                    progress.suspend(|| println!());
+                }
+            }
+        }
+
+        if opts.check_orphan_tables {
+            for ((uri, schema), covered) in &covered_tables {
+                let tables = match (uri, &state.snapshot) {
+                    (Some(uri), _) => {
+                        let pool = pools.get(uri).await?;
+                        schema::discover_all_tables(&pool, schema).await?
+                    }
+                    (None, Some(snapshot)) => snapshot.tables(schema),
+                    (None, None) => {
+                        unreachable!("tables are checked in a database without --schema-sql")
+                    }
+                };
+                for table in orphan_tables(&tables, covered) {
+                    summary.mismatches += state.baseline.check(
+                        schema,
+                        format!(
+                            "table {}.{} has no proto file",
+                            schema,
+                            table.clone().bold()
+                        ),
+                    );
+                }
             }
-            println!();
         }
     } else if let Some(file) = &opts.file {
-        if !verify_file(file, &opts).await? {
-            error!("found mismatch in schemas");
-            std::process::exit(2);
-        } else {
-            let path: &Path = file.as_ref();
-            info!(
-                "{}",
-                format!("{} is valid", path.file_name().unwrap().to_string_lossy()).bold()
-            );
+        if opts.check_orphan_tables {
+            warn!("--check-orphan-tables is only used with --dir");
         }
+        let verified = verify_file(file, opts, config, rules, pools, state).await?;
+        summary.add(&verified);
+        if verified.mismatches > 0 {
+            return Ok(summary);
+        }
+        let path: &Path = file.as_ref();
+        info!(
+            "{}",
+            format!("{} is valid", path.file_name().unwrap().to_string_lossy()).bold()
+        );
     } else {
-        error!("no --file or --dir specified");
-        std::process::exit(1);
+        bail!("no --file or --dir specified");
+    }
+
+    Ok(summary)
+}
+
+/// Counts of verified files, printed on its own with `--summary-only`.
+#[derive(Debug, Default, PartialEq)]
+struct Summary {
+    passed: usize,
+    failed: usize,
+    /// Mismatches across all files, including orphan tables
+    mismatches: usize,
+    /// Fields given with `--relation-fields`, which are stored in other tables
+    relations: usize,
+    /// Pairs of a proto field and its column across all tables, and how many of them match
+    pairs: usize,
+    matched: usize,
+}
+
+impl Summary {
+    fn add(&mut self, verified: &VerifiedFile) {
+        if verified.mismatches == 0 {
+            self.passed += 1;
+        } else {
+            self.failed += 1;
+        }
+        self.mismatches += verified.mismatches;
+        self.relations += verified.relations;
+        self.pairs += verified.pairs;
+        self.matched += verified.matched;
+    }
+
+    fn is_valid(&self) -> bool {
+        self.mismatches == 0
+    }
+
+    /// Percentage of field and column pairs which match, or `None` if nothing was compared.
+    fn coverage(&self) -> Option<f64> {
+        if self.pairs == 0 {
+            return None;
+        }
+        Some(self.matched as f64 * 100.0 / self.pairs as f64)
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "files": self.passed + self.failed,
+            "passed": self.passed,
+            "failed": self.failed,
+            "mismatches": self.mismatches,
+            "relations": self.relations,
+            "coverage": self.coverage(),
+        })
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} files, {} passed, {} failed, {} mismatches",
+            self.passed + self.failed,
+            self.passed,
+            self.failed,
+            self.mismatches
+        )?;
+        if self.relations > 0 {
+            write!(f, ", relations: {}", self.relations)?;
+        }
+        if let Some(coverage) = self.coverage() {
+            write!(f, ", coverage: {:.1}%", coverage)?;
+        }
+        Ok(())
     }
+}
+
+/// Outcome of verifying a proto file against its table.
+struct VerifiedFile {
+    /// Database the table was checked in, which is `None` with --schema-sql
+    uri: Option<String>,
+    schema: String,
+    table: String,
+    mismatches: usize,
+    relations: usize,
+    /// Pairs of a proto field and its column, and how many of them match
+    pairs: usize,
+    matched: usize,
+}
+
+/// Tables which aren't covered by a proto file.
+fn orphan_tables<'a>(tables: &'a [String], covered: &HashSet<String>) -> Vec<&'a String> {
+    tables
+        .iter()
+        .filter(|table| !covered.contains(*table))
+        .collect()
+}
+
+/// Result of verifying one proto file.
+struct FileResult {
+    path: PathBuf,
+    result: Result<VerifiedFile>,
+}
 
-    Ok(())
+/// Verifies up to `--jobs` proto files at the same time, yielding each result as soon as the file
+/// is done, so results can come in a different order than the files.
+///
+/// The stream can be dropped early to stop verifying, as `try_main` does on the first mismatch.
+fn verify_dir<'a>(
+    files: Vec<PathBuf>,
+    opts: &'a Protosql,
+    config: &'a Config,
+    rules: &'a LintRules,
+    pools: &'a Pools,
+    state: &'a RunState,
+    progress: &'a Progress,
+) -> impl Stream<Item = FileResult> + 'a {
+    stream::iter(files)
+        .map(move |path| async move {
+            let result = match path.file_name() {
+                Some(name) => {
+                    progress.start_file(&name.to_string_lossy());
+                    verify_file(&path, opts, config, rules, pools, state).await
+                }
+                None => Err(anyhow!("{} has no file name", path.display())),
+            };
+            progress.finish_file();
+            FileResult { path, result }
+        })
+        .buffer_unordered(opts.jobs.max(1))
 }
 
-async fn verify_file(path: impl AsRef<Path>, opts: &Protosql) -> Result<bool> {
+async fn verify_file(
+    path: impl AsRef<Path>,
+    opts: &Protosql,
+    config: &Config,
+    rules: &LintRules,
+    pools: &Pools,
+    state: &RunState,
+) -> Result<VerifiedFile> {
     // Open the proto file
     let file_name: &Path = path.as_ref();
-    let file = tokio::fs::read_to_string(&path)
-        .await
-        .context("could not read proto file")?;
-    let (_, proto) = parse(&file).map_err(|_| anyhow!("could not parse proto file"))?;
+    let file = read_proto_source(file_name).await?;
+    let mut proto =
+        parse_complete(&file).map_err(|err| anyhow!("could not parse proto file: {}", err))?;
     info!("loaded proto file '{}'", file_name.to_str().unwrap());
+    if let Some(warning) = assume_syntax(&mut proto, opts) {
+        warn!("{}: {}", file_name.to_string_lossy(), warning);
+    }
+    for diagnostic in proto.validate() {
+        match rules.level(&diagnostic) {
+            RuleLevel::Off => {}
+            RuleLevel::Warn => warn!("{}: {}", file_name.to_string_lossy(), diagnostic),
+            RuleLevel::Error => error!("{}: {}", file_name.to_string_lossy(), diagnostic),
+        }
+    }
 
-    let message_name = opts.message.clone().unwrap_or_else(|| {
+    let schema = opts.schema.as_deref().unwrap_or_else(|| {
+        proto
+            .package
+            .as_ref()
+            .map(|package| package.as_ref())
+            .unwrap_or("public")
+    });
+    let schema = schema::parse_identifier(schema)?;
+
+    let mut message_name = opts.message.clone().unwrap_or_else(|| {
         let file_name = file_name.file_name().unwrap().to_string_lossy();
         let message_name = file_name.split('.').next().unwrap().to_camel_case();
         if opts.dir.is_none() {
@@ -93,17 +520,43 @@ async fn verify_file(path: impl AsRef<Path>, opts: &Protosql) -> Result<bool> {
         }
         message_name
     });
+    // Files such as `user_service.proto` are named after something other than their message
+    let mut matched_table = None;
+    if opts.message.is_none() && find_proto_message(&proto, &message_name).is_err() {
+        let tables = match &opts.table {
+            Some(table) => vec![table.clone()],
+            None => message_tables(&proto, &schema, opts, config, pools, state).await?,
+        };
+        if let Some((name, table)) = message_for_tables(&proto, &tables) {
+            info!(
+                "no message '{}', using message '{}' which matches table '{}'",
+                message_name, name, table
+            );
+            message_name = name;
+            matched_table = Some(table);
+        }
+    }
     let message = find_proto_message(&proto, &message_name)?;
     info!("found message '{}'", message_name);
 
-    let schema = opts.schema.as_deref().unwrap_or_else(|| {
+    let mut resolver = TypeResolver::default();
+    if let Some(dir) = &opts.package_dir {
+        resolver.add_dir(dir).await?;
+    }
+    resolver.add_proto(&proto);
+    if !opts.include_path.is_empty() {
+        resolver.add_imports(&proto, &opts.include_path).await?;
+    }
+    let scope = qualify(
         proto
             .package
             .as_ref()
             .map(|package| package.as_ref())
-            .unwrap_or("public")
-    });
-    let table_name = opts.table.clone().unwrap_or_else(|| {
+            .unwrap_or(""),
+        &message_name,
+    );
+
+    let table_name = opts.table.clone().or(matched_table).unwrap_or_else(|| {
         let file_name = file_name.file_name().unwrap().to_string_lossy();
         let table_name = file_name.split('.').next().unwrap();
         if opts.dir.is_none() {
@@ -111,12 +564,144 @@ async fn verify_file(path: impl AsRef<Path>, opts: &Protosql) -> Result<bool> {
         }
         table_name.to_string()
     });
-    let table_columns = schema::discover_table_columns(&opts.uri, schema, &table_name).await?;
-    info!("connected to database");
+    let table_name = schema::parse_identifier(&table_name)?;
+    let file_ctx = FileContext {
+        proto: &proto,
+        resolver: &resolver,
+        schema: &schema,
+        snapshot: state.snapshot.as_ref(),
+        baseline: &state.baseline,
+    };
+    let mut verified =
+        verify_table(&file_ctx, &message, scope, &table_name, opts, config, pools).await?;
+
+    if opts.include_nested {
+        for (name, nested, nested_table) in nested_tables(&proto, &message_name) {
+            let scope = qualify(
+                proto
+                    .package
+                    .as_ref()
+                    .map(|package| package.as_ref())
+                    .unwrap_or(""),
+                &name,
+            );
+            info!(
+                "verifying nested message '{}' against table '{}'",
+                name, nested_table
+            );
+            let result = verify_table(
+                &file_ctx,
+                nested,
+                scope.clone(),
+                &nested_table,
+                opts,
+                config,
+                pools,
+            )
+            .await;
+            match result {
+                Ok(nested) => {
+                    verified.mismatches += nested.mismatches;
+                    verified.relations += nested.relations;
+                    verified.pairs += nested.pairs;
+                    verified.matched += nested.matched;
+                }
+                // Counted like a mismatch, so a known failure can be kept in the baseline
+                Err(err) => {
+                    verified.mismatches += state
+                        .baseline
+                        .check(&scope, format!("nested message '{}': {}", name, err));
+                }
+            }
+        }
+    }
+    Ok(verified)
+}
+
+/// File-wide state shared by the tables verified for a proto file
+struct FileContext<'a> {
+    proto: &'a AbstractProto<'a>,
+    resolver: &'a TypeResolver,
+    schema: &'a str,
+    /// Tables read from --schema-sql, used instead of connecting to the database
+    snapshot: Option<&'a SqlSchema>,
+    baseline: &'a Baseline,
+}
+
+/// Verifies a message against the columns of a table.
+async fn verify_table(
+    file_ctx: &FileContext<'_>,
+    message: &Message<'_>,
+    scope: String,
+    table_name: &str,
+    opts: &Protosql,
+    config: &Config,
+    pools: &Pools,
+) -> Result<VerifiedFile> {
+    let (proto, resolver) = (file_ctx.proto, file_ctx.resolver);
+    let schema = match config.table_schema(table_name) {
+        Some(schema) => schema::parse_identifier(schema)?,
+        None => file_ctx.schema.to_string(),
+    };
+    let table_name = table_name.to_string();
+    // Without a connection when the tables are read from --schema-sql
+    let uri = match file_ctx.snapshot {
+        Some(_) => None,
+        None => {
+            let default_uri = opts.database_uri()?;
+            Some(config.table_uri(&table_name, &default_uri).to_string())
+        }
+    };
+    let pool = match &uri {
+        Some(uri) => Some(pools.get(uri).await?),
+        None => None,
+    };
+    if opts.explain_discovery {
+        let queries = match &opts.columns_query {
+            Some(query) => vec![query.clone()],
+            None => schema::discovery_queries(&schema, &table_name),
+        };
+        for query in queries {
+            info!("discovery query:\n{}", query);
+        }
+    }
+    let (table_columns, array_elements) = match (file_ctx.snapshot, &pool) {
+        (Some(snapshot), _) => {
+            let table = snapshot.table(&schema, &table_name).ok_or_else(|| {
+                anyhow!("table {}.{} is not in the schema file", schema, table_name)
+            })?;
+            (table.columns.clone(), table.array_elements.clone())
+        }
+        (None, Some(pool)) => {
+            let table_columns = match &opts.columns_query {
+                Some(query) => {
+                    schema::query_table_columns(pool, query, &schema, &table_name).await?
+                }
+                None => {
+                    schema::discover_table_columns_with_pool(pool, &schema, &table_name).await?
+                }
+            };
+            info!("connected to database");
+            let array_elements = match &opts.columns_query {
+                Some(_) => HashMap::new(),
+                None => schema::discover_array_element_types(pool, &schema, &table_name).await?,
+            };
+            (table_columns, array_elements)
+        }
+        (None, None) => unreachable!("a pool is connected without --schema-sql"),
+    };
 
     if table_columns.is_empty() {
-        warn!("table {}.{} has no columns", schema, table_name);
-        std::process::exit(2);
+        let warning = format!("table {}.{} has no columns", schema, table_name);
+        return Ok(VerifiedFile {
+            mismatches: file_ctx.baseline.check(&scope, warning),
+            uri,
+            schema,
+            table: table_name,
+            relations: 0,
+            pairs: 0,
+            matched: 0,
+        });
     }
     info!(
         "found {} columns on table {}.{}",
@@ -125,193 +710,3313 @@ async fn verify_file(path: impl AsRef<Path>, opts: &Protosql) -> Result<bool> {
         table_name
     );
 
-    Ok(verify_message_with_columns(&message, &table_columns))
-}
-
-// async fn load_proto_file(path: impl AsRef<Path>) -> Result<AbstractProto> {
-//     let file = tokio::fs::read_to_string(path)
-//         .await
-//         .context("could not read proto file")?;
-//     let (_, abstract_proto) = parse(file).map_err(|_| anyhow!("could not parse proto file"))?;
-//     // let proto_file =
-//     //     FileDescriptor::parse(file).map_err(|_| anyhow!("could not parse proto file"))?;
-//     Ok(abstract_proto)
-// }
-
-async fn read_proto_dir(path: impl AsRef<Path>) -> Result<ReadDir> {
-    let dir = tokio::fs::read_dir(path)
-        .await
-        .context("could not read protos directory")?;
-    Ok(dir)
-}
-
-fn find_proto_message<'a>(proto: &'a AbstractProto, message_name: &str) -> Result<Message<'a>> {
-    proto
-        .messages
-        .iter()
-        .find(|message| {
-            message
-                .name
-                .as_ref()
-                .map(|name| name.as_ref() == message_name)
-                .unwrap_or(false)
-        })
-        .cloned()
-        .ok_or_else(|| anyhow!("could not find message {}", message_name))
-}
-
-fn verify_message_with_columns(message: &Message, table_columns: &[ColumnInfo]) -> bool {
-    // let max_items = message.fields.len().max(table_columns.len());
-    let mut success = true;
-
-    for proto_field in &message.fields {
-        // println!("{:#?}", proto_field);
-        let table_field = match table_columns
-            .iter()
-            .find(|col| col.name == proto_field.name.as_ref())
-        {
-            Some(col) => col,
-            None => {
-                success = false;
-                warn!(
-                    "missing field in database table: {} {}",
-                    proto_field.name.as_ref().bold(),
-                    format!("{:?}", proto_field.typ).dark_gray()
+    let mut mismatches = 0;
+    let collations = match &pool {
+        Some(pool) if opts.check_collation => {
+            let encoding = schema::database_encoding(pool).await?;
+            if encoding != "UTF8" {
+                mismatches += file_ctx.baseline.check(
+                    &scope,
+                    format!(
+                        "database encoding is {}, but string fields need {}",
+                        encoding,
+                        "UTF8".bold()
+                    ),
                 );
-                continue;
             }
-        };
+            schema::discover_column_collations(pool, &schema, &table_name).await?
+        }
+        _ => HashMap::new(),
+    };
 
-        // Verify types
-        if matches!(
-            proto_field.rule.variant,
-            protobuf_parser::RuleVariant::Repeated
-        ) {
-            if !matches!(table_field.col_type, ColumnType::Array) {
-                success = false;
-                warn!(
-                    "field '{}' is repeated, but database type is not an array",
-                    proto_field.name.as_ref()
-                );
-                continue;
+    let ctx = VerifyContext {
+        opts,
+        proto,
+        resolver,
+        scope,
+        array_elements,
+        collations,
+        split_timestamps: config.split_timestamps(&table_name),
+        baseline: file_ctx.baseline,
+        checker: &MappedTypeChecker::new(&config.types),
+        validators: &validator::from_config(config),
+    };
+    let verification = verify_columns(message, &table_columns, &ctx);
+    mismatches += verification.mismatches;
+    if opts.exact {
+        if let Some(problem) =
+            exact_problem(message, &table_columns, &verification.problem_pairs, &ctx)
+        {
+            mismatches += ctx.mismatch(problem);
+        }
+    }
+    let pairs = field_column_pairs(message, &table_columns, &ctx);
+    if let Some(pool) = pool.as_ref().filter(|_| opts.columns_query.is_none()) {
+        if opts.check_json_shape {
+            mismatches +=
+                check_json_shapes(&ctx, message, &table_columns, pool, &schema, &table_name)
+                    .await?;
+        }
+        if opts.check_pk {
+            let primary_key = schema::discover_primary_key(pool, &schema, &table_name).await?;
+            for problem in primary_key_problems(message, &primary_key) {
+                warn!("{}", problem);
             }
-        } else {
-            let valid_type = match &proto_field.typ {
-                FieldType::Int32 => matches!(table_field.col_type, ColumnType::Integer),
-                FieldType::Int64 => matches!(table_field.col_type, ColumnType::BigInt),
-                FieldType::Uint32 => matches!(table_field.col_type, ColumnType::Integer),
-                FieldType::Uint64 => matches!(table_field.col_type, ColumnType::BigInt),
-                FieldType::Sint32 => matches!(table_field.col_type, ColumnType::Integer),
-                FieldType::Sint64 => matches!(table_field.col_type, ColumnType::BigInt),
-                FieldType::Bool => matches!(table_field.col_type, ColumnType::Boolean),
-                FieldType::Fixed64 => matches!(table_field.col_type, ColumnType::BigInt),
-                FieldType::Sfixed64 => matches!(table_field.col_type, ColumnType::BigInt),
-                FieldType::Double => matches!(table_field.col_type, ColumnType::DoublePrecision),
-                FieldType::String => matches!(
-                    table_field.col_type,
-                    ColumnType::Varchar(_) | ColumnType::Uuid
-                ),
-                FieldType::Bytes => matches!(table_field.col_type, ColumnType::Bytea),
-                FieldType::Fixed32 => matches!(table_field.col_type, ColumnType::Integer),
-                FieldType::Sfixed32 => matches!(table_field.col_type, ColumnType::Integer),
-                FieldType::Float => matches!(table_field.col_type, ColumnType::Real),
-                FieldType::MessageOrEnum(name) => match name.as_ref() {
-                    "google.protobuf.Timestamp" => matches!(
-                        table_field.col_type,
-                        ColumnType::Timestamp(_) | ColumnType::TimestampWithTimeZone(_)
-                    ),
-                    _ => {
-                        warn!(
-                            "unknown type '{}' on field '{}'",
-                            name.as_ref(),
-                            proto_field.name.as_ref()
-                        );
-                        false
-                    }
-                },
-                FieldType::Map(_) => {
-                    warn!(
-                        "protobuf maps are not supported on field '{}'",
-                        proto_field.name.as_ref()
-                    );
-                    false
-                }
-                FieldType::Group(_) => {
-                    warn!(
-                        "protobuf groups are not supported on field '{}'",
-                        proto_field.name.as_ref()
-                    );
-                    false
+        }
+        if opts.check_indexes {
+            let indexes = schema::discover_indexes(pool, &schema, &table_name).await?;
+            for problem in index_problems(message, &indexes, opts.warn_unannotated_indexes) {
+                warn!("{}", problem);
+            }
+        }
+        if opts.check_relations {
+            let table = format!("{}.{}", schema, table_name);
+            for (field, child_table) in child_tables(&ctx, message) {
+                let child_schema = match config.table_schema(&child_table) {
+                    Some(child_schema) => schema::parse_identifier(child_schema)?,
+                    None => schema.clone(),
+                };
+                let foreign_keys =
+                    schema::discover_foreign_keys(pool, &child_schema, &child_table).await?;
+                let child_table = format!("{}.{}", child_schema, child_table);
+                if let Some(problem) =
+                    relation_problem(field, &child_table, &table, foreign_keys.as_deref())
+                {
+                    mismatches += ctx.mismatch(problem);
                 }
-            };
-            if !valid_type {
-                success = false;
-                warn!(
-                    "field '{}' has type '{:?}' which not match database type '{:?}'",
-                    proto_field.name.as_ref(),
-                    proto_field.typ,
-                    table_field.col_type
-                );
-                continue;
             }
         }
-
-        // Verify nullable
-        let field_optional = proto_field.rule.variant == RuleVariant::Optional
-            && proto_field.rule.position.is_some();
-        let column_optional = table_field.not_null.is_none();
-        if field_optional && !column_optional {
-            success = false;
-            warn!(
-                "field '{}' is marked as {} in database, but should be {}",
-                table_field.name,
-                "NOT NULL".bold(),
-                "NULL".bold()
-            );
-        } else if !field_optional && column_optional {
-            success = false;
+    } else if opts.check_relations {
+        // The child tables can only be looked up in a database
+        let fields: Vec<_> = child_tables(&ctx, message)
+            .into_iter()
+            .map(|(field, _)| field.name.as_ref())
+            .collect();
+        if !fields.is_empty() {
+            let source = match opts.columns_query {
+                Some(_) => "--columns-query",
+                None => "--schema-sql",
+            };
             warn!(
-                "field '{}' is marked as {} in database, but should be {}",
-                table_field.name,
-                "NULL".bold(),
-                "NOT NULL".bold()
+                "the child tables of fields {} are not checked with {}",
+                fields.join(", "),
+                source
             );
         }
     }
-
-    for table_column in table_columns {
-        if !message
-            .fields
-            .iter()
-            .any(|field| field.name.as_ref() == table_column.name)
+    if opts.warn_tag_gaps {
+        for warning in tag_gap_warning(message)
+            .into_iter()
+            .chain(tag_order_warning(message))
         {
-            success = false;
-            let field_null_str = if table_column.not_null.is_some() {
-                "nullable=false"
-            } else {
-                "nullable=true"
-            };
-            let field_default_string = if let Some(def) = &table_column.default {
-                format!("default={}", def.0)
-            } else {
-                String::new()
-            };
-            warn!(
-                "unknown field in database table: {} {}",
-                table_column.name.clone().bold(),
-                format!(
-                    "{}, {}, {}",
-                    format!("{:?}", table_column.col_type)
-                        .split('(')
-                        .next()
-                        .unwrap(),
-                    field_null_str,
-                    field_default_string
-                )
-                .dark_gray()
-            );
+            warn!("{}", warning);
         }
     }
+    if opts.warn_tag_efficiency {
+        for warning in tag_efficiency_warnings(message) {
+            warn!("{}", warning);
+        }
+    }
+    if opts.warn_deprecated {
+        let comments = match (file_ctx.snapshot, &pool) {
+            (Some(snapshot), _) => snapshot
+                .table(&schema, &table_name)
+                .map(|table| table.comments.clone())
+                .unwrap_or_default(),
+            (None, Some(pool)) if opts.columns_query.is_none() => {
+                schema::discover_column_comments(pool, &schema, &table_name).await?
+            }
+            _ => HashMap::new(),
+        };
+        for warning in deprecation_warnings(&ctx, message, &table_columns, &comments) {
+            warn!("{}", warning);
+        }
+    }
+    if opts.columns_query.is_none() {
+        if let Some(problem) = id_column_problem(message, &table_columns, &opts.id_field) {
+            warn!("{}", problem);
+        }
+    }
+    let relations = message
+        .all_fields()
+        .filter(|field| ctx.is_relation(message, field))
+        .count();
+    Ok(VerifiedFile {
+        mismatches,
+        relations,
+        pairs,
+        matched: pairs.saturating_sub(verification.problem_pairs.len()),
+        uri,
+        schema,
+        table: table_name,
+    })
+}
+
+/// Counts the pairs of a proto field and its column compared for a message, where a field
+/// without a column or a column without a field is a pair of its own.
+fn field_column_pairs(
+    message: &Message,
+    table_columns: &[ColumnInfo],
+    ctx: &VerifyContext,
+) -> usize {
+    let mut names: HashSet<String> = message
+        .all_fields()
+        .filter(|field| ctx.is_selected(field.name.as_ref()) && !ctx.is_relation(message, field))
+        .flat_map(|field| ctx.field_columns(field))
+        .collect();
+    if !ctx.opts.ignore_extra_columns {
+        names.extend(
+            table_columns
+                .iter()
+                .filter(|col| ctx.is_selected(&col.name))
+                .map(|col| col.name.clone()),
+        );
+    }
+    names.len()
+}
+
+/// Difference between the fields of a message and the columns of its table for `--exact`, or
+/// `None` if they match one to one. Columns in `reported` already have a mismatch of their own,
+/// so they are left out to count each column once.
+fn exact_problem(
+    message: &Message,
+    table_columns: &[ColumnInfo],
+    reported: &HashSet<String>,
+    ctx: &VerifyContext,
+) -> Option<String> {
+    let fields: BTreeSet<String> = message
+        .all_fields()
+        .filter(|field| ctx.is_selected(field.name.as_ref()) && !ctx.is_relation(message, field))
+        .flat_map(|field| ctx.field_columns(field))
+        .filter(|column| !reported.contains(column))
+        .collect();
+    let columns: BTreeSet<String> = table_columns
+        .iter()
+        .filter(|col| ctx.is_selected(&col.name) && !reported.contains(&col.name))
+        .map(|col| col.name.clone())
+        .collect();
+    if fields == columns {
+        return None;
+    }
+
+    let list = |names: Vec<&String>| {
+        if names.is_empty() {
+            return "none".to_string();
+        }
+        names
+            .iter()
+            .map(|name| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    Some(format!(
+        "fields and columns don't match exactly, fields without a column: {}, columns without a field: {}",
+        list(fields.difference(&columns).collect()),
+        list(columns.difference(&fields).collect())
+    ))
+}
+
+/// Overrides the syntax of a file with --assume-syntax, returning a warning if the file
+/// declares a different one.
+fn assume_syntax(proto: &mut AbstractProto, opts: &Protosql) -> Option<String> {
+    let assumed = opts.assume_syntax?.syntax();
+    let declared = proto.syntax;
+    proto.syntax = assumed;
+    if proto.syntax_declared && declared != assumed {
+        Some(format!(
+            "file declares {:?} syntax, but is validated as {:?} because of --assume-syntax",
+            declared, assumed
+        ))
+    } else {
+        None
+    }
+}
+
+/// Primary key columns without a field, or the key fields being declared in a different order
+/// than the key columns.
+fn primary_key_problems(message: &Message, primary_key: &[String]) -> Vec<String> {
+    let fields: Vec<_> = message
+        .all_fields()
+        .map(|field| field.name.as_ref())
+        .collect();
+    let missing: Vec<_> = primary_key
+        .iter()
+        .filter(|column| !fields.contains(&column.as_str()))
+        .map(|column| format!("primary key column '{}' has no field", column))
+        .collect();
+    if !missing.is_empty() {
+        return missing;
+    }
+
+    let declared: Vec<_> = fields
+        .into_iter()
+        .filter(|field| primary_key.iter().any(|column| column == field))
+        .collect();
+    if declared != primary_key {
+        vec![format!(
+            "primary key fields are declared as ({}), but the key is ({})",
+            declared.join(", "),
+            primary_key.join(", ")
+        )]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Repeated message fields with the child table --check-relations expects them in, named after
+/// the message.
+fn child_tables<'m, 'a>(
+    ctx: &VerifyContext,
+    message: &'m Message<'a>,
+) -> Vec<(&'m Field<'a>, String)> {
+    message
+        .all_fields()
+        .filter(|field| ctx.is_selected(field.name.as_ref()) && ctx.is_child_list(field))
+        .filter_map(|field| match &field.typ {
+            FieldType::MessageOrEnum(name) => {
+                let name = name.as_ref().rsplit('.').next().unwrap_or_default();
+                Some((field, name.to_snake_case()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Problem with the child table of a repeated message field, which must exist and have a
+/// foreign key referencing `table`. Both tables are schema qualified, such as `public.user`.
+/// `foreign_keys` is none if the child table doesn't exist.
+fn relation_problem(
+    field: &Field,
+    child_table: &str,
+    table: &str,
+    foreign_keys: Option<&[ForeignKey]>,
+) -> Option<String> {
+    let references = |key: &ForeignKey| format!("{}.{}", key.schema, key.table) == table;
+    match foreign_keys {
+        None => Some(format!(
+            "field '{}' is stored in table '{}', which does not exist",
+            field.name.as_ref(),
+            child_table
+        )),
+        Some(foreign_keys) if !foreign_keys.iter().any(references) => Some(format!(
+            "table '{}' of field '{}' has no foreign key referencing table '{}'",
+            child_table,
+            field.name.as_ref(),
+            table
+        )),
+        Some(_) => None,
+    }
+}
+
+/// Field option asking for the field's column to be indexed
+const INDEXED_OPTION: &str = "(db).indexed";
+
+/// Fields annotated with `[(db).indexed = true]` whose column doesn't lead an index, so lookups
+/// by it can't use one. With `unannotated`, also columns leading an index other than the primary
+/// key whose field has no annotation.
+fn index_problems(message: &Message, indexes: &[TableIndex], unannotated: bool) -> Vec<String> {
+    let leads_index = |column: &str| {
+        indexes
+            .iter()
+            .any(|index| index.columns.first().and_then(Option::as_deref) == Some(column))
+    };
+    let is_annotated = |field: &Field| {
+        field
+            .option(INDEXED_OPTION)
+            .is_some_and(|option| option.proto_value() == Some(ProtoValue::Bool(true)))
+    };
+
+    let mut problems: Vec<_> = message
+        .all_fields()
+        .filter(|field| is_annotated(field) && !leads_index(&column_name(field)))
+        .map(|field| {
+            format!(
+                "field '{}' is annotated as indexed, but no index starts with its column",
+                field.name.as_ref()
+            )
+        })
+        .collect();
+    if unannotated {
+        let mut columns: Vec<_> = indexes
+            .iter()
+            .filter(|index| !index.primary)
+            .filter_map(|index| index.columns.first()?.as_ref())
+            .collect();
+        columns.sort();
+        columns.dedup();
+        for column in columns {
+            let field = message
+                .all_fields()
+                .find(|field| &column_name(field) == column);
+            if let Some(field) = field.filter(|field| !is_annotated(field)) {
+                problems.push(format!(
+                    "column '{}' is indexed, but field '{}' has no {} annotation",
+                    column,
+                    field.name.as_ref(),
+                    INDEXED_OPTION
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// Field numbers a message skips without reserving them, for `--warn-tag-gaps`.
+fn tag_gap_warning(message: &Message) -> Option<String> {
+    let unused = message.unused_numbers();
+    if unused.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "message '{}' skips field numbers {} without reserving them",
+        message
+            .name
+            .as_ref()
+            .map(|name| name.as_ref())
+            .unwrap_or_default(),
+        format_numbers(&unused)
+    ))
+}
+
+/// Fields declared after a field with a higher number, for `--warn-tag-gaps`.
+fn tag_order_warning(message: &Message) -> Option<String> {
+    let mut fields: Vec<_> = message.all_fields().collect();
+    fields.sort_by_key(|field| field.number.position().offset);
+    let mut highest: Option<&Field> = None;
+    let mut out_of_order = Vec::new();
+    for field in fields {
+        match highest {
+            Some(previous) if previous.number.value() > field.number.value() => {
+                out_of_order.push(format!(
+                    "'{}' = {} after '{}' = {}",
+                    field.name.as_ref(),
+                    field.number.value(),
+                    previous.name.as_ref(),
+                    previous.number.value()
+                ));
+            }
+            _ => highest = Some(field),
+        }
+    }
+    if out_of_order.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "message '{}' declares fields out of number order: {}",
+        message
+            .name
+            .as_ref()
+            .map(|name| name.as_ref())
+            .unwrap_or_default(),
+        out_of_order.join(", ")
+    ))
+}
+
+/// Highest field number whose tag is encoded in a single byte
+const SINGLE_BYTE_TAGS: i32 = 15;
+
+/// Scalar fields numbered 16 or higher, whose tag takes two bytes, while numbers 1 to 15 which
+/// take a single byte are neither used nor reserved.
+fn tag_efficiency_warnings(message: &Message) -> Vec<String> {
+    let free: Vec<_> = message
+        .unused_numbers()
+        .into_iter()
+        .filter(|range| *range.start() <= SINGLE_BYTE_TAGS)
+        .map(|range| *range.start()..=(*range.end()).min(SINGLE_BYTE_TAGS))
+        .collect();
+    if free.is_empty() {
+        return Vec::new();
+    }
+    message
+        .all_fields()
+        .filter(|field| field.number.value() > SINGLE_BYTE_TAGS && !field.deprecated)
+        .filter(|field| {
+            !matches!(
+                field.typ,
+                FieldType::MessageOrEnum(_) | FieldType::Map(_) | FieldType::Group(_)
+            )
+        })
+        .map(|field| {
+            format!(
+                "field '{}' uses number {}, which takes two bytes to encode, while {} are free",
+                field.name.as_ref(),
+                field.number.value(),
+                format_numbers(&free)
+            )
+        })
+        .collect()
+}
+
+/// Ranges of numbers as a list such as `3, 5 to 7`.
+fn format_numbers(ranges: &[RangeInclusive<i32>]) -> String {
+    let numbers: Vec<_> = ranges
+        .iter()
+        .map(|range| {
+            if range.start() == range.end() {
+                range.start().to_string()
+            } else {
+                format!("{} to {}", range.start(), range.end())
+            }
+        })
+        .collect();
+    numbers.join(", ")
+}
+
+/// Uses of deprecated messages by the fields of a message which isn't deprecated itself, and
+/// deprecated fields whose column has no comment marking it deprecated.
+fn deprecation_warnings(
+    ctx: &VerifyContext,
+    message: &Message,
+    columns: &[ColumnInfo],
+    comments: &HashMap<String, String>,
+) -> Vec<String> {
+    let messages: HashMap<_, _> = ctx.proto.all_messages().collect();
+    let mut warnings = Vec::new();
+    for field in message.all_fields() {
+        let type_name = match &field.typ {
+            FieldType::MessageOrEnum(name) => Some(name),
+            FieldType::Map(map) => map.value_type_name(),
+            _ => None,
+        };
+        let deprecated_type = type_name
+            .and_then(|name| lookup(&messages, ctx.local_scope(), name.as_ref()))
+            .filter(|(_, typ)| typ.is_deprecated());
+        if let (false, Some((name, _))) = (message.is_deprecated(), deprecated_type) {
+            warnings.push(format!(
+                "field '{}' uses deprecated message {}",
+                field.name.as_ref(),
+                name
+            ));
+        }
+
+        let has_column = columns.iter().any(|col| col.name == field.name.as_ref());
+        let column_deprecated = comments
+            .get(field.name.as_ref())
+            .is_some_and(|comment| comment.to_lowercase().contains("deprecated"));
+        if field.deprecated && has_column && !column_deprecated {
+            warnings.push(format!(
+                "deprecated field '{}' still backs a column which isn't marked deprecated in its comment",
+                field.name.as_ref()
+            ));
+        }
+    }
+    warnings
+}
+
+/// Problem with the column of an integer id field which the database doesn't generate, because
+/// it's not an identity column and doesn't default to the next value of a sequence.
+fn id_column_problem(message: &Message, columns: &[ColumnInfo], id_field: &str) -> Option<String> {
+    let field = message
+        .all_fields()
+        .find(|field| field.name.as_ref() == id_field)?;
+    if !matches!(
+        field.typ,
+        FieldType::Int32
+            | FieldType::Int64
+            | FieldType::Uint32
+            | FieldType::Uint64
+            | FieldType::Sint32
+            | FieldType::Sint64
+    ) {
+        return None;
+    }
+    let column = columns.iter().find(|col| col.name == id_field)?;
+    // Serial columns default to `nextval(...)` of their sequence, while any other default, such
+    // as `0`, gives every row left without an id the same one
+    match &column.default {
+        _ if column.is_identity => None,
+        Some(default) if default.0.contains("nextval(") => None,
+        Some(default) => Some(format!(
+            "id column '{}' defaults to {}, which is not a sequence, so clients must supply ids",
+            id_field, default.0
+        )),
+        None => Some(format!(
+            "id column '{}' has no default, sequence or identity, so clients must supply ids",
+            id_field
+        )),
+    }
+}
+
+async fn load_config(opts: &Protosql) -> Result<Config> {
+    match &opts.config {
+        Some(path) => Config::load(path).await,
+        None => Ok(Config::default()),
+    }
+}
+
+/// The proto files given with `--file` or `--dir`, sorted by name.
+async fn proto_files(opts: &Protosql) -> Result<Vec<PathBuf>> {
+    if let Some(dir) = &opts.dir {
+        let mut dirs = read_proto_dir(dir).await?;
+        let mut files = Vec::new();
+        while let Some(entry) = dirs.next_entry().await? {
+            files.push(entry.path());
+        }
+        files.sort();
+        Ok(files)
+    } else if let Some(file) = &opts.file {
+        Ok(vec![PathBuf::from(file)])
+    } else {
+        bail!("no --file or --dir specified");
+    }
+}
+
+/// Reads a proto file, or fetches it if the path is an http(s) URL.
+async fn read_proto_source(path: &Path) -> Result<String> {
+    match path.to_str().filter(|path| http::is_url(path)) {
+        Some(url) => decode_proto_file(path, http::fetch(url).await?),
+        None => read_proto_file(path).await,
+    }
+}
+
+async fn read_proto_file(path: &Path) -> Result<String> {
+    let bytes = tokio::fs::read(path)
+        .await
+        .context("could not read proto file")?;
+    decode_proto_file(path, bytes)
+}
+
+/// Writes a file through a temporary file next to it which is renamed into place, so an
+/// interrupted run never leaves a partially written file behind.
+async fn write_file_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    tokio::fs::write(&tmp_path, contents).await?;
+    tokio::fs::rename(&tmp_path, path).await
+}
+
+/// Proto files must be UTF-8, so report where the encoding is wrong rather than a raw io error.
+fn decode_proto_file(path: &Path, bytes: Vec<u8>) -> Result<String> {
+    String::from_utf8(bytes).map_err(|err| {
+        anyhow!(
+            "proto file '{}' is not valid UTF-8 at byte {}",
+            path.display(),
+            err.utf8_error().valid_up_to()
+        )
+    })
+}
+
+// async fn load_proto_file(path: impl AsRef<Path>) -> Result<AbstractProto> {
+//     let file = tokio::fs::read_to_string(path)
+//         .await
+//         .context("could not read proto file")?;
+//     let abstract_proto = parse_complete(file).map_err(|_| anyhow!("could not parse proto file"))?;
+//     // let proto_file =
+//     //     FileDescriptor::parse(file).map_err(|_| anyhow!("could not parse proto file"))?;
+//     Ok(abstract_proto)
+// }
+
+async fn read_proto_dir(path: impl AsRef<Path>) -> Result<ReadDir> {
+    let dir = tokio::fs::read_dir(path)
+        .await
+        .context("could not read protos directory")?;
+    Ok(dir)
+}
+
+/// Number of values sampled from each column with --check-json-shape
+const JSON_SAMPLE_SIZE: i64 = 100;
+
+/// Samples the json and jsonb columns storing messages declared in the file, and checks the
+/// values against the JSON Schema of the message. Each column with invalid values is a mismatch.
+async fn check_json_shapes(
+    ctx: &VerifyContext<'_>,
+    message: &Message<'_>,
+    table_columns: &[ColumnInfo],
+    pool: &PgPool,
+    schema: &str,
+    table_name: &str,
+) -> Result<usize> {
+    let schemas = JsonSchemas::new(ctx.proto);
+    let scope = ctx.local_scope();
+
+    let mut mismatches = 0;
+    for field in message.all_fields() {
+        let column = match table_columns
+            .iter()
+            .find(|col| col.name == column_name(field))
+        {
+            Some(column) if types::is_compatible_kind(TypeKind::Message, &column.col_type) => {
+                column
+            }
+            _ => continue,
+        };
+        let json_schema = match schemas.column_schema(scope, field) {
+            Some(json_schema) => json_schema,
+            None => continue,
+        };
+
+        let values =
+            schema::sample_json_values(pool, schema, table_name, &column.name, JSON_SAMPLE_SIZE)
+                .await?;
+        let problems: Vec<_> = values
+            .iter()
+            .flat_map(|value| json_schema::validate(&json_schema, value))
+            .collect();
+        if let Some(problem) = problems.first() {
+            mismatches += ctx.mismatch(format!(
+                "column {} has {} problems with the shape of its values, such as: {}",
+                column.name.as_str().bold(),
+                problems.len(),
+                problem
+            ));
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Nested messages of a message with the tables they are verified against, named after their
+/// path, such as `user_address` for `User.Address`.
+fn nested_tables<'p, 'a>(
+    proto: &'p AbstractProto<'a>,
+    message_name: &str,
+) -> Vec<(String, &'p Message<'a>, String)> {
+    let prefix = format!("{}.", message_name);
+    proto
+        .all_messages()
+        .filter(|(name, _)| name.starts_with(&prefix))
+        .map(|(name, message)| {
+            let table = name
+                .split('.')
+                .map(|part| part.to_snake_case())
+                .collect::<Vec<_>>()
+                .join("_");
+            (name, message, table)
+        })
+        .collect()
+}
+
+/// Problems with a timestamp field stored as a date column and a time column, which must
+/// both exist with those types.
+fn split_timestamp_problems(
+    field: &Field,
+    split: &SplitTimestamp,
+    table_columns: &[ColumnInfo],
+) -> Vec<String> {
+    if field.well_known_type() != Some(WellKnownType::Timestamp) {
+        return vec![format!(
+            "field '{}' is split into date and time columns, but is not a google.protobuf.Timestamp",
+            field.name.as_ref()
+        )];
+    }
+    // Whether each column has the right type, or `None` if it's missing
+    let valid_type = |name: &str, is_valid: fn(&ColumnType) -> bool| {
+        table_columns
+            .iter()
+            .find(|col| col.name == name)
+            .map(|col| is_valid(&col.col_type))
+    };
+    let checks = [
+        (
+            &split.date,
+            "date",
+            valid_type(&split.date, |typ| matches!(typ, ColumnType::Date)),
+        ),
+        (
+            &split.time,
+            "time",
+            valid_type(&split.time, |typ| {
+                matches!(typ, ColumnType::Time(_) | ColumnType::TimeWithTimeZone(_))
+            }),
+        ),
+    ];
+
+    let mut problems = Vec::new();
+    for (name, expected, valid) in &checks {
+        match valid {
+            None => problems.push(format!(
+                "missing {} column '{}' of timestamp field '{}'",
+                expected,
+                name,
+                field.name.as_ref()
+            )),
+            Some(false) => problems.push(format!(
+                "column '{}' of timestamp field '{}' should be a {} column",
+                name,
+                field.name.as_ref(),
+                expected
+            )),
+            Some(true) => {}
+        }
+    }
+    problems
+}
+
+/// Name of the column storing a field. A group's field is named after the group in lower case,
+/// such as `result` for `group Result`.
+fn column_name(field: &Field) -> String {
+    match field.typ {
+        FieldType::Group(_) => field.name.as_ref().to_lowercase(),
+        _ => field.name.as_ref().to_string(),
+    }
+}
+
+/// Tables named after a top level message of `proto` which exist, each looked up in the database
+/// and schema it would be verified in. Databases whose tables can't be listed are left out, so a
+/// file without a matching message fails with that instead of a connection error.
+async fn message_tables(
+    proto: &AbstractProto<'_>,
+    schema: &str,
+    opts: &Protosql,
+    config: &Config,
+    pools: &Pools,
+    state: &RunState,
+) -> Result<Vec<String>> {
+    let default_uri = opts.database_uri().ok();
+    let mut listed: HashMap<(Option<String>, String), Vec<String>> = HashMap::new();
+    let mut tables = Vec::new();
+    for message in &proto.messages {
+        let table = match &message.name {
+            Some(name) => name.as_ref().to_snake_case(),
+            None => continue,
+        };
+        let table_schema = match config.table_schema(&table) {
+            Some(table_schema) => schema::parse_identifier(table_schema)?,
+            None => schema.to_string(),
+        };
+        let uri = default_uri
+            .as_deref()
+            .map(|default_uri| config.table_uri(&table, default_uri).to_string());
+        let key = (uri, table_schema);
+        if !listed.contains_key(&key) {
+            let found = match (&key.0, &state.snapshot) {
+                (_, Some(snapshot)) => snapshot.tables(&key.1),
+                (Some(uri), None) => match list_tables(pools, uri, &key.1).await {
+                    Ok(found) => found,
+                    Err(err) => {
+                        debug!("could not list the tables of schema {}: {:#}", key.1, err);
+                        Vec::new()
+                    }
+                },
+                (None, None) => Vec::new(),
+            };
+            listed.insert(key.clone(), found);
+        }
+        if listed[&key].contains(&table) {
+            tables.push(table);
+        }
+    }
+    Ok(tables)
+}
+
+async fn list_tables(pools: &Pools, uri: &str, schema: &str) -> Result<Vec<String>> {
+    let pool = pools.get(uri).await?;
+    schema::discover_all_tables(&pool, schema).await
+}
+
+/// First top level message named after one of `tables`, such as `UserRole` for `user_role`,
+/// with the table it matches.
+fn message_for_tables(proto: &AbstractProto, tables: &[String]) -> Option<(String, String)> {
+    proto.messages.iter().find_map(|message| {
+        let name = message.name.as_ref()?.as_ref();
+        tables
+            .iter()
+            .find(|table| **table == name.to_snake_case())
+            .map(|table| (name.to_string(), table.clone()))
+    })
+}
+
+fn find_proto_message<'a>(proto: &'a AbstractProto, message_name: &str) -> Result<Message<'a>> {
+    proto
+        .messages
+        .iter()
+        .find(|message| {
+            message
+                .name
+                .as_ref()
+                .map(|name| name.as_ref() == message_name)
+                .unwrap_or(false)
+        })
+        .cloned()
+        .ok_or_else(|| anyhow!("could not find message {}", message_name))
+}
+
+/// State shared while verifying a message against its table.
+struct VerifyContext<'a> {
+    opts: &'a Protosql,
+    /// The proto file the message is from, for file-wide settings such as the syntax
+    proto: &'a AbstractProto<'a>,
+    /// Resolves message and enum field types
+    resolver: &'a TypeResolver,
+    /// Fully qualified name of the message, which type names are resolved relative to
+    scope: String,
+    /// Element types of array columns, such as `status` for a `status[]` column
+    array_elements: HashMap<String, String>,
+    /// Collations of the text columns, by column name, when checked with `--check-collation`
+    collations: HashMap<String, ColumnCollation>,
+    /// Timestamp fields stored as separate date and time columns, from the config file
+    split_timestamps: HashMap<String, SplitTimestamp>,
+    /// Known mismatches given with `--baseline`, which aren't reported
+    baseline: &'a Baseline,
+    /// Decides which column types can store a proto type
+    checker: &'a dyn TypeChecker,
+    /// Custom checks run after the built-in ones
+    validators: &'a [Box<MessageValidator>],
+}
+
+impl<'a> VerifyContext<'a> {
+    /// Reports a mismatch of the message unless it's in the baseline, returning the number of
+    /// mismatches to count.
+    fn mismatch(&self, warning: String) -> usize {
+        self.baseline.check(&self.scope, warning)
+    }
+
+    /// Names of the columns a field is stored in, which are two for a split timestamp.
+    fn field_columns(&self, field: &Field) -> Vec<String> {
+        match self.split_timestamps.get(field.name.as_ref()) {
+            Some(split) => vec![split.date.clone(), split.time.clone()],
+            None => vec![column_name(field)],
+        }
+    }
+
+    /// Checks if a field's type is a message or enum of the given kind.
+    fn resolves_to(&self, field: &Field, kind: TypeKind) -> bool {
+        match &field.typ {
+            FieldType::MessageOrEnum(name) => {
+                self.resolver.resolve(&self.scope, name.as_ref()) == Some(kind)
+            }
+            _ => false,
+        }
+    }
+
+    /// Presence of a field of `message`, treating message types the proto file imports like
+    /// the ones it declares, which always have explicit presence.
+    fn field_presence(&self, message: &Message, field: &Field) -> FieldPresence {
+        match self.proto.field_presence(message, field) {
+            FieldPresence::Implicit
+                if field.rule.variant != RuleVariant::Repeated
+                    && self.resolves_to(field, TypeKind::Message) =>
+            {
+                FieldPresence::Explicit
+            }
+            presence => presence,
+        }
+    }
+
+    /// Checks if a field or column is verified, which is all of them unless `--columns` is given.
+    fn is_selected(&self, name: &str) -> bool {
+        self.opts.columns.is_empty() || self.opts.columns.iter().any(|column| column == name)
+    }
+
+    /// Checks if a bytes field was marked as a UUID with `--bytes-uuid-fields`,
+    /// either by its name or as `Message.field`.
+    fn is_bytes_uuid(&self, message: &Message, field: &Field) -> bool {
+        matches_field_list(&self.opts.bytes_uuid_fields, message, field)
+    }
+
+    /// Checks if a 32-bit integer field was marked as small with `--smallint-fields`,
+    /// either by its name or as `Message.field`.
+    fn is_smallint(&self, message: &Message, field: &Field) -> bool {
+        matches_field_list(&self.opts.smallint_fields, message, field)
+    }
+
+    /// Checks the collation of a string field's column, returning the problem if it isn't the
+    /// one given with `--expected-collation`, or any deterministic collation without it.
+    fn collation_problem(&self, column: &str) -> Option<String> {
+        if !self.opts.check_collation {
+            return None;
+        }
+        let collation = self.collations.get(column)?;
+        match &self.opts.expected_collation {
+            Some(expected) if &collation.name != expected => Some(format!(
+                "uses collation {}, but should use {}",
+                collation.name, expected
+            )),
+            None if !collation.deterministic => Some(format!(
+                "uses nondeterministic collation {}",
+                collation.name
+            )),
+            _ => None,
+        }
+    }
+
+    /// Scope of the message without the package, for looking up types declared in the file by
+    /// their name without the package.
+    fn local_scope(&self) -> &str {
+        self.proto
+            .package
+            .as_ref()
+            .and_then(|package| self.scope.strip_prefix(package.as_ref()))
+            .and_then(|scope| scope.strip_prefix('.'))
+            .unwrap_or(&self.scope)
+    }
+
+    /// Checks if a field was marked with `--relation-fields` as a relationship stored in another
+    /// table, so it has no column.
+    ///
+    /// With `--check-relations`, repeated message fields are stored in a child table too.
+    fn is_relation(&self, message: &Message, field: &Field) -> bool {
+        matches_field_list(&self.opts.relation_fields, message, field)
+            || (self.opts.check_relations && self.is_child_list(field))
+    }
+
+    /// Checks if a field is a repeated message, which isn't a well-known type.
+    fn is_child_list(&self, field: &Field) -> bool {
+        field.rule.variant == RuleVariant::Repeated
+            && self.resolves_to(field, TypeKind::Message)
+            && !field.is_well_known_type()
+    }
+
+    /// Checks if a proto3 `optional` field has a NOT NULL column without a default, so inserting
+    /// a message which leaves it unset fails. Only checked with `--strict-optional`.
+    fn fails_unset_insert(&self, message: &Message, field: &Field, column: &ColumnInfo) -> bool {
+        self.opts.strict_optional
+            && matches!(self.proto.syntax, Syntax::Proto3)
+            && self.field_presence(message, field) == FieldPresence::Explicit
+            && column.not_null.is_some()
+            && column.default.is_none()
+    }
+
+    /// Checks if an int64 field was marked as a Unix timestamp with `--epoch-fields`.
+    fn is_epoch(&self, message: &Message, field: &Field) -> bool {
+        matches_field_list(&self.opts.epoch_fields, message, field)
+    }
+
+    /// Checks if a column can store a Unix timestamp, warning if it doesn't use the column type
+    /// given with `--epoch-column-type`.
+    fn check_epoch_column(&self, field: &Field, col_type: &ColumnType) -> bool {
+        let used = match col_type {
+            ColumnType::BigInt => EpochColumnType::Bigint,
+            ColumnType::TimestampWithTimeZone(_) => EpochColumnType::Timestamptz,
+            _ => return false,
+        };
+        match self.opts.epoch_column_type {
+            Some(expected) if expected != used => warn!(
+                "field '{}' is stored as {}, but --epoch-column-type is {}",
+                field.name.as_ref(),
+                format!("{:?}", used).to_lowercase(),
+                format!("{:?}", expected).to_lowercase()
+            ),
+            _ => {}
+        }
+        true
+    }
+}
+
+/// Checks if a field is in a list of `field` names, `Message.field` names and patterns with a
+/// leading `*`, such as `*_at`.
+fn matches_field_list(list: &[String], message: &Message, field: &Field) -> bool {
+    let message_name = message.name.as_ref().map(|name| name.as_ref());
+    let field_name = field.name.as_ref();
+    list.iter().any(|entry| {
+        let pattern = match entry.split_once('.') {
+            Some((message, pattern)) if message_name == Some(message) => pattern,
+            Some(_) => return false,
+            None => entry,
+        };
+        match pattern.strip_prefix('*') {
+            Some(suffix) => field_name.ends_with(suffix),
+            None => pattern == field_name,
+        }
+    })
+}
+
+/// Mismatches found verifying a message against table columns.
+struct ColumnsVerification {
+    mismatches: usize,
+    /// Names of the fields' columns and the extra columns with at least one mismatch, each a
+    /// pair counted by `field_column_pairs`
+    problem_pairs: HashSet<String>,
+}
+
+/// Verifies a message against table columns, returning the number of mismatches found.
+fn verify_message_with_columns(
+    message: &Message,
+    table_columns: &[ColumnInfo],
+    ctx: &VerifyContext,
+) -> usize {
+    verify_columns(message, table_columns, ctx).mismatches
+}
+
+/// Verifies a message against table columns, also returning the pairs with a mismatch.
+fn verify_columns(
+    message: &Message,
+    table_columns: &[ColumnInfo],
+    ctx: &VerifyContext,
+) -> ColumnsVerification {
+    // let max_items = message.fields.len().max(table_columns.len());
+    let mut mismatches = 0;
+    let mut problem_pairs = HashSet::new();
+    let mut unmatched_fields = Vec::new();
+
+    // Oneof members are verified like regular fields, but since at most one of them can be set,
+    // their columns must always be nullable.
+    for proto_field in message.all_fields() {
+        if !ctx.is_selected(proto_field.name.as_ref()) || ctx.is_relation(message, proto_field) {
+            continue;
+        }
+        let field_mismatches = verify_field(
+            message,
+            proto_field,
+            table_columns,
+            ctx,
+            &mut unmatched_fields,
+        );
+        if field_mismatches > 0 {
+            problem_pairs.extend(ctx.field_columns(proto_field));
+        }
+        mismatches += field_mismatches;
+    }
+
+    // Columns of every field, built once for the message instead of for each column
+    let field_columns: HashSet<String> = message
+        .all_fields()
+        .flat_map(|field| ctx.field_columns(field))
+        .collect();
+    let extra_columns: Vec<_> = table_columns
+        .iter()
+        .filter(|col| ctx.is_selected(&col.name) && !field_columns.contains(&col.name))
+        .collect();
+    if ctx.opts.suggest_matches {
+        let unmatched_columns: Vec<_> = extra_columns.iter().map(|col| col.name.clone()).collect();
+        for suggestion in suggest::suggestions(&unmatched_fields, &unmatched_columns) {
+            info!("{}", suggestion);
+        }
+    }
+    if !ctx.opts.ignore_extra_columns {
+        for table_column in extra_columns {
+            let field_null_str = if table_column.not_null.is_some() {
+                "nullable=false"
+            } else {
+                "nullable=true"
+            };
+            let field_default_string = if let Some(def) = &table_column.default {
+                format!("default={}", def.0)
+            } else {
+                String::new()
+            };
+            let column_mismatches = ctx.mismatch(format!(
+                "unknown field in database table: {} {}",
+                table_column.name.clone().bold(),
+                format!(
+                    "{}, {}, {}",
+                    format!("{:?}", table_column.col_type)
+                        .split('(')
+                        .next()
+                        .unwrap(),
+                    field_null_str,
+                    field_default_string
+                )
+                .dark_gray()
+            ));
+            if column_mismatches > 0 {
+                problem_pairs.insert(table_column.name.clone());
+            }
+            mismatches += column_mismatches;
+        }
+    }
+
+    for validator in ctx.validators {
+        for mismatch in validator(message, table_columns) {
+            let counted = ctx.mismatch(mismatch.to_string());
+            if let (Some(column), true) = (mismatch.column, counted > 0) {
+                problem_pairs.insert(column);
+            }
+            mismatches += counted;
+        }
+    }
+
+    ColumnsVerification {
+        mismatches,
+        problem_pairs,
+    }
+}
+
+/// Verifies a field against its column, returning the number of mismatches. Fields without a
+/// column are added to `unmatched_fields`.
+fn verify_field(
+    message: &Message,
+    proto_field: &Field,
+    table_columns: &[ColumnInfo],
+    ctx: &VerifyContext,
+    unmatched_fields: &mut Vec<String>,
+) -> usize {
+    let mut mismatches = 0;
+    let oneof = proto_field.oneof_index.map(|index| &message.oneofs[index]);
+    if let Some(split) = ctx.split_timestamps.get(proto_field.name.as_ref()) {
+        for problem in split_timestamp_problems(proto_field, split, table_columns) {
+            mismatches += ctx.mismatch(problem);
+        }
+        return mismatches;
+    }
+    // println!("{:#?}", proto_field);
+    let table_field = match table_columns
+        .iter()
+        .find(|col| col.name == column_name(proto_field))
+    {
+        Some(col) => col,
+        None => {
+            mismatches += ctx.mismatch(format!(
+                "missing field in database table: {} {}",
+                column_name(proto_field).bold(),
+                if ctx.is_bytes_uuid(message, proto_field) {
+                    Some("uuid".to_string())
+                } else {
+                    types::default_postgres_type(proto_field)
+                }
+                .unwrap_or_else(|| format!("{:?}", proto_field.typ))
+                .dark_gray()
+            ));
+            unmatched_fields.push(column_name(proto_field));
+            return mismatches;
+        }
+    };
+
+    // Verify types, preferring a `(sql).type` annotation over the default mapping
+    if let Some(sql_type) = types::sql_type_annotation(proto_field) {
+        if !types::matches_sql_type(sql_type, &table_field.col_type) {
+            mismatches += ctx.mismatch(format!(
+                "field '{}' is annotated with type '{}' which does not match database type '{:?}'",
+                proto_field.name.as_ref(),
+                sql_type,
+                table_field.col_type
+            ));
+            return mismatches;
+        }
+    } else if matches!(proto_field.rule.variant, RuleVariant::Repeated) {
+        // A list of messages or groups can also be stored as a single jsonb array
+        let is_message_list = (ctx.resolves_to(proto_field, TypeKind::Message)
+            || matches!(proto_field.typ, FieldType::Group(_)))
+            && types::is_compatible_kind(TypeKind::Message, &table_field.col_type);
+        if !matches!(table_field.col_type, ColumnType::Array) && !is_message_list {
+            mismatches += ctx.mismatch(format!(
+                "field '{}' is repeated, but database type is not an array",
+                proto_field.name.as_ref()
+            ));
+            return mismatches;
+        }
+
+        // Enum arrays can also be checked by their element type
+        let element = ctx.array_elements.get(&table_field.name);
+        if let (FieldType::MessageOrEnum(name), Some(element)) = (&proto_field.typ, element) {
+            if ctx.resolves_to(proto_field, TypeKind::Enum)
+                && !types::is_compatible_enum(name.as_ref(), &ColumnType::from_str(element))
+            {
+                mismatches += ctx.mismatch(format!(
+                    "field '{}' is a list of enum {}, but database type is {}[]",
+                    proto_field.name.as_ref(),
+                    name.as_ref(),
+                    element
+                ));
+                return mismatches;
+            }
+        }
+    } else {
+        let valid_type = match &proto_field.typ {
+            FieldType::Bytes if ctx.is_bytes_uuid(message, proto_field) => {
+                matches!(table_field.col_type, ColumnType::Uuid)
+            }
+            FieldType::Int64 if ctx.is_epoch(message, proto_field) => {
+                ctx.check_epoch_column(proto_field, &table_field.col_type)
+            }
+            FieldType::Int32 | FieldType::Sint32 | FieldType::Sfixed32
+                if ctx.is_smallint(message, proto_field) =>
+            {
+                matches!(
+                    table_field.col_type,
+                    ColumnType::SmallInt | ColumnType::Integer
+                )
+            }
+            // The values of a map of messages must be declared in the proto files
+            FieldType::Map(map)
+                if map.value().well_known_type().is_none()
+                    && map.value_type_name().is_some_and(|name| {
+                        ctx.resolver.resolve(&ctx.scope, name.as_ref()).is_none()
+                    }) =>
+            {
+                warn!(
+                    "unknown map value type '{}' on field '{}'",
+                    map.value_type_name().unwrap().as_ref(),
+                    proto_field.name.as_ref()
+                );
+                false
+            }
+            typ => match ctx.checker.is_compatible(typ, &table_field.col_type) {
+                Compatibility::Compatible => true,
+                Compatibility::Incompatible => false,
+                Compatibility::Unsupported(reason) => {
+                    // Fall back to message and enum types declared in the proto files
+                    let kind = match typ {
+                        FieldType::MessageOrEnum(name) => ctx
+                            .resolver
+                            .resolve(&ctx.scope, name.as_ref())
+                            .map(|kind| (name.as_ref(), kind)),
+                        _ => None,
+                    };
+                    match kind {
+                        Some((name, TypeKind::Enum)) => {
+                            types::is_compatible_enum(name, &table_field.col_type)
+                        }
+                        Some((_, kind)) => types::is_compatible_kind(kind, &table_field.col_type),
+                        None => {
+                            warn!("{} on field '{}'", reason, proto_field.name.as_ref());
+                            false
+                        }
+                    }
+                }
+            },
+        };
+        if !valid_type {
+            mismatches += ctx.mismatch(format!(
+                "field '{}' has type '{:?}' which not match database type '{:?}'",
+                proto_field.name.as_ref(),
+                proto_field.typ,
+                table_field.col_type
+            ));
+            return mismatches;
+        }
+    }
+
+    // Verify collations
+    if matches!(proto_field.typ, FieldType::String) {
+        if let Some(problem) = ctx.collation_problem(&table_field.name) {
+            mismatches += ctx.mismatch(format!(
+                "string field '{}' {}",
+                proto_field.name.as_ref(),
+                problem
+            ));
+        }
+    }
+
+    // Verify defaults
+    let mut has_matching_default = false;
+    if let (true, Some(default)) = (ctx.opts.check_defaults, &proto_field.default) {
+        match &table_field.default {
+            Some(expr)
+                if proto_field
+                    .default_value()
+                    .is_some_and(|value| types::default_matches(&value, &expr.0)) =>
+            {
+                has_matching_default = true;
+            }
+            column_default => {
+                mismatches += ctx.mismatch(format!(
+                    "field '{}' has default {}, but database default is {}",
+                    proto_field.name.as_ref(),
+                    default.as_ref(),
+                    column_default
+                        .as_ref()
+                        .map(|expr| expr.0.as_str())
+                        .unwrap_or("not set")
+                ));
+            }
+        }
+    }
+
+    // Verify nullable, where a matching default stands in for an unset field. proto3 has no
+    // custom defaults, so there an unset field is always left out of the insert
+    let default_applies = has_matching_default && ctx.proto.syntax != Syntax::Proto3;
+    let field_optional = oneof.is_some()
+        || (ctx.field_presence(message, proto_field) == FieldPresence::Explicit
+            && !default_applies);
+    let column_optional = table_field.not_null.is_none();
+    if ctx.fails_unset_insert(message, proto_field, table_field) {
+        // Counted on its own, so a baselined nullability warning doesn't hide it
+        mismatches += ctx.mismatch(format!(
+            "optional field '{}' is {} in database without a default, so inserts leaving it unset fail",
+            table_field.name,
+            "NOT NULL".bold()
+        ));
+    } else if let (Some(oneof), false) = (oneof, column_optional) {
+        mismatches += ctx.mismatch(format!(
+            "field '{}' is part of oneof '{}' and is marked as {} in database, but should be {}",
+            table_field.name,
+            oneof.name.as_ref(),
+            "NOT NULL".bold(),
+            "NULL".bold()
+        ));
+    } else if field_optional && !column_optional {
+        mismatches += ctx.mismatch(format!(
+            "field '{}' is marked as {} in database, but should be {}",
+            table_field.name,
+            "NOT NULL".bold(),
+            "NULL".bold()
+        ));
+    } else if !field_optional && column_optional {
+        mismatches += ctx.mismatch(format!(
+            "field '{}' is marked as {} in database, but should be {}",
+            table_field.name,
+            "NULL".bold(),
+            "NOT NULL".bold()
+        ));
+    }
+
+    mismatches
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::OnceLock;
+
+    use sea_schema::postgres::def::{ArbitraryPrecisionNumericAttr, ColumnExpression, NotNull};
+
+    use super::*;
+
+    impl<'a> VerifyContext<'a> {
+        /// A context for a message, with no column details and an empty baseline.
+        fn for_test(
+            opts: &'a Protosql,
+            proto: &'a AbstractProto<'a>,
+            resolver: &'a TypeResolver,
+            scope: &str,
+        ) -> Self {
+            static BASELINE: OnceLock<Baseline> = OnceLock::new();
+            VerifyContext {
+                opts,
+                proto,
+                resolver,
+                scope: scope.to_string(),
+                array_elements: HashMap::new(),
+                collations: HashMap::new(),
+                split_timestamps: HashMap::new(),
+                baseline: BASELINE.get_or_init(Baseline::default),
+                checker: &types::PostgresTypeChecker,
+                validators: &[],
+            }
+        }
+    }
+
+    fn column(name: &str, col_type: ColumnType, not_null: bool) -> ColumnInfo {
+        ColumnInfo {
+            name: name.to_string(),
+            col_type,
+            default: None,
+            generated: None,
+            not_null: NotNull::from_bool(not_null),
+            is_identity: false,
+        }
+    }
+
+    fn opts(args: &[&str]) -> Protosql {
+        let mut all_args = vec!["protosql", "--uri", "postgres://localhost"];
+        all_args.extend_from_slice(args);
+        Protosql::parse_from(all_args)
+    }
+
+    #[test]
+    fn test_decode_invalid_utf8() {
+        // "caf\xe9" is "café" in Windows-1252
+        let bytes = b"message Caf\xe9 {}".to_vec();
+        let err = decode_proto_file(Path::new("cafe.proto"), bytes).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "proto file 'cafe.proto' is not valid UTF-8 at byte 11"
+        );
+
+        let bytes = "message Café {}".as_bytes().to_vec();
+        assert!(decode_proto_file(Path::new("cafe.proto"), bytes).is_ok());
+    }
+
+    #[test]
+    fn test_oneof_columns_nullable() {
+        let proto = parse_complete(
+            r#"message Payment {
+                int64 id = 1;
+                oneof method {
+                    string card = 2;
+                    string iban = 3;
+                }
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Payment").unwrap();
+        let opts = opts(&[]);
+        let resolver = TypeResolver::default();
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Payment");
+
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("card", ColumnType::Varchar(Default::default()), false),
+            column("iban", ColumnType::Varchar(Default::default()), false),
+        ];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 0);
+
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("card", ColumnType::Varchar(Default::default()), true),
+            column("iban", ColumnType::Varchar(Default::default()), false),
+        ];
+        assert_ne!(verify_message_with_columns(&message, &columns, &ctx), 0);
+    }
 
-    success
+    #[test]
+    fn test_resolved_message_and_enum_columns() {
+        let proto = parse_complete(
+            r#"package shop;
+            enum Currency { USD = 0; }
+            message Order {
+                enum Status { PENDING = 0; }
+                int64 id = 1;
+                Status status = 2;
+                Currency currency = 3;
+                Address address = 4;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Order").unwrap();
+        let mut resolver = TypeResolver::default();
+        resolver.add_proto(&proto);
+
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("status", ColumnType::Integer, true),
+            column("currency", ColumnType::Varchar(Default::default()), true),
+            column("address", ColumnType::Unknown("jsonb".to_string()), false),
+        ];
+        // `Address` is neither declared nor imported
+        let opts = opts(&[]);
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "shop.Order");
+        assert_ne!(verify_message_with_columns(&message, &columns, &ctx), 0);
+
+        let imported =
+            parse_complete("package shop; message Address { string city = 1; }").unwrap();
+        resolver.add_proto(&imported);
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "shop.Order");
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 0);
+    }
+
+    #[test]
+    fn test_bytes_uuid_fields() {
+        let proto = parse_complete(
+            r#"message Upload {
+                bytes id = 1;
+                bytes data = 2;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Upload").unwrap();
+        let resolver = TypeResolver::default();
+        let columns = vec![
+            column("id", ColumnType::Uuid, true),
+            column("data", ColumnType::Bytea, true),
+        ];
+
+        let opts_without_hint = opts(&[]);
+        let ctx = VerifyContext::for_test(&opts_without_hint, &proto, &resolver, "Upload");
+        assert_ne!(verify_message_with_columns(&message, &columns, &ctx), 0);
+
+        let opts_with_hint = opts(&["--bytes-uuid-fields", "Upload.id"]);
+        let ctx = VerifyContext {
+            opts: &opts_with_hint,
+            ..ctx
+        };
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 0);
+
+        // The blob is still checked as bytea
+        let columns = vec![
+            column("id", ColumnType::Uuid, true),
+            column("data", ColumnType::Uuid, true),
+        ];
+        assert_ne!(verify_message_with_columns(&message, &columns, &ctx), 0);
+    }
+
+    #[test]
+    fn test_group_fields() {
+        let proto = parse_complete(
+            r#"syntax = "proto2";
+            message SearchResponse {
+                required int64 id = 1;
+                optional group Summary = 2 {
+                    optional int32 total = 3;
+                }
+                repeated group Result = 4 {
+                    required string url = 5;
+                }
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "SearchResponse").unwrap();
+        let opts = opts(&[]);
+        let resolver = TypeResolver::default();
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "SearchResponse");
+        let jsonb = || ColumnType::Unknown("jsonb".to_string());
+
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("summary", jsonb(), false),
+            column("result", jsonb(), true),
+        ];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 0);
+
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("summary", ColumnType::Text, false),
+            column("result", jsonb(), true),
+        ];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 1);
+    }
+
+    #[test]
+    fn test_split_timestamps() {
+        let proto = parse_complete(
+            r#"syntax = "proto3";
+            message Event {
+                int64 id = 1;
+                google.protobuf.Timestamp occurred_at = 2;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Event").unwrap();
+        let opts = opts(&["--exact"]);
+        let resolver = TypeResolver::default();
+        let split = SplitTimestamp {
+            date: "event_date".to_string(),
+            time: "event_time".to_string(),
+        };
+        let ctx = VerifyContext {
+            split_timestamps: vec![("occurred_at".to_string(), split)]
+                .into_iter()
+                .collect(),
+            ..VerifyContext::for_test(&opts, &proto, &resolver, "Event")
+        };
+
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("event_date", ColumnType::Date, true),
+            column("event_time", ColumnType::Time(Default::default()), true),
+        ];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 0);
+        assert_eq!(
+            exact_problem(&message, &columns, &HashSet::new(), &ctx),
+            None
+        );
+
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("event_date", ColumnType::Varchar(Default::default()), true),
+        ];
+        assert_eq!(
+            split_timestamp_problems(
+                &message.fields[1],
+                &ctx.split_timestamps["occurred_at"],
+                &columns
+            ),
+            vec![
+                "column 'event_date' of timestamp field 'occurred_at' should be a date column",
+                "missing time column 'event_time' of timestamp field 'occurred_at'",
+            ]
+        );
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 2);
+    }
+
+    #[test]
+    fn test_smallint_fields() {
+        let proto = parse_complete(
+            r#"syntax = "proto3";
+            message Ticket {
+                enum Priority { LOW = 0; HIGH = 1; }
+                Priority priority = 1;
+                int32 seats = 2;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Ticket").unwrap();
+        let mut resolver = TypeResolver::default();
+        resolver.add_proto(&proto);
+        let columns = vec![
+            column("priority", ColumnType::SmallInt, true),
+            column("seats", ColumnType::SmallInt, true),
+        ];
+
+        let opts_without_hint = opts(&[]);
+        let ctx = VerifyContext::for_test(&opts_without_hint, &proto, &resolver, "Ticket");
+        // Only the int32 field needs the hint
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 1);
+
+        let opts_with_hint = opts(&["--smallint-fields", "Ticket.seats"]);
+        let ctx = VerifyContext {
+            opts: &opts_with_hint,
+            ..ctx
+        };
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 0);
+
+        // The hint still accepts the default integer column
+        let columns = vec![
+            column("priority", ColumnType::Integer, true),
+            column("seats", ColumnType::Integer, true),
+        ];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 0);
+    }
+
+    #[test]
+    fn test_sql_type_annotation() {
+        let proto = parse_complete(
+            r#"message Invoice {
+                int64 total = 1 [(sql).type = "numeric(10,2)"];
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Invoice").unwrap();
+        let opts = opts(&[]);
+        let resolver = TypeResolver::default();
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Invoice");
+        let numeric = |scale| {
+            ColumnType::Numeric(ArbitraryPrecisionNumericAttr {
+                precision: Some(10),
+                scale: Some(scale),
+            })
+        };
+
+        let columns = vec![column("total", numeric(2), true)];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 0);
+
+        let columns = vec![column("total", numeric(4), true)];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 1);
+
+        // The annotation replaces the default int64 mapping
+        let columns = vec![column("total", ColumnType::BigInt, true)];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 1);
+    }
+
+    #[test]
+    fn test_extra_columns() {
+        let proto = parse_complete(
+            r#"message UserName {
+                int64 id = 1;
+                string name = 2;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "UserName").unwrap();
+        let resolver = TypeResolver::default();
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("name", ColumnType::Varchar(Default::default()), true),
+            column("email", ColumnType::Varchar(Default::default()), true),
+            column(
+                "created_at",
+                ColumnType::Timestamp(Default::default()),
+                true,
+            ),
+        ];
+        let verify = |args: &[&str]| {
+            let opts = opts(args);
+            let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "UserName");
+            verify_message_with_columns(&message, &columns, &ctx)
+        };
+
+        assert_eq!(verify(&[]), 2);
+        assert_eq!(verify(&["--ignore-extra-columns"]), 0);
+        assert_eq!(verify(&["--columns", "id,name"]), 0);
+        assert_eq!(verify(&["--columns", "id,email"]), 1);
+    }
+
+    #[test]
+    fn test_query_columns() {
+        let proto = parse_complete(
+            r#"message Price {
+                int64 id = 1;
+                optional string currency = 2;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Price").unwrap();
+        let opts = opts(&[]);
+        let resolver = TypeResolver::default();
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Price");
+
+        let rows = vec![
+            ("id".to_string(), "bigint".to_string(), false),
+            ("currency".to_string(), "varchar(3)".to_string(), true),
+        ];
+        let columns = schema::columns_from_rows(rows);
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 0);
+
+        let rows = vec![
+            ("id".to_string(), "INTEGER".to_string(), false),
+            ("currency".to_string(), "varchar(3)".to_string(), false),
+        ];
+        let columns = schema::columns_from_rows(rows);
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 2);
+    }
+
+    #[tokio::test]
+    async fn test_verify_dir_stream() {
+        let dir = std::env::temp_dir().join(format!("protosql-stream-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let mut files = Vec::new();
+        for name in ["users.proto", "orders.proto", "invoices.proto"] {
+            let path = dir.join(name);
+            // No matching message, so each file fails
+            tokio::fs::write(&path, "message Other {}").await.unwrap();
+            files.push(path);
+        }
+        // A path without a file name fails on its own instead of ending the stream
+        files.insert(1, dir.join(".."));
+
+        // Tables can't be listed from an invalid URI, which keeps the missing message error
+        let opts = Protosql::parse_from(["protosql", "--uri", "postgres://localhost:port"]);
+        let config = Config::default();
+        let rules = LintRules::default();
+        let pools = Pools::default();
+        let progress = Progress::default();
+        let results: Vec<_> = verify_dir(
+            files.clone(),
+            &opts,
+            &config,
+            &rules,
+            &pools,
+            &RunState::default(),
+            &progress,
+        )
+        .collect()
+        .await;
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        assert_eq!(results.len(), 4);
+        assert!(results.iter().all(|file| match &file.result {
+            Err(err) if file.path == files[1] => err.to_string().ends_with("has no file name"),
+            Err(err) => err.to_string().starts_with("could not find message"),
+            Ok(_) => false,
+        }));
+        let mut paths: Vec<_> = results.into_iter().map(|file| file.path).collect();
+        paths.sort();
+        files.sort();
+        assert_eq!(paths, files);
+    }
+
+    #[tokio::test]
+    async fn test_verify_dir_jobs() {
+        let dir = std::env::temp_dir().join(format!("protosql-jobs-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let mut files = Vec::new();
+        for (name, proto) in [
+            ("users", "message Users { int64 id = 1; }"),
+            ("orders", "message Orders { int64 id = 1; }"),
+            ("invoices", "message Invoices { string id = 1; }"),
+            ("refunds", "message Other { int64 id = 1; }"),
+            ("coupons", "message Coupons { int64 id = 1; }"),
+        ] {
+            let path = dir.join(format!("{}.proto", name));
+            tokio::fs::write(&path, format!("syntax = \"proto3\"; {}", proto))
+                .await
+                .unwrap();
+            files.push(path);
+        }
+
+        let opts = Protosql::parse_from(["protosql", "--schema-sql", "schema.sql", "--jobs", "2"]);
+        let config = Config::default();
+        let rules = LintRules::default();
+        let pools = Pools::default();
+        let state = RunState {
+            snapshot: Some(SqlSchema::parse(
+                "CREATE TABLE public.users (id bigint NOT NULL);
+                CREATE TABLE public.orders (id bigint NOT NULL);
+                CREATE TABLE public.invoices (id bigint NOT NULL);
+                CREATE TABLE public.refunds (id bigint NOT NULL);
+                CREATE TABLE public.coupons (id bigint NOT NULL);",
+            )),
+            ..Default::default()
+        };
+        let progress = Progress::default();
+        let results: Vec<_> = verify_dir(files, &opts, &config, &rules, &pools, &state, &progress)
+            .collect()
+            .await;
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        let mut results: BTreeMap<_, _> = results
+            .into_iter()
+            .map(|file| {
+                let name = file.path.file_stem().unwrap().to_string_lossy().to_string();
+                (name, file.result.map(|verified| verified.mismatches))
+            })
+            .collect();
+        assert_eq!(results.len(), 5);
+        assert!(matches!(
+            results.remove("refunds").unwrap(),
+            Err(err) if err.to_string().starts_with("could not find message")
+        ));
+        let mismatches: Vec<_> = results
+            .into_iter()
+            .map(|(name, result)| (name, result.unwrap()))
+            .collect();
+        assert_eq!(
+            mismatches,
+            vec![
+                ("coupons".to_string(), 0),
+                ("invoices".to_string(), 1),
+                ("orders".to_string(), 0),
+                ("users".to_string(), 0),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_schema_sql() {
+        let dir = std::env::temp_dir().join(format!("protosql-schema-sql-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join("users.proto");
+        tokio::fs::write(
+            &path,
+            r#"syntax = "proto3";
+            message Users {
+                int64 id = 1;
+                string name = 2;
+                repeated string tags = 3;
+            }"#,
+        )
+        .await
+        .unwrap();
+
+        let opts = Protosql::parse_from(["protosql", "--schema-sql", "schema.sql"]);
+        let config = Config::default();
+        let rules = LintRules::default();
+        let pools = Pools::default();
+        let mut results = Vec::new();
+        for sql in [
+            "CREATE TABLE public.users (
+                id bigint NOT NULL,
+                name character varying NOT NULL,
+                tags character varying[] NOT NULL
+            );",
+            "CREATE TABLE public.users (
+                id integer NOT NULL,
+                name character varying NOT NULL,
+                tags character varying[] NOT NULL
+            );",
+            "CREATE TABLE public.orders (id bigint NOT NULL);",
+            // Partitions have no column list of their own and are validated through the parent
+            "CREATE TABLE public.users (
+                id bigint NOT NULL,
+                name character varying NOT NULL,
+                tags character varying[] NOT NULL
+            ) PARTITION BY RANGE (id);
+            CREATE TABLE public.users_1 PARTITION OF public.users FOR VALUES FROM (0) TO (1000);",
+        ] {
+            let state = RunState {
+                snapshot: Some(SqlSchema::parse(sql)),
+                ..Default::default()
+            };
+            let result = verify_file(&path, &opts, &config, &rules, &pools, &state).await;
+            results.push(result.map(|verified| verified.mismatches));
+        }
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        assert_eq!(results[0].as_ref().unwrap(), &0);
+        assert_eq!(results[1].as_ref().unwrap(), &1);
+        assert!(results[2].is_err());
+        assert_eq!(results[3].as_ref().unwrap(), &0);
+    }
+
+    #[tokio::test]
+    async fn test_nested_table_baseline() {
+        let dir =
+            std::env::temp_dir().join(format!("protosql-nested-baseline-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join("users.proto");
+        tokio::fs::write(
+            &path,
+            r#"syntax = "proto3";
+            message Users {
+                int64 id = 1;
+                message Address { string city = 1; }
+            }"#,
+        )
+        .await
+        .unwrap();
+
+        let opts =
+            Protosql::parse_from(["protosql", "--schema-sql", "schema.sql", "--include-nested"]);
+        let config = Config::default();
+        let rules = LintRules::default();
+        let pools = Pools::default();
+        // The nested message has no table
+        let snapshot = || {
+            Some(SqlSchema::parse(
+                "CREATE TABLE public.users (id bigint NOT NULL);",
+            ))
+        };
+        let state = RunState {
+            snapshot: snapshot(),
+            ..Default::default()
+        };
+        let verified = verify_file(&path, &opts, &config, &rules, &pools, &state)
+            .await
+            .unwrap();
+        assert_eq!(verified.mismatches, 1);
+        assert_eq!(
+            state.baseline.found(),
+            vec!["Users.Address: nested message 'Users.Address': table public.users_address is not in the schema file"]
+        );
+
+        let state = RunState {
+            snapshot: snapshot(),
+            baseline: Baseline::parse(&serde_json::to_string(&state.baseline.found()).unwrap())
+                .unwrap(),
+        };
+        let verified = verify_file(&path, &opts, &config, &rules, &pools, &state)
+            .await
+            .unwrap();
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+        assert_eq!(verified.mismatches, 0);
+    }
+
+    #[tokio::test]
+    async fn test_table_schemas() {
+        let dir =
+            std::env::temp_dir().join(format!("protosql-table-schemas-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        for (file, message) in [("users", "Users"), ("invoices", "Invoices")] {
+            let proto = format!(
+                "syntax = \"proto3\"; message {} {{ int64 id = 1; }}",
+                message
+            );
+            tokio::fs::write(dir.join(format!("{}.proto", file)), proto)
+                .await
+                .unwrap();
+        }
+
+        let opts = Protosql::parse_from(["protosql", "--schema-sql", "schema.sql"]);
+        let config = Config::parse(
+            r#"
+            [tables.users]
+            schema = "accounts"
+
+            [tables.invoices]
+            schema = "billing"
+            "#,
+        )
+        .unwrap();
+        let state = RunState {
+            snapshot: Some(SqlSchema::parse(
+                "CREATE TABLE accounts.users (id bigint NOT NULL);
+                CREATE TABLE billing.invoices (id bigint NOT NULL);
+                CREATE TABLE public.invoices (id integer NOT NULL);",
+            )),
+            ..Default::default()
+        };
+        let rules = LintRules::default();
+        let pools = Pools::default();
+        let mut results = Vec::new();
+        for file in ["users.proto", "invoices.proto"] {
+            let path = dir.join(file);
+            let verified = verify_file(&path, &opts, &config, &rules, &pools, &state)
+                .await
+                .unwrap();
+            results.push((verified.schema, verified.table, verified.mismatches));
+        }
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        assert_eq!(
+            results,
+            vec![
+                ("accounts".to_string(), "users".to_string(), 0),
+                ("billing".to_string(), "invoices".to_string(), 0),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_message_matching_table() {
+        let dir =
+            std::env::temp_dir().join(format!("protosql-message-match-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join("user_service.proto");
+        tokio::fs::write(
+            &path,
+            r#"syntax = "proto3";
+            message GetUserRequest { int64 id = 1; }
+            message UserRole { int64 id = 1; }"#,
+        )
+        .await
+        .unwrap();
+
+        let opts = Protosql::parse_from(["protosql", "--schema-sql", "schema.sql"]);
+        let state = RunState {
+            snapshot: Some(SqlSchema::parse(
+                "CREATE TABLE public.user_role (id bigint NOT NULL);
+                CREATE TABLE public.accounts (id bigint NOT NULL);",
+            )),
+            ..Default::default()
+        };
+        let config = Config::default();
+        let rules = LintRules::default();
+        let pools = Pools::default();
+        let verified = verify_file(&path, &opts, &config, &rules, &pools, &state).await;
+
+        // Tables are looked up in the schema configured for them
+        let config = Config::parse(
+            r#"
+            [tables.user_role]
+            schema = "accounts"
+            "#,
+        )
+        .unwrap();
+        let state = RunState {
+            snapshot: Some(SqlSchema::parse(
+                "CREATE TABLE accounts.user_role (id bigint NOT NULL);",
+            )),
+            ..Default::default()
+        };
+        let configured = verify_file(&path, &opts, &config, &rules, &pools, &state).await;
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        let verified = verified.unwrap();
+        assert_eq!(verified.table, "user_role");
+        assert_eq!(verified.mismatches, 0);
+        let configured = configured.unwrap();
+        assert_eq!(
+            (configured.schema.as_str(), configured.table.as_str()),
+            ("accounts", "user_role")
+        );
+
+        let proto = parse_complete("message UserRole {} message Team {}").unwrap();
+        assert_eq!(
+            message_for_tables(&proto, &["team".to_string(), "users".to_string()]),
+            Some(("Team".to_string(), "team".to_string()))
+        );
+        assert_eq!(message_for_tables(&proto, &["users".to_string()]), None);
+    }
+
+    #[test]
+    fn test_edition_field_presence() {
+        let proto = parse_complete(
+            r#"edition = "2023";
+            option features.field_presence = IMPLICIT;
+            message Account {
+                int64 id = 1;
+                string nickname = 2 [features.field_presence = EXPLICIT];
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Account").unwrap();
+        let opts = opts(&[]);
+        let resolver = TypeResolver::default();
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Account");
+
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("nickname", ColumnType::Varchar(Default::default()), false),
+        ];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 0);
+
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("nickname", ColumnType::Varchar(Default::default()), true),
+        ];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 1);
+    }
+
+    #[test]
+    fn test_proto3_field_presence() {
+        let proto = parse_complete(
+            r#"syntax = "proto3";
+            message Account {
+                int64 id = 1;
+                optional string nickname = 2;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Account").unwrap();
+        let opts = opts(&[]);
+        let resolver = TypeResolver::default();
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Account");
+        let verify = |id_not_null, nickname_not_null| {
+            let columns = vec![
+                column("id", ColumnType::BigInt, id_not_null),
+                column(
+                    "nickname",
+                    ColumnType::Varchar(Default::default()),
+                    nickname_not_null,
+                ),
+            ];
+            verify_message_with_columns(&message, &columns, &ctx)
+        };
+
+        assert_eq!(verify(true, false), 0);
+        // Implicit presence fields are never unset, so their columns must be NOT NULL
+        assert_eq!(verify(false, false), 1);
+        assert_eq!(verify(true, true), 1);
+        assert_eq!(verify(false, true), 2);
+
+        // Message fields have explicit presence without `optional`
+        let proto = parse_complete(
+            r#"syntax = "proto3";
+            message Account {
+                google.protobuf.Timestamp deleted_at = 1;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Account").unwrap();
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Account");
+        let verify = |not_null| {
+            let columns = vec![column(
+                "deleted_at",
+                ColumnType::TimestampWithTimeZone(Default::default()),
+                not_null,
+            )];
+            verify_message_with_columns(&message, &columns, &ctx)
+        };
+        assert_eq!(verify(false), 0);
+        assert_eq!(verify(true), 1);
+    }
+
+    #[test]
+    fn test_message_field_presence() {
+        let mut resolver = TypeResolver::default();
+        let imported =
+            parse_complete("package shop; message Address { string city = 1; }").unwrap();
+        resolver.add_proto(&imported);
+        let proto = parse_complete(
+            r#"syntax = "proto3";
+            package shop;
+            message Account {
+                google.protobuf.Timestamp created_at = 1;
+                Profile profile = 2;
+                Address address = 3;
+                Status status = 4;
+            }
+            message Profile { string bio = 1; }
+            enum Status { STATUS_UNKNOWN = 0; }"#,
+        )
+        .unwrap();
+        resolver.add_proto(&proto);
+        let message = find_proto_message(&proto, "Account").unwrap();
+        let opts = opts(&[]);
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "shop.Account");
+        let columns = |not_null| {
+            vec![
+                column(
+                    "created_at",
+                    ColumnType::TimestampWithTimeZone(Default::default()),
+                    not_null,
+                ),
+                column(
+                    "profile",
+                    ColumnType::Unknown("jsonb".to_string()),
+                    not_null,
+                ),
+                column(
+                    "address",
+                    ColumnType::Unknown("jsonb".to_string()),
+                    not_null,
+                ),
+                column("status", ColumnType::Integer, true),
+            ]
+        };
+
+        // Message fields can be unset even without a label, so their columns must be NULL
+        assert_eq!(
+            verify_message_with_columns(&message, &columns(false), &ctx),
+            0
+        );
+        assert_eq!(
+            verify_message_with_columns(&message, &columns(true), &ctx),
+            3
+        );
+    }
+
+    #[test]
+    fn test_custom_type_checker() {
+        /// Stores every string in a `text` column
+        struct TextChecker;
+
+        impl TypeChecker for TextChecker {
+            fn is_compatible(&self, field: &FieldType, col: &ColumnType) -> Compatibility {
+                match (field, col) {
+                    (FieldType::String, ColumnType::Text) => Compatibility::Compatible,
+                    (FieldType::String, _) => Compatibility::Incompatible,
+                    (field, col) => types::PostgresTypeChecker.is_compatible(field, col),
+                }
+            }
+        }
+
+        let proto = parse_complete(
+            r#"syntax = "proto3";
+            message Note {
+                int64 id = 1;
+                string body = 2;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Note").unwrap();
+        let opts = opts(&[]);
+        let resolver = TypeResolver::default();
+        let verify = |checker: &dyn TypeChecker, body: ColumnType| {
+            let ctx = VerifyContext {
+                checker,
+                ..VerifyContext::for_test(&opts, &proto, &resolver, "Note")
+            };
+            let columns = vec![
+                column("id", ColumnType::BigInt, true),
+                column("body", body, true),
+            ];
+            verify_message_with_columns(&message, &columns, &ctx)
+        };
+        let varchar = || ColumnType::Varchar(Default::default());
+
+        assert_eq!(verify(&types::PostgresTypeChecker, ColumnType::Text), 1);
+        assert_eq!(verify(&types::PostgresTypeChecker, varchar()), 0);
+        assert_eq!(verify(&TextChecker, ColumnType::Text), 0);
+        assert_eq!(verify(&TextChecker, varchar()), 1);
+
+        // The [types] table of the config file adds column types on top of the built-in ones
+        let config = Config::parse("[types]\nstring = [\"text\"]").unwrap();
+        let mapped = MappedTypeChecker::new(&config.types);
+        assert_eq!(verify(&mapped, ColumnType::Text), 0);
+        assert_eq!(verify(&mapped, varchar()), 0);
+        assert_eq!(verify(&mapped, ColumnType::Integer), 1);
+    }
+
+    #[test]
+    fn test_custom_validator() {
+        let proto = parse_complete(
+            r#"syntax = "proto3";
+            message Order {
+                int64 id = 1;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Order").unwrap();
+        let opts = opts(&["--ignore-extra-columns"]);
+        let resolver = TypeResolver::default();
+        let config = Config::parse(r#"required_columns = ["created_at"]"#).unwrap();
+        let validators = validator::from_config(&config);
+        let ctx = VerifyContext {
+            validators: &validators,
+            ..VerifyContext::for_test(&opts, &proto, &resolver, "Order")
+        };
+
+        let columns = vec![column("id", ColumnType::BigInt, true)];
+        let verification = verify_columns(&message, &columns, &ctx);
+        assert_eq!(verification.mismatches, 1);
+        assert!(verification.problem_pairs.contains("created_at"));
+
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column(
+                "created_at",
+                ColumnType::TimestampWithTimeZone(Default::default()),
+                true,
+            ),
+        ];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 0);
+    }
+
+    #[test]
+    fn test_strict_optional() {
+        let proto = parse_complete(
+            r#"syntax = "proto3";
+            message Account {
+                int64 id = 1;
+                optional string nickname = 2;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Account").unwrap();
+        let resolver = TypeResolver::default();
+        let fails_unset_insert = |args: &[&str], field: usize, column: &ColumnInfo| {
+            let opts = opts(args);
+            let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Account");
+            ctx.fails_unset_insert(&message, &message.fields[field], column)
+        };
+        let not_null = column("nickname", ColumnType::Varchar(Default::default()), true);
+        let with_default = ColumnInfo {
+            default: Some(ColumnExpression("''::character varying".to_string())),
+            ..not_null.clone()
+        };
+        let nullable = column("nickname", ColumnType::Varchar(Default::default()), false);
+
+        assert!(fails_unset_insert(&["--strict-optional"], 1, &not_null));
+        assert!(!fails_unset_insert(&[], 1, &not_null));
+        assert!(!fails_unset_insert(
+            &["--strict-optional"],
+            1,
+            &with_default
+        ));
+        assert!(!fails_unset_insert(&["--strict-optional"], 1, &nullable));
+        // Fields without a label are never unset in proto3
+        let id = column("id", ColumnType::BigInt, true);
+        assert!(!fails_unset_insert(&["--strict-optional"], 0, &id));
+
+        // The failing insert is a mismatch of its own, which the baselined nullability warning
+        // doesn't cover
+        let baseline = Baseline::parse(
+            r#"["Account: field 'nickname' is marked as NOT NULL in database, but should be NULL"]"#,
+        )
+        .unwrap();
+        let columns = [id, not_null];
+        let mismatches = |args: &[&str]| {
+            let opts = opts(args);
+            let ctx = VerifyContext {
+                baseline: &baseline,
+                ..VerifyContext::for_test(&opts, &proto, &resolver, "Account")
+            };
+            verify_message_with_columns(&message, &columns, &ctx)
+        };
+        assert_eq!(mismatches(&[]), 0);
+        assert_eq!(mismatches(&["--strict-optional"]), 1);
+    }
+
+    #[test]
+    fn test_tag_gap_warning() {
+        let proto = parse_complete(
+            r#"message User {
+                int64 id = 1;
+                string name = 2;
+                string email = 4;
+                string phone = 8;
+            }
+            message Team {
+                int64 id = 1;
+                string name = 2;
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            tag_gap_warning(&find_proto_message(&proto, "User").unwrap()).unwrap(),
+            "message 'User' skips field numbers 3, 5 to 7 without reserving them"
+        );
+        assert_eq!(
+            tag_gap_warning(&find_proto_message(&proto, "Team").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tag_order_warning() {
+        let proto = parse_complete(
+            r#"message User {
+                int64 id = 1;
+                string email = 3;
+                string name = 2;
+                oneof contact {
+                    string phone = 5;
+                    string fax = 4;
+                }
+                string note = 6;
+            }
+            message Team {
+                int64 id = 1;
+                oneof owner {
+                    int64 user_id = 2;
+                }
+                string name = 3;
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            tag_order_warning(&find_proto_message(&proto, "User").unwrap()).unwrap(),
+            "message 'User' declares fields out of number order: 'name' = 2 after 'email' = 3, 'fax' = 4 after 'phone' = 5"
+        );
+        assert_eq!(
+            tag_order_warning(&find_proto_message(&proto, "Team").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tag_efficiency_warnings() {
+        let proto = parse_complete(
+            r#"message User {
+                reserved 2 to 4;
+                optional int64 id = 1;
+                optional string name = 20;
+                optional Address address = 21;
+                optional string nickname = 22 [deprecated = true];
+            }
+            message Team {
+                reserved 2 to 15;
+                optional int64 id = 1;
+                optional string name = 16;
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            tag_efficiency_warnings(&find_proto_message(&proto, "User").unwrap()),
+            vec!["field 'name' uses number 20, which takes two bytes to encode, while 5 to 15 are free"]
+        );
+        assert!(tag_efficiency_warnings(&find_proto_message(&proto, "Team").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_relations() {
+        let proto = parse_complete(
+            r#"syntax = "proto3";
+            message User {
+                int64 id = 1;
+                repeated Order orders = 2;
+                repeated UserRole roles = 3;
+                repeated string tags = 4;
+                Order last_order = 5;
+            }
+            message Order {}
+            message UserRole {}"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "User").unwrap();
+        let mut resolver = TypeResolver::default();
+        resolver.add_proto(&proto);
+        let opts = opts(&["--check-relations"]);
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "User");
+
+        let children: Vec<_> = child_tables(&ctx, &message)
+            .into_iter()
+            .map(|(field, table)| (field.name.as_ref().to_string(), table))
+            .collect();
+        assert_eq!(
+            children,
+            vec![
+                ("orders".to_string(), "order".to_string()),
+                ("roles".to_string(), "user_role".to_string()),
+            ]
+        );
+
+        let field = message.field_by_name("orders").unwrap();
+        let foreign_key = |schema: &str, table: &str| ForeignKey {
+            name: format!("order_{}_fkey", table),
+            schema: schema.to_string(),
+            table: table.to_string(),
+        };
+        let relation = |foreign_keys: Option<&[ForeignKey]>| {
+            relation_problem(field, "public.order", "public.user", foreign_keys)
+        };
+        assert_eq!(relation(Some(&[foreign_key("public", "user")])), None);
+        assert_eq!(
+            relation(Some(&[foreign_key("public", "shop")])).unwrap(),
+            "table 'public.order' of field 'orders' has no foreign key referencing table 'public.user'"
+        );
+        // A table of the same name in another schema is a different table
+        assert_eq!(
+            relation(Some(&[foreign_key("archive", "user")])).unwrap(),
+            "table 'public.order' of field 'orders' has no foreign key referencing table 'public.user'"
+        );
+        assert_eq!(
+            relation(None).unwrap(),
+            "field 'orders' is stored in table 'public.order', which does not exist"
+        );
+
+        // The child tables replace the columns
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("tags", ColumnType::Array, true),
+            column(
+                "last_order",
+                ColumnType::Unknown("jsonb".to_string()),
+                false,
+            ),
+        ];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 0);
+    }
+
+    #[test]
+    fn test_index_problems() {
+        let proto = parse_complete(
+            r#"syntax = "proto3";
+            message User {
+                int64 id = 1;
+                string email = 2 [(db).indexed = true];
+                int64 team_id = 3 [(db).indexed = true];
+                string name = 4;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "User").unwrap();
+        let index = |columns: &[Option<&str>], primary| TableIndex {
+            columns: columns
+                .iter()
+                .map(|column| column.map(str::to_string))
+                .collect(),
+            primary,
+        };
+        let indexes = vec![
+            index(&[Some("id")], true),
+            index(&[Some("email")], false),
+            // Only covers team_id after name
+            index(&[Some("name"), Some("team_id")], false),
+            // Only covers team_id after an expression, such as `(lower(email), team_id)`
+            index(&[None, Some("team_id")], false),
+        ];
+
+        assert_eq!(
+            index_problems(&message, &indexes, false),
+            vec!["field 'team_id' is annotated as indexed, but no index starts with its column"]
+        );
+        assert_eq!(
+            index_problems(&message, &indexes, true),
+            vec![
+                "field 'team_id' is annotated as indexed, but no index starts with its column",
+                "column 'name' is indexed, but field 'name' has no (db).indexed annotation",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deprecation_warnings() {
+        let proto = parse_complete(
+            r#"syntax = "proto3";
+            package shop;
+            message Order {
+                int64 id = 1;
+                string coupon = 2 [deprecated = true];
+                string note = 3 [deprecated = true];
+                LegacyAddress address = 4;
+                map<string, LegacyAddress> addresses = 5;
+            }
+            message LegacyAddress {
+                option deprecated = true;
+                string line = 1;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Order").unwrap();
+        let opts = opts(&["--warn-deprecated"]);
+        let resolver = TypeResolver::default();
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "shop.Order");
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("coupon", ColumnType::Varchar(Default::default()), false),
+            column("note", ColumnType::Varchar(Default::default()), false),
+        ];
+        let mut comments = HashMap::new();
+        comments.insert("note".to_string(), "DEPRECATED, unused".to_string());
+
+        assert_eq!(
+            deprecation_warnings(&ctx, &message, &columns, &comments),
+            vec![
+                "deprecated field 'coupon' still backs a column which isn't marked deprecated in its comment",
+                "field 'address' uses deprecated message LegacyAddress",
+                "field 'addresses' uses deprecated message LegacyAddress",
+            ]
+        );
+
+        let legacy = find_proto_message(&proto, "LegacyAddress").unwrap();
+        assert!(deprecation_warnings(&ctx, &legacy, &[], &HashMap::new()).is_empty());
+    }
+
+    #[test]
+    fn test_assume_syntax() {
+        let source = r#"message Account {
+            optional string nickname = 1;
+        }"#;
+        let nickname = column("nickname", ColumnType::Varchar(Default::default()), true);
+        let fails_unset_insert = |args: &[&str]| {
+            let mut proto = parse_complete(source).unwrap();
+            let opts = opts(args);
+            assert_eq!(assume_syntax(&mut proto, &opts), None);
+            let message = find_proto_message(&proto, "Account").unwrap();
+            let resolver = TypeResolver::default();
+            let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Account");
+            ctx.fails_unset_insert(&message, &message.fields[0], &nickname)
+        };
+
+        // Without a declaration the file is proto2, where --strict-optional doesn't apply
+        assert!(!fails_unset_insert(&["--strict-optional"]));
+        assert!(fails_unset_insert(&[
+            "--strict-optional",
+            "--assume-syntax",
+            "proto3"
+        ]));
+
+        // Labels decide presence in both proto2 and proto3, so only editions changes whether an
+        // unlabeled field's column should be nullable
+        let source = r#"message Account {
+            string nickname = 1;
+        }"#;
+        let nullability_mismatches = |args: &[&str]| {
+            let mut proto = parse_complete(source).unwrap();
+            let opts = opts(args);
+            assume_syntax(&mut proto, &opts);
+            let message = find_proto_message(&proto, "Account").unwrap();
+            let resolver = TypeResolver::default();
+            let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Account");
+            verify_message_with_columns(&message, std::slice::from_ref(&nickname), &ctx)
+        };
+        assert_eq!(nullability_mismatches(&[]), 0);
+        assert_eq!(nullability_mismatches(&["--assume-syntax", "proto3"]), 0);
+        assert_eq!(nullability_mismatches(&["--assume-syntax", "editions"]), 1);
+
+        // A default stands in for an unset field in proto2, but proto3 has no custom defaults,
+        // so the same NOT NULL column becomes a mismatch
+        let source = r#"message Account {
+            optional string nickname = 1 [default = "anon"];
+        }"#;
+        let nickname = ColumnInfo {
+            default: Some(ColumnExpression("'anon'::character varying".to_string())),
+            ..nickname.clone()
+        };
+        let default_mismatches = |args: &[&str]| {
+            let mut proto = parse_complete(source).unwrap();
+            let opts = opts(args);
+            assume_syntax(&mut proto, &opts);
+            let message = find_proto_message(&proto, "Account").unwrap();
+            let resolver = TypeResolver::default();
+            let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Account");
+            verify_message_with_columns(&message, std::slice::from_ref(&nickname), &ctx)
+        };
+        assert_eq!(default_mismatches(&["--check-defaults"]), 0);
+        assert_eq!(
+            default_mismatches(&["--check-defaults", "--assume-syntax", "proto3"]),
+            1
+        );
+
+        let mut proto = parse_complete(r#"syntax = "proto2"; message Account {}"#).unwrap();
+        let warning = assume_syntax(&mut proto, &opts(&["--assume-syntax", "proto3"]));
+        assert_eq!(
+            warning.unwrap(),
+            "file declares Proto2 syntax, but is validated as Proto3 because of --assume-syntax"
+        );
+        assert_eq!(proto.syntax, Syntax::Proto3);
+    }
+
+    #[test]
+    fn test_map_columns() {
+        let proto = parse_complete(
+            r#"message Customer {
+                map<string, int32> scores = 1;
+                map<string, Address> addresses = 2;
+                map<string, Missing> others = 3;
+                message Address {
+                    string city = 1;
+                }
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Customer").unwrap();
+        let opts = opts(&[]);
+        let mut resolver = TypeResolver::default();
+        resolver.add_proto(&proto);
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Customer");
+        let jsonb = || ColumnType::Unknown("jsonb".to_string());
+
+        let columns = vec![
+            column("scores", jsonb(), true),
+            column("addresses", jsonb(), true),
+            column("others", jsonb(), true),
+        ];
+        // Only the map of an undeclared message is rejected
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 1);
+
+        let columns = vec![
+            column("scores", ColumnType::Integer, true),
+            column("addresses", ColumnType::Text, true),
+            column("others", jsonb(), true),
+        ];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 3);
+    }
+
+    #[test]
+    fn test_check_collation() {
+        let proto = parse_complete(
+            r#"message User {
+                string email = 1;
+                string name = 2;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "User").unwrap();
+        let resolver = TypeResolver::default();
+        let collation = |name: &str, deterministic| ColumnCollation {
+            name: name.to_string(),
+            deterministic,
+        };
+        let verify = |args: &[&str]| {
+            let opts = opts(args);
+            let ctx = VerifyContext {
+                collations: vec![
+                    ("email".to_string(), collation("case_insensitive", false)),
+                    ("name".to_string(), collation("default", true)),
+                ]
+                .into_iter()
+                .collect(),
+                ..VerifyContext::for_test(&opts, &proto, &resolver, "User")
+            };
+            let columns = vec![
+                column("email", ColumnType::Varchar(Default::default()), true),
+                column("name", ColumnType::Varchar(Default::default()), true),
+            ];
+            verify_message_with_columns(&message, &columns, &ctx)
+        };
+
+        assert_eq!(verify(&[]), 0);
+        assert_eq!(verify(&["--check-collation"]), 1);
+        assert_eq!(
+            verify(&["--check-collation", "--expected-collation", "default"]),
+            1
+        );
+        assert_eq!(
+            verify(&["--check-collation", "--expected-collation", "C"]),
+            2
+        );
+    }
+
+    #[test]
+    fn test_relation_fields() {
+        let proto = parse_complete(
+            r#"message Order {
+                int64 id = 1;
+                repeated Product products = 2;
+            }
+            message Product {
+                int64 id = 1;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Order").unwrap();
+        let mut resolver = TypeResolver::default();
+        resolver.add_proto(&proto);
+        let columns = vec![column("id", ColumnType::BigInt, true)];
+        let verify = |args: &[&str]| {
+            let opts = opts(args);
+            let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Order");
+            verify_message_with_columns(&message, &columns, &ctx)
+        };
+
+        assert_eq!(verify(&[]), 1);
+        assert_eq!(verify(&["--relation-fields", "products"]), 0);
+        assert_eq!(verify(&["--relation-fields", "Order.products"]), 0);
+
+        let mut summary = Summary::default();
+        summary.add(&VerifiedFile {
+            uri: None,
+            schema: "public".to_string(),
+            table: "orders".to_string(),
+            mismatches: 0,
+            relations: 1,
+            pairs: 0,
+            matched: 0,
+        });
+        assert_eq!(
+            summary.to_string(),
+            "1 files, 1 passed, 0 failed, 0 mismatches, relations: 1"
+        );
+    }
+
+    #[test]
+    fn test_primary_key_problems() {
+        let proto = parse_complete(
+            r#"message OrderLine {
+                int64 order_id = 1;
+                int32 line = 2;
+                string sku = 3;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "OrderLine").unwrap();
+        let key = |columns: &[&str]| -> Vec<String> {
+            columns.iter().map(|column| column.to_string()).collect()
+        };
+
+        assert!(primary_key_problems(&message, &key(&["order_id", "line"])).is_empty());
+        assert!(primary_key_problems(&message, &[]).is_empty());
+        assert_eq!(
+            primary_key_problems(&message, &key(&["order_id", "position"])),
+            vec!["primary key column 'position' has no field"]
+        );
+        assert_eq!(
+            primary_key_problems(&message, &key(&["line", "order_id"])),
+            vec!["primary key fields are declared as (order_id, line), but the key is (line, order_id)"]
+        );
+    }
+
+    #[test]
+    fn test_id_column_problem() {
+        let proto = parse_complete(
+            r#"message User {
+                int64 id = 1;
+                string name = 2;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "User").unwrap();
+
+        let serial = ColumnInfo {
+            default: Some(ColumnExpression(
+                "nextval('users_id_seq'::regclass)".to_string(),
+            )),
+            ..column("id", ColumnType::BigInt, true)
+        };
+        assert_eq!(id_column_problem(&message, &[serial], "id"), None);
+        let identity = ColumnInfo {
+            is_identity: true,
+            ..column("id", ColumnType::BigInt, true)
+        };
+        assert_eq!(id_column_problem(&message, &[identity], "id"), None);
+
+        let plain = column("id", ColumnType::BigInt, true);
+        assert_eq!(
+            id_column_problem(&message, std::slice::from_ref(&plain), "id"),
+            Some(
+                "id column 'id' has no default, sequence or identity, so clients must supply ids"
+                    .to_string()
+            )
+        );
+        let constant = ColumnInfo {
+            default: Some(ColumnExpression("0".to_string())),
+            ..plain.clone()
+        };
+        assert_eq!(
+            id_column_problem(&message, &[constant], "id"),
+            Some(
+                "id column 'id' defaults to 0, which is not a sequence, so clients must supply ids"
+                    .to_string()
+            )
+        );
+        assert_eq!(id_column_problem(&message, &[plain], "user_id"), None);
+        let name = column("name", ColumnType::Text, true);
+        assert_eq!(id_column_problem(&message, &[name], "name"), None);
+    }
+
+    #[test]
+    fn test_nested_tables() {
+        let proto = parse_complete(
+            r#"message User {
+                int64 id = 1;
+                message Address {
+                    string city = 1;
+                    message GeoPoint {
+                        double lat = 1;
+                    }
+                }
+            }
+            message Other {
+                message Address {}
+            }"#,
+        )
+        .unwrap();
+        let tables: Vec<_> = nested_tables(&proto, "User")
+            .into_iter()
+            .map(|(name, message, table)| (name, message.name.as_ref().unwrap().as_ref(), table))
+            .collect();
+        assert_eq!(
+            tables,
+            vec![
+                (
+                    "User.Address".to_string(),
+                    "Address",
+                    "user_address".to_string()
+                ),
+                (
+                    "User.Address.GeoPoint".to_string(),
+                    "GeoPoint",
+                    "user_address_geo_point".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_epoch_fields() {
+        let proto = parse_complete(
+            r#"message Event {
+                int64 id = 1;
+                int64 created_at = 2;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Event").unwrap();
+        let resolver = TypeResolver::default();
+        let verify = |args: &[&str], created_at: ColumnType| {
+            let opts = opts(args);
+            let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Event");
+            let columns = vec![
+                column("id", ColumnType::BigInt, true),
+                column("created_at", created_at, true),
+            ];
+            verify_message_with_columns(&message, &columns, &ctx)
+        };
+        let timestamptz = || ColumnType::TimestampWithTimeZone(Default::default());
+
+        assert_eq!(verify(&[], ColumnType::BigInt), 0);
+        assert_eq!(verify(&[], timestamptz()), 1);
+        assert_eq!(
+            verify(&["--epoch-fields", "created_at"], ColumnType::BigInt),
+            0
+        );
+        assert_eq!(verify(&["--epoch-fields", "created_at"], timestamptz()), 0);
+        assert_eq!(verify(&["--epoch-fields", "Event.*_at"], timestamptz()), 0);
+        assert_eq!(verify(&["--epoch-fields", "User.*_at"], timestamptz()), 1);
+        assert_eq!(verify(&["--epoch-fields", "*_at"], ColumnType::Text), 1);
+        // The convention only warns
+        assert_eq!(
+            verify(
+                &["--epoch-fields", "*_at", "--epoch-column-type", "bigint"],
+                timestamptz()
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn test_check_defaults() {
+        let proto = parse_complete(
+            r#"syntax = "proto2";
+            message Job {
+                optional int32 retries = 1 [default = 5];
+                optional string queue = 2 [default = "main"];
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Job").unwrap();
+        let resolver = TypeResolver::default();
+        let verify = |args: &[&str], retries: Option<&str>| {
+            let opts = opts(args);
+            let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Job");
+            let columns = vec![
+                ColumnInfo {
+                    default: retries.map(|expr| ColumnExpression(expr.to_string())),
+                    ..column("retries", ColumnType::Integer, true)
+                },
+                ColumnInfo {
+                    default: Some(ColumnExpression("'main'::character varying".to_string())),
+                    ..column("queue", ColumnType::Varchar(Default::default()), true)
+                },
+            ];
+            verify_message_with_columns(&message, &columns, &ctx)
+        };
+
+        assert_eq!(verify(&["--check-defaults"], Some("5")), 0);
+        assert_eq!(verify(&["--check-defaults"], Some("'5'::integer")), 0);
+        assert_eq!(verify(&["--check-defaults"], Some("3")), 2);
+        assert_eq!(verify(&["--check-defaults"], None), 2);
+        // Without the check, NOT NULL columns for optional fields are still reported
+        assert_eq!(verify(&[], Some("5")), 2);
+    }
+
+    #[test]
+    fn test_enum_arrays() {
+        let proto = parse_complete(
+            r#"enum Status { ACTIVE = 0; }
+            message Ticket {
+                repeated Status statuses = 1;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Ticket").unwrap();
+        let mut resolver = TypeResolver::default();
+        resolver.add_proto(&proto);
+        let opts = opts(&[]);
+        let columns = vec![column("statuses", ColumnType::Array, true)];
+        let verify = |element: Option<&str>| {
+            let ctx = VerifyContext {
+                array_elements: element
+                    .map(|element| ("statuses".to_string(), element.to_string()))
+                    .into_iter()
+                    .collect(),
+                ..VerifyContext::for_test(&opts, &proto, &resolver, "Ticket")
+            };
+            verify_message_with_columns(&message, &columns, &ctx)
+        };
+
+        assert_eq!(verify(None), 0);
+        assert_eq!(verify(Some("status")), 0);
+        assert_eq!(verify(Some("integer")), 0);
+        assert_eq!(verify(Some("character varying")), 0);
+        assert_eq!(verify(Some("text")), 0);
+        assert_eq!(verify(Some("role")), 1);
+        assert_eq!(verify(Some("boolean")), 1);
+    }
+
+    #[test]
+    fn test_enum_types() {
+        let proto = parse_complete(
+            r#"enum MyStatus { ACTIVE = 0; }
+            message Ticket {
+                MyStatus status = 1;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Ticket").unwrap();
+        let mut resolver = TypeResolver::default();
+        resolver.add_proto(&proto);
+        let opts = opts(&[]);
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Ticket");
+        let verify = |col_type: ColumnType| {
+            let columns = vec![column("status", col_type, true)];
+            verify_message_with_columns(&message, &columns, &ctx)
+        };
+
+        assert_eq!(verify(ColumnType::Integer), 0);
+        assert_eq!(verify(ColumnType::Text), 0);
+        assert_eq!(verify(ColumnType::Unknown("my_status".to_string())), 0);
+        assert_eq!(verify(ColumnType::Unknown("role".to_string())), 1);
+        assert_eq!(verify(ColumnType::Boolean), 1);
+    }
+
+    #[test]
+    fn test_orphan_tables() {
+        let tables = vec![
+            "users".to_string(),
+            "orders".to_string(),
+            "audit_log".to_string(),
+        ];
+        let covered = ["users", "orders"]
+            .iter()
+            .map(|table| table.to_string())
+            .collect();
+        assert_eq!(orphan_tables(&tables, &covered), vec!["audit_log"]);
+    }
+
+    #[test]
+    fn test_summary_only() {
+        assert_eq!(
+            log_level(&opts(&["--summary-only", "--verbose"])),
+            LevelFilter::Off
+        );
+        assert_eq!(
+            log_level(&opts(&["--summary-only", "--output", "report.txt"])),
+            LevelFilter::Info
+        );
+
+        let mut summary = Summary::default();
+        for mismatches in [0, 3, 0] {
+            summary.add(&VerifiedFile {
+                uri: None,
+                schema: "public".to_string(),
+                table: "users".to_string(),
+                mismatches,
+                relations: 0,
+                pairs: 0,
+                matched: 0,
+            });
+        }
+        assert!(!summary.is_valid());
+        assert_eq!(
+            summary.to_string(),
+            "3 files, 2 passed, 1 failed, 3 mismatches"
+        );
+    }
+
+    #[test]
+    fn test_baseline_mismatches() {
+        let proto = parse_complete(
+            r#"syntax = "proto3";
+            message User {
+                int64 id = 1;
+                string name = 2;
+                string email = 3;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "User").unwrap();
+        let opts = opts(&[]);
+        let resolver = TypeResolver::default();
+        let baseline = Baseline::parse(
+            r#"["User: field 'name' has type 'String' which not match database type 'Integer'"]"#,
+        )
+        .unwrap();
+        let ctx = VerifyContext {
+            baseline: &baseline,
+            ..VerifyContext::for_test(&opts, &proto, &resolver, "User")
+        };
+
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("name", ColumnType::Integer, true),
+            column("email", ColumnType::Varchar(Default::default()), true),
+        ];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 0);
+
+        // email is missing, which isn't in the baseline
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("name", ColumnType::Integer, true),
+        ];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 1);
+        assert_eq!(baseline.found().len(), 2);
+    }
+
+    #[test]
+    fn test_exact_problem() {
+        let proto = parse_complete(
+            r#"syntax = "proto3";
+            message User {
+                int64 id = 1;
+                string name = 2;
+                string email = 3;
+                repeated int64 team_ids = 4;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "User").unwrap();
+        let opts = opts(&["--exact", "--relation-fields", "team_ids"]);
+        let resolver = TypeResolver::default();
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "User");
+        let varchar = || ColumnType::Varchar(Default::default());
+
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("name", varchar(), true),
+            column("email", varchar(), true),
+        ];
+        assert_eq!(
+            exact_problem(&message, &columns, &HashSet::new(), &ctx),
+            None
+        );
+
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("name", varchar(), true),
+            column("created_at", ColumnType::Integer, true),
+        ];
+        assert_eq!(
+            exact_problem(&message, &columns, &HashSet::new(), &ctx).unwrap(),
+            "fields and columns don't match exactly, fields without a column: email, columns without a field: created_at"
+        );
+
+        // Columns with a mismatch of their own are counted once
+        let verification = verify_columns(&message, &columns, &ctx);
+        assert_eq!(verification.mismatches, 2);
+        assert_eq!(
+            exact_problem(&message, &columns, &verification.problem_pairs, &ctx),
+            None
+        );
+    }
+
+    #[test]
+    fn test_coverage() {
+        let mut summary = Summary::default();
+        assert_eq!(summary.coverage(), None);
+        for (pairs, matched) in [(10, 10), (8, 5), (2, 2)] {
+            summary.add(&VerifiedFile {
+                uri: None,
+                schema: "public".to_string(),
+                table: "users".to_string(),
+                mismatches: pairs - matched,
+                relations: 0,
+                pairs,
+                matched,
+            });
+        }
+        assert_eq!(summary.coverage(), Some(85.0));
+        assert_eq!(
+            summary.to_string(),
+            "3 files, 2 passed, 1 failed, 3 mismatches, coverage: 85.0%"
+        );
+        let json = summary_line(&opts(&["--summary-only", "--format", "json"]), &summary);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json).unwrap()["coverage"],
+            85.0
+        );
+
+        let proto = parse_complete(
+            r#"syntax = "proto3";
+            message User {
+                int64 id = 1;
+                string name = 2;
+                string email = 3;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "User").unwrap();
+        let opts = opts(&[]);
+        let resolver = TypeResolver::default();
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "User");
+        // id and name match, email has no column and created_at has no field
+        let columns = vec![
+            column("id", ColumnType::BigInt, true),
+            column("name", ColumnType::Varchar(Default::default()), true),
+            column("created_at", ColumnType::Integer, true),
+        ];
+        assert_eq!(field_column_pairs(&message, &columns, &ctx), 4);
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 2);
+    }
+
+    #[test]
+    fn test_coverage_problem_pairs() {
+        // A field with two problems is still a single pair which didn't match
+        let proto = parse_complete(
+            r#"message Stock {
+                optional int32 id = 1;
+                optional int32 count = 2 [default = 5];
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Stock").unwrap();
+        let opts = opts(&["--check-defaults"]);
+        let resolver = TypeResolver::default();
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Stock");
+        let mut count = column("count", ColumnType::Integer, true);
+        count.default = Some(ColumnExpression("6".to_string()));
+        let columns = vec![column("id", ColumnType::Integer, false), count];
+        let verification = verify_columns(&message, &columns, &ctx);
+        assert_eq!(verification.mismatches, 2);
+        assert_eq!(field_column_pairs(&message, &columns, &ctx), 2);
+        assert_eq!(
+            verification.problem_pairs,
+            HashSet::from(["count".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_show_progress() {
+        assert!(show_progress(&opts(&["--dir", "protos"]), true));
+        assert!(!show_progress(&opts(&["--dir", "protos"]), false));
+        assert!(!show_progress(&opts(&["--dir", "protos", "--quiet"]), true));
+        assert!(!show_progress(
+            &opts(&["--dir", "protos", "--format", "json"]),
+            true
+        ));
+        assert!(!show_progress(
+            &opts(&["--dir", "protos", "--summary-only"]),
+            true
+        ));
+        assert!(!show_progress(&opts(&["--file", "user.proto"]), true));
+    }
+
+    #[test]
+    fn test_drift_metric() {
+        let opts = opts(&["--drift-metric"]);
+        assert_eq!(log_level(&opts), LevelFilter::Off);
+
+        let mut summary = Summary::default();
+        for mismatches in [2, 0, 5] {
+            summary.add(&VerifiedFile {
+                uri: None,
+                schema: "public".to_string(),
+                table: "users".to_string(),
+                mismatches,
+                relations: 0,
+                pairs: 0,
+                matched: 0,
+            });
+        }
+        summary.mismatches += 1;
+        assert_eq!(summary_line(&opts, &summary), "8");
+        assert_eq!(
+            summary_line(&opts, &Summary::default())
+                .parse::<usize>()
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_message_field_cardinality() {
+        let proto = parse_complete(
+            r#"message Order {
+                message Line { string sku = 1; }
+                Line first_line = 1;
+                repeated Line lines = 2;
+            }"#,
+        )
+        .unwrap();
+        let message = find_proto_message(&proto, "Order").unwrap();
+        let opts = opts(&[]);
+        let mut resolver = TypeResolver::default();
+        resolver.add_proto(&proto);
+        let ctx = VerifyContext::for_test(&opts, &proto, &resolver, "Order");
+        let jsonb = || ColumnType::Unknown("jsonb".to_string());
+
+        // A repeated message can be a jsonb array or a jsonb[] column
+        let columns = vec![
+            column("first_line", jsonb(), false),
+            column("lines", jsonb(), true),
+        ];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 0);
+        let columns = vec![
+            column("first_line", jsonb(), false),
+            column("lines", ColumnType::Array, true),
+        ];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 0);
+
+        // A single message can't be an array
+        let columns = vec![
+            column("first_line", ColumnType::Array, true),
+            column("lines", jsonb(), true),
+        ];
+        assert_eq!(verify_message_with_columns(&message, &columns, &ctx), 1);
+    }
 }
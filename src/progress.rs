@@ -0,0 +1,55 @@
+//! Progress line for validating a directory of proto files, shown on interactive terminals.
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+/// Shows how many files were verified out of the total, and the file being verified.
+///
+/// Cloned handles share the same line. A disabled progress ignores every call.
+#[derive(Clone)]
+pub struct Progress {
+    bar: ProgressBar,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress::new(false)
+    }
+}
+
+impl Progress {
+    pub fn new(enabled: bool) -> Self {
+        if !enabled {
+            return Progress {
+                bar: ProgressBar::hidden(),
+            };
+        }
+        let bar = ProgressBar::with_draw_target(Some(0), ProgressDrawTarget::stdout());
+        bar.set_style(ProgressStyle::with_template("[{pos}/{len}] {wide_msg}").unwrap());
+        Progress { bar }
+    }
+
+    pub fn set_total(&self, total: usize) {
+        self.bar.set_length(total as u64);
+    }
+
+    /// Shows the file being verified
+    pub fn start_file(&self, name: &str) {
+        self.bar.set_message(name.to_string());
+    }
+
+    /// Advances the bar after a file was verified
+    pub fn finish_file(&self) {
+        self.bar.inc(1);
+        self.bar.set_message("");
+    }
+
+    /// Clears the progress line for good, so the summary can be printed
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+
+    /// Clears the progress line while printing other output, then draws it again
+    pub fn suspend(&self, f: impl FnOnce()) {
+        self.bar.suspend(f)
+    }
+}
@@ -0,0 +1,80 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result};
+
+use crate::write_file_atomic;
+
+/// Report collected from the log output, written to a file with `--output`.
+#[derive(Clone, Default)]
+pub struct Report {
+    lines: Arc<Mutex<Vec<String>>>,
+}
+
+impl Report {
+    /// Adds a line to the report, stripping any terminal colors.
+    pub fn push_line(&self, line: &str) {
+        self.lines.lock().unwrap().push(strip_ansi(line));
+    }
+
+    pub fn contents(&self) -> String {
+        let lines = self.lines.lock().unwrap();
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+        contents
+    }
+
+    /// Writes the report, replacing any previous report in one step.
+    pub async fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        write_file_atomic(path.as_ref(), &self.contents())
+            .await
+            .context("could not write report file")
+    }
+}
+
+pub fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // Skip the escape sequence up to and including its final letter
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod test {
+    use colorful::Colorful;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_report() {
+        let report = Report::default();
+        report.push_line(&format!(
+            "{}: loaded proto file 'user.proto'",
+            "info".blue()
+        ));
+        report.push_line(&format!("{}: missing field", "warn".yellow().bold()));
+
+        let path = std::env::temp_dir().join(format!("protosql-report-{}.txt", std::process::id()));
+        report.write(&path).await.unwrap();
+
+        let contents = tokio::fs::read_to_string(&path).await.unwrap();
+        tokio::fs::remove_file(&path).await.unwrap();
+        assert_eq!(
+            contents,
+            "info: loaded proto file 'user.proto'\nwarn: missing field\n"
+        );
+    }
+}
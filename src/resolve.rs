@@ -0,0 +1,345 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use protobuf_parser::{parse_complete, AbstractProto, Message};
+
+use crate::{log::*, read_proto_dir, read_proto_file};
+
+/// Kind of a named proto type
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypeKind {
+    Message,
+    Enum,
+}
+
+/// Message and enum types which can be referenced from a proto file, including imported ones.
+#[derive(Debug, Default)]
+pub struct TypeResolver {
+    /// Type kinds, keyed by fully qualified name without the leading dot
+    types: HashMap<String, TypeKind>,
+    /// Packages of the added files, including their parent packages such as `shop` for `shop.v1`
+    packages: HashSet<String>,
+}
+
+impl TypeResolver {
+    /// Adds every message and enum declared in a proto file, including nested ones.
+    pub fn add_proto(&mut self, proto: &AbstractProto) {
+        let package = proto
+            .package
+            .as_ref()
+            .map(|package| package.as_ref())
+            .unwrap_or("");
+        let mut parent = package;
+        while !parent.is_empty() {
+            self.packages.insert(parent.to_string());
+            parent = parent.rfind('.').map(|i| &parent[..i]).unwrap_or("");
+        }
+        for message in &proto.messages {
+            self.add_message(package, message);
+        }
+        for enumeration in &proto.enums {
+            self.types
+                .insert(qualify(package, enumeration.name.as_ref()), TypeKind::Enum);
+        }
+    }
+
+    fn add_message(&mut self, scope: &str, message: &Message) {
+        let name = match &message.name {
+            Some(name) => qualify(scope, name.as_ref()),
+            None => return,
+        };
+        for nested in &message.messages {
+            self.add_message(&name, nested);
+        }
+        for enumeration in &message.enums {
+            self.types
+                .insert(qualify(&name, enumeration.name.as_ref()), TypeKind::Enum);
+        }
+        self.types.insert(name, TypeKind::Message);
+    }
+
+    /// Resolves a type name referenced from `scope`, a package or fully qualified message name.
+    ///
+    /// Like protoc, scopes are searched from the innermost outwards,
+    /// and names starting with a `.` are fully qualified. A nested path such as `Outer.Inner`
+    /// is looked up in the innermost scope declaring `Outer`, even if it has no `Inner`.
+    pub fn resolve(&self, scope: &str, name: &str) -> Option<TypeKind> {
+        self.resolve_name(scope, name).map(|(_, kind)| kind)
+    }
+
+    /// Resolves a type name like `resolve`, also returning its fully qualified name.
+    pub fn resolve_name(&self, scope: &str, name: &str) -> Option<(String, TypeKind)> {
+        if let Some(name) = name.strip_prefix('.') {
+            return self.types.get(name).map(|kind| (name.to_string(), *kind));
+        }
+
+        let first = name.split('.').next().unwrap_or(name);
+        let mut scope = scope;
+        loop {
+            let outer = qualify(scope, first);
+            if self.types.contains_key(&outer) || self.packages.contains(&outer) {
+                let qualified = qualify(scope, name);
+                if let Some(kind) = self.types.get(&qualified) {
+                    return Some((qualified, *kind));
+                }
+                // Keep looking outwards for packages, which can be split across files
+                if !self.packages.contains(&outer) {
+                    return None;
+                }
+            }
+            if scope.is_empty() {
+                return None;
+            }
+            scope = scope.rfind('.').map(|i| &scope[..i]).unwrap_or("");
+        }
+    }
+
+    /// Adds the types of every proto file in a directory, so files of a package split across
+    /// several files can reference each other without imports.
+    pub async fn add_dir(&mut self, dir: impl AsRef<Path>) -> Result<()> {
+        let mut entries = read_proto_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "proto") {
+                continue;
+            }
+            let file = read_proto_file(&path).await?;
+            let proto = parse_complete(&file).map_err(|err| {
+                anyhow!(
+                    "could not parse package proto file '{}': {}",
+                    path.to_string_lossy(),
+                    err
+                )
+            })?;
+            self.add_proto(&proto);
+        }
+        Ok(())
+    }
+
+    /// Loads the files imported by a proto file from the include paths, along with their own imports.
+    ///
+    /// Each import is only loaded once, so circular imports are fine.
+    pub async fn add_imports(
+        &mut self,
+        proto: &AbstractProto<'_>,
+        include_paths: &[String],
+    ) -> Result<()> {
+        let mut pending: Vec<String> = proto
+            .import_paths
+            .iter()
+            .map(|import| import.as_ref().to_string())
+            .collect();
+        let mut loaded = HashSet::new();
+
+        while let Some(import) = pending.pop() {
+            // Well-known types are handled by the verifier itself
+            if import.starts_with("google/protobuf/") || !loaded.insert(import.clone()) {
+                continue;
+            }
+
+            let path = match find_import(&import, include_paths) {
+                Some(path) => path,
+                None => {
+                    warn!("could not find import '{}' in include paths", import);
+                    continue;
+                }
+            };
+            let file = read_proto_file(&path).await?;
+            let imported = parse_complete(&file).map_err(|err| {
+                anyhow!(
+                    "could not parse imported proto file '{}': {}",
+                    path.to_string_lossy(),
+                    err
+                )
+            })?;
+            info!("loaded imported proto file '{}'", path.to_string_lossy());
+
+            self.add_proto(&imported);
+            pending.extend(
+                imported
+                    .import_paths
+                    .iter()
+                    .map(|import| import.as_ref().to_string()),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Finds a type declared in a single file by name, from the innermost scope outwards like
+/// protoc does. Returns the fully qualified name with the type.
+pub fn lookup<'m, T>(
+    types: &'m HashMap<String, T>,
+    scope: &str,
+    name: &str,
+) -> Option<(String, &'m T)> {
+    let mut scope = scope;
+    loop {
+        let qualified = qualify(scope, name);
+        if let Some(typ) = types.get(&qualified) {
+            return Some((qualified, typ));
+        }
+        if scope.is_empty() {
+            return None;
+        }
+        scope = scope.rfind('.').map(|i| &scope[..i]).unwrap_or("");
+    }
+}
+
+/// Fully qualified name of `name` declared inside `scope`.
+pub fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+fn find_import(import: &str, include_paths: &[String]) -> Option<PathBuf> {
+    include_paths
+        .iter()
+        .map(|include_path| Path::new(include_path).join(import))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolve_scopes() {
+        let proto = parse_complete(
+            r#"package shop.v1;
+            enum Currency { USD = 0; }
+            message Order {
+                enum Status { PENDING = 0; }
+                message Line { string sku = 1; }
+            }"#,
+        )
+        .unwrap();
+        let mut resolver = TypeResolver::default();
+        resolver.add_proto(&proto);
+
+        let scope = "shop.v1.Order";
+        assert_eq!(resolver.resolve(scope, "Status"), Some(TypeKind::Enum));
+        assert_eq!(resolver.resolve(scope, "Line"), Some(TypeKind::Message));
+        assert_eq!(resolver.resolve(scope, "Currency"), Some(TypeKind::Enum));
+        assert_eq!(resolver.resolve(scope, "v1.Currency"), Some(TypeKind::Enum));
+        assert_eq!(
+            resolver.resolve(scope, ".shop.v1.Order.Line"),
+            Some(TypeKind::Message)
+        );
+        assert_eq!(resolver.resolve("shop.v1", "Status"), None);
+        assert_eq!(resolver.resolve(scope, ".Currency"), None);
+    }
+
+    #[test]
+    fn test_resolve_nested_path() {
+        let proto = parse_complete(
+            r#"message Outer {
+                message Inner { string name = 1; }
+                enum Kind { KIND_UNKNOWN = 0; }
+            }
+            message User {
+                Outer.Inner inner = 1;
+                Outer.Kind kind = 2;
+            }
+            message Shadow {
+                message Outer {}
+                Outer.Inner inner = 1;
+            }"#,
+        )
+        .unwrap();
+        let mut resolver = TypeResolver::default();
+        resolver.add_proto(&proto);
+
+        assert_eq!(
+            resolver.resolve("User", "Outer.Inner"),
+            Some(TypeKind::Message)
+        );
+        assert_eq!(resolver.resolve("User", "Outer.Kind"), Some(TypeKind::Enum));
+        assert_eq!(resolver.resolve("User", "Outer.Missing"), None);
+        // `Outer` is found as `Shadow.Outer` first, which has no `Inner`
+        assert_eq!(resolver.resolve("Shadow", "Outer.Inner"), None);
+    }
+
+    #[tokio::test]
+    async fn test_add_imports() {
+        let dir = std::env::temp_dir().join(format!("protosql-imports-{}", std::process::id()));
+        tokio::fs::create_dir_all(dir.join("other")).await.unwrap();
+        tokio::fs::write(
+            dir.join("other/address.proto"),
+            r#"syntax = "proto3";
+            package other;
+            import "user.proto";
+            message Address { string city = 1; }"#,
+        )
+        .await
+        .unwrap();
+        tokio::fs::write(
+            dir.join("user.proto"),
+            r#"syntax = "proto3";
+            import "other/address.proto";
+            import "google/protobuf/timestamp.proto";
+            message User { other.Address address = 1; }"#,
+        )
+        .await
+        .unwrap();
+
+        let file = tokio::fs::read_to_string(dir.join("user.proto"))
+            .await
+            .unwrap();
+        let proto = parse_complete(&file).unwrap();
+        let mut resolver = TypeResolver::default();
+        resolver.add_proto(&proto);
+        resolver
+            .add_imports(&proto, &[dir.to_string_lossy().to_string()])
+            .await
+            .unwrap();
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        assert_eq!(
+            resolver.resolve("User", "other.Address"),
+            Some(TypeKind::Message)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_dir() {
+        let dir = std::env::temp_dir().join(format!("protosql-package-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        tokio::fs::write(
+            dir.join("status.proto"),
+            r#"syntax = "proto3";
+            package shop;
+            enum Status { PENDING = 0; }"#,
+        )
+        .await
+        .unwrap();
+        tokio::fs::write(
+            dir.join("order.proto"),
+            r#"syntax = "proto3";
+            package shop;
+            message Order { Status status = 1; }"#,
+        )
+        .await
+        .unwrap();
+
+        let mut resolver = TypeResolver::default();
+        resolver.add_dir(&dir).await.unwrap();
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        assert_eq!(
+            resolver.resolve("shop.Order", "Status"),
+            Some(TypeKind::Enum)
+        );
+        assert_eq!(
+            resolver.resolve("shop.Order", "Order"),
+            Some(TypeKind::Message)
+        );
+    }
+}
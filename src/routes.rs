@@ -0,0 +1,132 @@
+//! `protosql routes`, which lists the HTTP routes rpc methods are mapped to with
+//! `google.api.http` annotations, as used by gRPC-gateway.
+
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use protobuf_parser::{parse_complete, DeclOptionName, Rpc};
+use serde::Serialize;
+
+use crate::{
+    commands::{Format, Protosql},
+    proto_files, read_proto_source,
+};
+
+const HTTP_OPTION: &str = "google.api.http";
+
+/// HTTP methods of a `google.api.http` rule, named by their field in the rule
+const HTTP_METHODS: &[&str] = &["get", "put", "post", "delete", "patch"];
+
+/// An HTTP route of an rpc method.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Route {
+    pub file: String,
+    /// HTTP method in upper case, such as `GET`
+    pub method: String,
+    /// Path template, such as `/v1/users/{id}`
+    pub path: String,
+    /// Full name of the rpc method, such as `Users.GetUser`
+    pub rpc: String,
+}
+
+/// Prints the HTTP routes in the proto files given with `--file` or `--dir`.
+pub async fn routes(opts: &Protosql) -> Result<bool> {
+    let mut routes = Vec::new();
+    for path in proto_files(opts).await? {
+        let source = read_proto_source(&path).await?;
+        routes.extend(list_routes(&path, &source)?);
+    }
+
+    match opts.format {
+        Format::Text => {
+            for route in &routes {
+                println!(
+                    "{}: {} {} -> {}",
+                    route.file, route.method, route.path, route.rpc
+                );
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string_pretty(&routes)?),
+    }
+
+    Ok(true)
+}
+
+/// Routes of the rpc methods in a single proto file, in declaration order. Methods without an
+/// http annotation have no route.
+pub fn list_routes(path: &Path, source: &str) -> Result<Vec<Route>> {
+    let proto =
+        parse_complete(source).map_err(|err| anyhow!("could not parse proto file: {}", err))?;
+    let mut routes = Vec::new();
+    for service in &proto.services {
+        for rpc in &service.rpcs {
+            if let Some((method, route_path)) = http_rule(rpc) {
+                routes.push(Route {
+                    file: path.to_string_lossy().to_string(),
+                    method,
+                    path: route_path,
+                    rpc: format!("{}.{}", service.name.as_ref(), rpc.name.as_ref()),
+                });
+            }
+        }
+    }
+    Ok(routes)
+}
+
+/// The HTTP method and path of an rpc's `option (google.api.http) = { get: "..." }`.
+/// Only the primary binding is read, not `additional_bindings`.
+fn http_rule(rpc: &Rpc) -> Option<(String, String)> {
+    let option = rpc.options.iter().rev().find(|option| {
+        matches!(option.name(), DeclOptionName::Custom(name) if name.as_ref() == HTTP_OPTION)
+    })?;
+    option
+        .aggregate_fields()?
+        .into_iter()
+        .find(|(name, _)| HTTP_METHODS.contains(&name.as_str()))
+        .map(|(method, path)| (method.to_uppercase(), path))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_list_routes() {
+        let source = r#"syntax = "proto3";
+service Users {
+  rpc GetUser (GetUserRequest) returns (User) {
+    option (google.api.http) = { get: "/v1/users/{id}" };
+  }
+  rpc CreateUser (User) returns (User) {
+    option (google.api.http) = {
+      post: "/v1/users"
+      body: "*"
+    };
+  }
+  rpc Watch (WatchRequest) returns (stream User);
+}
+message User {
+  int64 id = 1;
+}"#;
+        let routes: Vec<_> = list_routes(Path::new("users.proto"), source)
+            .unwrap()
+            .into_iter()
+            .map(|route| (route.method, route.path, route.rpc))
+            .collect();
+        assert_eq!(
+            routes,
+            vec![
+                (
+                    "GET".to_string(),
+                    "/v1/users/{id}".to_string(),
+                    "Users.GetUser".to_string()
+                ),
+                (
+                    "POST".to_string(),
+                    "/v1/users".to_string(),
+                    "Users.CreateUser".to_string()
+                ),
+            ]
+        );
+    }
+}
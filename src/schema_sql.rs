@@ -0,0 +1,541 @@
+//! Tables read from a `pg_dump --schema-only` file with `--schema-sql`, to verify proto files
+//! without connecting to a database.
+//!
+//! Only the statements pg_dump writes for table columns are understood: `CREATE TABLE`,
+//! `ALTER TABLE` setting a column default or adding an identity, and `COMMENT ON COLUMN`.
+//! Everything else is skipped.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{Context, Result};
+use sea_schema::postgres::def::{ColumnExpression, ColumnInfo, ColumnType, NotNull};
+
+use crate::{schema, types};
+
+/// Keywords ending the type or default of a column definition
+const COLUMN_CONSTRAINTS: &[&str] = &[
+    "not",
+    "null",
+    "default",
+    "constraint",
+    "primary",
+    "unique",
+    "check",
+    "references",
+    "generated",
+    "collate",
+];
+
+/// Keywords starting a table constraint instead of a column definition
+const TABLE_CONSTRAINTS: &[&str] = &[
+    "constraint",
+    "primary",
+    "unique",
+    "check",
+    "foreign",
+    "exclude",
+    "like",
+];
+
+/// A table declared in the SQL file
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SqlTable {
+    pub columns: Vec<ColumnInfo>,
+    /// Element types of the array columns, by column name
+    pub array_elements: HashMap<String, String>,
+    /// Comments of the columns which have one, by column name
+    pub comments: HashMap<String, String>,
+}
+
+/// The tables declared in a SQL file, by schema and table name.
+#[derive(Debug, Default)]
+pub struct SqlSchema {
+    tables: BTreeMap<(String, String), SqlTable>,
+}
+
+impl SqlSchema {
+    pub async fn load(path: &str) -> Result<Self> {
+        let sql = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("could not read schema file {}", path))?;
+        Ok(Self::parse(&sql))
+    }
+
+    pub fn parse(sql: &str) -> Self {
+        let mut tables = BTreeMap::new();
+        for statement in statements(sql) {
+            let words = words(&statement);
+            let keywords: Vec<_> = words.iter().take(2).map(|w| w.to_lowercase()).collect();
+            match keywords.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+                ["create", _] => {
+                    if let Some((name, table)) = create_table(&words[1..]) {
+                        tables.insert(name, table);
+                    }
+                }
+                ["alter", "table"] => alter_column(&mut tables, &words[2..]),
+                ["comment", "on"] => comment_on_column(&mut tables, &words[2..]),
+                _ => {}
+            }
+        }
+        SqlSchema { tables }
+    }
+
+    pub fn table(&self, schema: &str, table: &str) -> Option<&SqlTable> {
+        self.tables.get(&(schema.to_string(), table.to_string()))
+    }
+
+    /// Names of the tables in a schema.
+    pub fn tables(&self, schema: &str) -> Vec<String> {
+        self.tables
+            .keys()
+            .filter(|(table_schema, _)| table_schema == schema)
+            .map(|(_, table)| table.clone())
+            .collect()
+    }
+}
+
+/// Parses the words of `CREATE [UNLOGGED] TABLE [IF NOT EXISTS] name (...)` following `CREATE`.
+fn create_table(words: &[String]) -> Option<((String, String), SqlTable)> {
+    let mut words = words.iter().skip_while(|word| {
+        ["unlogged", "temporary", "temp"].contains(&word.to_lowercase().as_str())
+    });
+    if !words.next()?.eq_ignore_ascii_case("table") {
+        return None;
+    }
+    let mut words =
+        words.skip_while(|word| ["if", "not", "exists"].contains(&word.to_lowercase().as_str()));
+
+    // The column list may directly follow the name, as in `users(id bigint)`
+    let name = words.next()?;
+    let (name, body) = match name.find('(') {
+        Some(open) => (&name[..open], name[open..].to_string()),
+        None => (name.as_str(), words.next()?.clone()),
+    };
+    let name = table_name(name)?;
+    let body = body.strip_prefix('(')?.strip_suffix(')')?;
+
+    let mut table = SqlTable::default();
+    for definition in split_top_level(body, |c| c == ',') {
+        let first = definition
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+        if TABLE_CONSTRAINTS.contains(&first.as_str()) {
+            continue;
+        }
+        if let Some((column, element)) = column(&definition) {
+            if let Some(element) = element {
+                table.array_elements.insert(column.name.clone(), element);
+            }
+            table.columns.push(column);
+        }
+    }
+    Some((name, table))
+}
+
+/// Parses a column definition such as `name character varying(64) DEFAULT 'a' NOT NULL`,
+/// returning the column and its element type if it's an array.
+fn column(definition: &str) -> Option<(ColumnInfo, Option<String>)> {
+    let words = words(definition);
+    let (name, rest) = words.split_first()?;
+    let type_len = constraint_position(rest);
+    let sql_type = rest[..type_len].join(" ");
+    let constraints: Vec<_> = rest[type_len..]
+        .iter()
+        .map(|word| word.to_lowercase())
+        .collect();
+
+    let (col_type, element) = match sql_type.strip_suffix("[]") {
+        // Named like `format_type` does, which leaves out the schema of types on the search path
+        Some(element) => (
+            ColumnType::Array,
+            Some(types::canonical_type_name(
+                element.trim_start_matches("public."),
+            )),
+        ),
+        None => (types::parse_sql_type(&sql_type), None),
+    };
+    let not_null = constraints
+        .windows(2)
+        .any(|pair| pair == ["not", "null"] || pair == ["primary", "key"]);
+    let default = constraints
+        .iter()
+        .position(|word| word == "default")
+        .map(|i| default_expression(&rest[type_len + i + 1..]));
+    let is_identity = constraints.iter().any(|word| word == "identity");
+
+    Some((
+        ColumnInfo {
+            name: identifier(name)?,
+            col_type,
+            default,
+            generated: None,
+            not_null: NotNull::from_bool(not_null),
+            is_identity,
+        },
+        element,
+    ))
+}
+
+/// Applies the words of `ALTER TABLE [ONLY] name ALTER [COLUMN] column SET DEFAULT ...` and
+/// `... ADD GENERATED ... AS IDENTITY` following `ALTER TABLE`, which pg_dump writes for serial
+/// and identity columns.
+fn alter_column(tables: &mut BTreeMap<(String, String), SqlTable>, words: &[String]) {
+    let lower: Vec<_> = words.iter().map(|word| word.to_lowercase()).collect();
+    let mut i = 0;
+    if lower.get(i).map(String::as_str) == Some("only") {
+        i += 1;
+    }
+    let table = match words
+        .get(i)
+        .and_then(|name| table_name(name))
+        .and_then(|name| tables.get_mut(&name))
+    {
+        Some(table) => table,
+        None => return,
+    };
+    i += 1;
+    if lower.get(i).map(String::as_str) != Some("alter") {
+        return;
+    }
+    i += 1;
+    if lower.get(i).map(String::as_str) == Some("column") {
+        i += 1;
+    }
+    let name = words.get(i).and_then(|name| identifier(name));
+    let column = match table
+        .columns
+        .iter_mut()
+        .find(|column| Some(&column.name) == name.as_ref())
+    {
+        Some(column) => column,
+        None => return,
+    };
+
+    let action = &lower[i + 1..];
+    if action.starts_with(&["set".to_string(), "default".to_string()]) {
+        column.default = Some(default_expression(&words[i + 3..]));
+    } else if action.first().map(String::as_str) == Some("add")
+        && action.iter().any(|word| word == "identity")
+    {
+        column.is_identity = true;
+    }
+}
+
+/// Applies the words of `COMMENT ON COLUMN [schema.]table.column IS '...'` following
+/// `COMMENT ON`.
+fn comment_on_column(tables: &mut BTreeMap<(String, String), SqlTable>, words: &[String]) {
+    let (name, comment) = match words {
+        [column, name, is, comment]
+            if column.eq_ignore_ascii_case("column") && is.eq_ignore_ascii_case("is") =>
+        {
+            (name, comment)
+        }
+        _ => return,
+    };
+    let comment = match comment
+        .strip_prefix('\'')
+        .and_then(|c| c.strip_suffix('\''))
+    {
+        Some(comment) => comment.replace("''", "'"),
+        // `IS NULL` removes the comment
+        None => return,
+    };
+    let mut parts = split_top_level(name, |c| c == '.');
+    let column = match parts.pop().and_then(|column| identifier(&column)) {
+        Some(column) => column,
+        None => return,
+    };
+    if let Some(table) = table_name(&parts.join(".")).and_then(|name| tables.get_mut(&name)) {
+        table.comments.insert(column, comment);
+    }
+}
+
+/// The words of a default expression, up to the next column constraint.
+fn default_expression(words: &[String]) -> ColumnExpression {
+    ColumnExpression(words[..constraint_position(words)].join(" "))
+}
+
+fn constraint_position(words: &[String]) -> usize {
+    words
+        .iter()
+        .position(|word| COLUMN_CONSTRAINTS.contains(&word.to_lowercase().as_str()))
+        .unwrap_or(words.len())
+}
+
+/// Splits a possibly schema qualified table name, defaulting to the `public` schema.
+fn table_name(name: &str) -> Option<(String, String)> {
+    match split_top_level(name, |c| c == '.').as_slice() {
+        [table] => Some(("public".to_string(), identifier(table)?)),
+        [schema, table] => Some((identifier(schema)?, identifier(table)?)),
+        _ => None,
+    }
+}
+
+/// Unquotes a quoted identifier, or folds an unquoted one to lower case like Postgres does.
+fn identifier(word: &str) -> Option<String> {
+    if word.starts_with('"') {
+        schema::parse_identifier(word).ok()
+    } else {
+        Some(word.to_lowercase())
+    }
+}
+
+/// Statements of a SQL file without comments, split on `;` outside of quotes, parentheses and
+/// dollar quoted bodies such as `$$ ... $$` or `$body$ ... $body$`.
+fn statements(sql: &str) -> Vec<String> {
+    let mut statements = vec![String::new()];
+    let mut depth = 0usize;
+    let mut rest = sql;
+    while let Some(c) = rest.chars().next() {
+        let statement = statements.last_mut().unwrap();
+        if rest.starts_with("--") || rest.starts_with("/*") {
+            statement.push(' ');
+            rest = &rest[comment_len(rest)..];
+            continue;
+        }
+        let len = match c {
+            '\'' | '"' => rest[1..].find(c).map_or(rest.len(), |end| end + 2),
+            // `$` in the middle of a name such as `a$b` doesn't start a quote
+            '$' if !statement.ends_with(|c: char| c.is_alphanumeric() || c == '_') => {
+                dollar_quote_len(rest).unwrap_or(1)
+            }
+            '(' => {
+                depth += 1;
+                1
+            }
+            ')' => {
+                depth = depth.saturating_sub(1);
+                1
+            }
+            ';' if depth == 0 => {
+                statements.push(String::new());
+                rest = &rest[1..];
+                continue;
+            }
+            c => c.len_utf8(),
+        };
+        statement.push_str(&rest[..len]);
+        rest = &rest[len..];
+    }
+    statements
+        .into_iter()
+        .map(|statement| statement.trim().to_string())
+        .filter(|statement| !statement.is_empty())
+        .collect()
+}
+
+/// Length of the `--` or `/* */` comment at the start of the text, up to the end of the line or
+/// the closing `*/`. Block comments can be nested.
+fn comment_len(text: &str) -> usize {
+    if text.starts_with("--") {
+        return text.find('\n').unwrap_or(text.len());
+    }
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < text.len() {
+        if text[i..].starts_with("/*") {
+            depth += 1;
+            i += 2;
+        } else if text[i..].starts_with("*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += text[i..].chars().next().unwrap().len_utf8();
+        }
+    }
+    text.len()
+}
+
+/// Length of the dollar quoted string at the start of the text, including both tags, or `None`
+/// if the text doesn't start with a tag such as `$$` or `$body$`.
+fn dollar_quote_len(text: &str) -> Option<usize> {
+    let tag_len = text[1..].find('$')? + 2;
+    let tag = &text[..tag_len];
+    let name = &tag[1..tag_len - 1];
+    if name.starts_with(|c: char| c.is_ascii_digit())
+        || !name.chars().all(|c| c.is_alphanumeric() || c == '_')
+    {
+        return None;
+    }
+    let end = text[tag_len..]
+        .find(tag)
+        .map_or(text.len(), |end| tag_len + end + tag_len);
+    Some(end)
+}
+
+/// Words of a statement, keeping quoted identifiers, strings and parentheses together.
+fn words(text: &str) -> Vec<String> {
+    split_top_level(text, char::is_whitespace)
+}
+
+/// Splits text on separators outside of quotes and parentheses, dropping empty parts.
+fn split_top_level(text: &str, is_separator: impl Fn(char) -> bool) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut depth = 0usize;
+    let mut quote = None;
+    for c in text.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '(' => depth += 1,
+            None if c == ')' => depth = depth.saturating_sub(1),
+            None if depth == 0 && is_separator(c) => {
+                parts.push(String::new());
+                continue;
+            }
+            None => {}
+        }
+        parts.last_mut().unwrap().push(c);
+    }
+    parts
+        .into_iter()
+        .map(|part| part.trim().to_string())
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_pg_dump() {
+        let sql = r#"
+--
+-- Name: users; Type: TABLE; Schema: public; Owner: app
+--
+
+CREATE TABLE public.users (
+    id bigint NOT NULL,
+    name character varying(64) DEFAULT 'a; b'::character varying NOT NULL,
+    roles public.role[],
+    "Created At" timestamp with time zone DEFAULT now(),
+    CONSTRAINT name_not_empty CHECK ((name <> ''::text))
+);
+
+CREATE TABLE shop.orders (
+    id integer NOT NULL, -- the id
+    total numeric(10,2)
+);
+
+CREATE SEQUENCE public.users_id_seq START WITH 1;
+COMMENT ON COLUMN public.users.name IS 'Deprecated: use the profile''s name';
+ALTER TABLE ONLY public.users ALTER COLUMN id SET DEFAULT nextval('public.users_id_seq'::regclass);
+ALTER TABLE shop.orders ALTER COLUMN id ADD GENERATED ALWAYS AS IDENTITY (
+    SEQUENCE NAME shop.orders_id_seq
+);
+"#;
+        let schema = SqlSchema::parse(sql);
+        assert_eq!(schema.tables("public"), vec!["users"]);
+        assert_eq!(schema.tables("shop"), vec!["orders"]);
+
+        let users = schema.table("public", "users").unwrap();
+        let columns: Vec<_> = users
+            .columns
+            .iter()
+            .map(|column| {
+                (
+                    column.name.as_str(),
+                    column.col_type.clone(),
+                    column.not_null.is_some(),
+                    column.default.as_ref().map(|default| default.0.as_str()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            columns,
+            vec![
+                (
+                    "id",
+                    ColumnType::BigInt,
+                    true,
+                    Some("nextval('public.users_id_seq'::regclass)")
+                ),
+                (
+                    "name",
+                    types::parse_sql_type("varchar(64)"),
+                    true,
+                    Some("'a; b'::character varying")
+                ),
+                ("roles", ColumnType::Array, false, None),
+                (
+                    "Created At",
+                    ColumnType::TimestampWithTimeZone(Default::default()),
+                    false,
+                    Some("now()")
+                ),
+            ]
+        );
+        assert_eq!(users.array_elements["roles"], "role");
+        assert_eq!(users.comments["name"], "Deprecated: use the profile's name");
+
+        let orders = schema.table("shop", "orders").unwrap();
+        assert!(orders.columns[0].is_identity);
+        assert_eq!(
+            orders.columns[1].col_type,
+            types::parse_sql_type("numeric(10,2)")
+        );
+        assert!(schema.table("public", "orders").is_none());
+    }
+
+    #[test]
+    fn test_dollar_quotes() {
+        let sql = r#"
+CREATE FUNCTION public.touch() RETURNS trigger AS $$
+BEGIN
+    NEW.updated_at := now(); -- keep it fresh
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE FUNCTION public.note() RETURNS text AS $body$
+    SELECT 'costs $5; no $$ inside';
+$body$ LANGUAGE sql;
+
+CREATE TABLE public.users (id bigint NOT NULL, price$ numeric);
+"#;
+        assert_eq!(
+            statements(sql),
+            vec![
+                "CREATE FUNCTION public.touch() RETURNS trigger AS $$\nBEGIN\n    \
+                 NEW.updated_at := now(); -- keep it fresh\n    RETURN NEW;\nEND;\n$$ LANGUAGE plpgsql",
+                "CREATE FUNCTION public.note() RETURNS text AS $body$\n    \
+                 SELECT 'costs $5; no $$ inside';\n$body$ LANGUAGE sql",
+                "CREATE TABLE public.users (id bigint NOT NULL, price$ numeric)",
+            ]
+        );
+        let schema = SqlSchema::parse(sql);
+        assert_eq!(schema.tables("public"), vec!["users"]);
+        assert_eq!(schema.table("public", "users").unwrap().columns.len(), 2);
+    }
+
+    #[test]
+    fn test_block_comments() {
+        let sql = r#"
+/*
+ * Schema for the shop; generated by hand.
+ * CREATE TABLE public.ignored (id bigint);
+ */
+CREATE TABLE public.orders (
+    id bigint NOT NULL, /* the id; /* nested */ still a comment */
+    total numeric -- the total; in cents
+);
+"#;
+        let schema = SqlSchema::parse(sql);
+        assert_eq!(schema.tables("public"), vec!["orders"]);
+        let names: Vec<_> = schema
+            .table("public", "orders")
+            .unwrap()
+            .columns
+            .iter()
+            .map(|column| column.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["id", "total"]);
+    }
+}
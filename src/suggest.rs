@@ -0,0 +1,84 @@
+//! Suggestions printed with `--suggest-matches`, pairing fields without a column with the
+//! closest column without a field, and the other way around, to spot renamed columns or fields.
+
+/// Suggestions for the fields without a column and the columns without a field, such as
+/// `field 'userEmail' has no column, did you mean 'user_email_address'?`.
+pub fn suggestions(fields: &[String], columns: &[String]) -> Vec<String> {
+    let field_suggestions = fields.iter().filter_map(|field| {
+        let column = closest(field, columns)?;
+        Some(format!(
+            "field '{}' has no column, did you mean '{}'?",
+            field, column
+        ))
+    });
+    let column_suggestions = columns.iter().filter_map(|column| {
+        let field = closest(column, fields)?;
+        Some(format!(
+            "column '{}' has no field, did you mean '{}'?",
+            column, field
+        ))
+    });
+    field_suggestions.chain(column_suggestions).collect()
+}
+
+/// The candidate closest to `name`, ignoring case and underscores so `userEmail` and
+/// `user_email` are equal. Candidates differing in more than half of the longer name are too
+/// far off to be a rename.
+fn closest<'c>(name: &str, candidates: &'c [String]) -> Option<&'c str> {
+    let name = normalize(name);
+    candidates
+        .iter()
+        .map(|candidate| {
+            let normalized = normalize(candidate);
+            let distance = levenshtein(&name, &normalized);
+            let max_len = name.chars().count().max(normalized.chars().count());
+            (candidate, distance, max_len)
+        })
+        .filter(|(_, distance, max_len)| distance * 2 <= *max_len)
+        .min_by_key(|(_, distance, _)| *distance)
+        .map(|(candidate, _, _)| candidate.as_str())
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| *c != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Number of single character insertions, deletions and substitutions turning `a` into `b`.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_suggestions() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+
+        let fields = vec!["userEmail".to_string(), "zip".to_string()];
+        let columns = vec!["user_email_address".to_string(), "created_at".to_string()];
+        assert_eq!(
+            suggestions(&fields, &columns),
+            vec![
+                "field 'userEmail' has no column, did you mean 'user_email_address'?",
+                "column 'user_email_address' has no field, did you mean 'userEmail'?",
+            ]
+        );
+        assert!(suggestions(&fields, &[]).is_empty());
+    }
+}
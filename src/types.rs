@@ -0,0 +1,561 @@
+use std::{collections::HashMap, mem};
+
+use heck::SnakeCase;
+
+use protobuf_parser::{Field, FieldType, ProtoValue, RuleVariant, WellKnownType};
+use sea_schema::postgres::def::{ArbitraryPrecisionNumericAttr, ColumnType, StringAttr, TimeAttr};
+
+use crate::resolve::TypeKind;
+
+/// Postgres types a proto type can be stored as, with the canonical type first.
+///
+/// Names are understood by `ColumnType::from_str`.
+fn postgres_types(typ: &FieldType) -> &'static [&'static str] {
+    match typ {
+        FieldType::Int32
+        | FieldType::Uint32
+        | FieldType::Sint32
+        | FieldType::Fixed32
+        | FieldType::Sfixed32 => &["integer"],
+        FieldType::Int64
+        | FieldType::Uint64
+        | FieldType::Sint64
+        | FieldType::Fixed64
+        | FieldType::Sfixed64 => &["bigint"],
+        FieldType::Bool => &["boolean"],
+        FieldType::Double => &["double precision"],
+        FieldType::Float => &["real"],
+        FieldType::String => &["varchar", "uuid"],
+        FieldType::Bytes => &["bytea"],
+        FieldType::MessageOrEnum(_) => match typ.well_known_type() {
+            Some(WellKnownType::Timestamp) => &["timestamp with time zone", "timestamp"],
+            _ => &[],
+        },
+        // Maps are stored as a JSON object, whether their values are scalars or messages
+        FieldType::Map(_) => MESSAGE_TYPES,
+        // Groups are inline nested messages
+        FieldType::Group(_) => MESSAGE_TYPES,
+    }
+}
+
+/// Result of checking a proto type against a column type
+#[derive(Debug, Clone, PartialEq)]
+pub enum Compatibility {
+    Compatible,
+    Incompatible,
+    /// The proto type has no column mapping, with the reason why
+    Unsupported(String),
+}
+
+/// Checks a proto type against a column type. Message and enum types without a mapping here
+/// are `Unsupported`, and are resolved against the proto files afterwards.
+pub fn check_compatibility(field: &FieldType, col: &ColumnType) -> Compatibility {
+    match field {
+        FieldType::MessageOrEnum(name) if postgres_type(field).is_none() => {
+            Compatibility::Unsupported(format!("unknown type '{}'", name.as_ref()))
+        }
+        field if is_compatible(field, col) => Compatibility::Compatible,
+        _ => Compatibility::Incompatible,
+    }
+}
+
+/// Decides which column types can store a proto type.
+///
+/// Message and enum types which the checker reports as `Unsupported` are resolved against the
+/// proto files afterwards, so checkers only need to handle the types they know about.
+pub trait TypeChecker {
+    fn is_compatible(&self, field: &FieldType, col: &ColumnType) -> Compatibility;
+}
+
+/// The default `TypeChecker`, using the Postgres types listed in this module.
+pub struct PostgresTypeChecker;
+
+impl TypeChecker for PostgresTypeChecker {
+    fn is_compatible(&self, field: &FieldType, col: &ColumnType) -> Compatibility {
+        check_compatibility(field, col)
+    }
+}
+
+/// A `TypeChecker` which also accepts the column types given for a proto type in the `[types]`
+/// table of the config file, such as `string = ["text", "citext"]`. Everything else is left to
+/// `PostgresTypeChecker`.
+pub struct MappedTypeChecker {
+    types: HashMap<String, Vec<ColumnType>>,
+}
+
+impl MappedTypeChecker {
+    /// Column type names by proto type name, such as `string` or `google.protobuf.Timestamp`
+    pub fn new(types: &HashMap<String, Vec<String>>) -> Self {
+        let types = types
+            .iter()
+            .map(|(typ, columns)| {
+                let columns = columns.iter().map(|name| parse_sql_type(name)).collect();
+                (typ.trim_start_matches('.').to_string(), columns)
+            })
+            .collect();
+        MappedTypeChecker { types }
+    }
+}
+
+impl TypeChecker for MappedTypeChecker {
+    fn is_compatible(&self, field: &FieldType, col: &ColumnType) -> Compatibility {
+        let name = crate::compat::type_name(field);
+        let mapped = self
+            .types
+            .get(name.trim_start_matches('.'))
+            .is_some_and(|columns| columns.iter().any(|typ| same_type(typ, col)));
+        if mapped {
+            Compatibility::Compatible
+        } else {
+            PostgresTypeChecker.is_compatible(field, col)
+        }
+    }
+}
+
+/// Postgres types a proto enum can be stored as, by number or by name
+const ENUM_TYPES: &[&str] = &["integer", "smallint", "varchar", "text"];
+
+/// Postgres types a proto message can be stored as
+const MESSAGE_TYPES: &[&str] = &["jsonb", "json"];
+
+/// Canonical Postgres type for a proto type, or `None` if it has no column mapping.
+pub fn postgres_type(typ: &FieldType) -> Option<&'static str> {
+    postgres_types(typ).first().copied()
+}
+
+/// Canonical Postgres column type for a field, such as `bigint` or `varchar[]` for repeated fields.
+pub fn default_postgres_type(field: &Field) -> Option<String> {
+    let typ = postgres_type(&field.typ)?;
+    // A repeated group is stored as a single jsonb array
+    if field.rule.variant == RuleVariant::Repeated && !matches!(field.typ, FieldType::Group(_)) {
+        Some(format!("{}[]", typ))
+    } else {
+        Some(typ.to_string())
+    }
+}
+
+/// Checks if a column can store a (non-repeated) proto type.
+pub fn is_compatible(typ: &FieldType, col_type: &ColumnType) -> bool {
+    postgres_types(typ)
+        .iter()
+        .any(|name| same_type(&ColumnType::from_str(name), col_type))
+}
+
+/// Checks if a column can store a (non-repeated) message or enum type.
+pub fn is_compatible_kind(kind: TypeKind, col_type: &ColumnType) -> bool {
+    let types = match kind {
+        TypeKind::Enum => ENUM_TYPES,
+        TypeKind::Message => MESSAGE_TYPES,
+    };
+    types
+        .iter()
+        .any(|name| same_type(&ColumnType::from_str(name), col_type))
+}
+
+/// Checks if an enum can be stored in a column, by number or name, or as a Postgres enum type
+/// named after it, such as `my_status` for `MyStatus`.
+pub fn is_compatible_enum(enum_name: &str, col_type: &ColumnType) -> bool {
+    if is_compatible_kind(TypeKind::Enum, col_type) {
+        return true;
+    }
+    match col_type {
+        ColumnType::Unknown(type_name) => {
+            let type_name = type_name.rsplit('.').next().unwrap_or_default();
+            let enum_name = enum_name.rsplit('.').next().unwrap_or_default();
+            type_name.trim_matches('"') == enum_name.to_snake_case()
+        }
+        _ => false,
+    }
+}
+
+/// Custom field option giving the exact column type a field is stored as
+const SQL_TYPE_OPTION: &str = "(sql).type";
+
+/// Column type a field is annotated with, such as `[(sql).type = "numeric(10,2)"]`.
+pub fn sql_type_annotation<'a>(field: &Field<'a>) -> Option<&'a str> {
+    field.option(SQL_TYPE_OPTION)?.string_value()
+}
+
+/// Checks a column against an annotated type.
+///
+/// Attributes such as precision or length are only compared when the annotation gives them,
+/// and a trailing `[]` only requires an array column.
+pub fn matches_sql_type(sql: &str, col_type: &ColumnType) -> bool {
+    let sql = sql.trim();
+    if sql.ends_with("[]") {
+        return matches!(col_type, ColumnType::Array);
+    }
+
+    let expected = parse_sql_type(sql);
+    same_type(&expected, col_type) && same_attrs(&expected, col_type)
+}
+
+/// Compares the attributes the expected type gives, ignoring the ones it leaves out.
+fn same_attrs(expected: &ColumnType, actual: &ColumnType) -> bool {
+    fn same<T: PartialEq>(expected: Option<T>, actual: Option<T>) -> bool {
+        expected.is_none() || expected == actual
+    }
+
+    match (expected, actual) {
+        (ColumnType::Numeric(e), ColumnType::Numeric(a))
+        | (ColumnType::Decimal(e), ColumnType::Decimal(a)) => {
+            same(e.precision, a.precision) && same(e.scale, a.scale)
+        }
+        (ColumnType::Varchar(e), ColumnType::Varchar(a))
+        | (ColumnType::Char(e), ColumnType::Char(a)) => same(e.length, a.length),
+        (ColumnType::Timestamp(e), ColumnType::Timestamp(a))
+        | (ColumnType::TimestampWithTimeZone(e), ColumnType::TimestampWithTimeZone(a))
+        | (ColumnType::Time(e), ColumnType::Time(a))
+        | (ColumnType::TimeWithTimeZone(e), ColumnType::TimeWithTimeZone(a)) => {
+            same(e.precision, a.precision)
+        }
+        _ => true,
+    }
+}
+
+/// Type names Postgres accepts, with the name `format_type` writes in the catalog for them
+const TYPE_ALIASES: &[(&str, &str)] = &[
+    ("int", "integer"),
+    ("int2", "smallint"),
+    ("int4", "integer"),
+    ("int8", "bigint"),
+    ("smallserial", "smallint"),
+    ("serial2", "smallint"),
+    ("serial", "integer"),
+    ("serial4", "integer"),
+    ("bigserial", "bigint"),
+    ("serial8", "bigint"),
+    ("float", "double precision"),
+    ("float4", "real"),
+    ("float8", "double precision"),
+    ("decimal", "numeric"),
+    ("bool", "boolean"),
+    ("varchar", "character varying"),
+    ("char", "character"),
+    ("bpchar", "character"),
+    ("timestamptz", "timestamp with time zone"),
+    ("timetz", "time with time zone"),
+];
+
+/// Names a type like the catalog does, such as `timestamp with time zone` for `timestamptz` or
+/// `character varying(64)[]` for `varchar(64)[]`.
+pub fn canonical_type_name(sql: &str) -> String {
+    let sql = sql.trim().to_lowercase();
+    if let Some(element) = sql.strip_suffix("[]") {
+        return format!("{}[]", canonical_type_name(element));
+    }
+    let (name, args) = sql.split_at(sql.find('(').unwrap_or(sql.len()));
+    let name = name.trim();
+    let name = TYPE_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, canonical)| canonical);
+    format!("{}{}", name, args)
+}
+
+/// Parses a Postgres type name, including the arguments of `numeric(p,s)`, `varchar(n)` and
+/// `timestamp(p)`. Aliases such as `int8` are understood, and a trailing `[]` is an array.
+pub fn parse_sql_type(sql: &str) -> ColumnType {
+    let sql = canonical_type_name(sql);
+    if sql.ends_with("[]") {
+        return ColumnType::Array;
+    }
+    let (name, args) = match sql.split_once('(') {
+        Some((name, rest)) => {
+            let (args, suffix) = rest.split_once(')').unwrap_or((rest, ""));
+            (format!("{} {}", name.trim(), suffix.trim()), args)
+        }
+        None => return ColumnType::from_str(sql.trim()),
+    };
+    let mut args = args.split(',').map(|arg| arg.trim().parse::<u16>().ok());
+    let mut next = || args.next().flatten();
+
+    match name.trim() {
+        "numeric" | "decimal" => ColumnType::Numeric(ArbitraryPrecisionNumericAttr {
+            precision: next(),
+            scale: next().or(Some(0)),
+        }),
+        "varchar" | "character varying" => ColumnType::Varchar(StringAttr { length: next() }),
+        "char" | "character" => ColumnType::Char(StringAttr { length: next() }),
+        "timestamp" | "timestamp without time zone" => {
+            ColumnType::Timestamp(TimeAttr { precision: next() })
+        }
+        "timestamptz" | "timestamp with time zone" => {
+            ColumnType::TimestampWithTimeZone(TimeAttr { precision: next() })
+        }
+        "time" | "time without time zone" => ColumnType::Time(TimeAttr { precision: next() }),
+        "timetz" | "time with time zone" => {
+            ColumnType::TimeWithTimeZone(TimeAttr { precision: next() })
+        }
+        name => ColumnType::from_str(name),
+    }
+}
+
+/// Checks if a proto `[default = ...]` value matches a column's default expression, such as
+/// `5` and `'5'::integer`, or `"main"` and `'main'::character varying`.
+pub fn default_matches(proto_default: &ProtoValue, column_default: &str) -> bool {
+    let column_default = normalize_default(column_default);
+    // Integers are compared exactly, so large uint64 values don't lose precision as floats
+    let integer_matches = |value: i128| match column_default.parse::<i128>() {
+        Ok(column) => column == value,
+        Err(_) => column_default.parse::<f64>().ok() == Some(value as f64),
+    };
+    match proto_default {
+        ProtoValue::Int(value) => integer_matches(i128::from(*value)),
+        ProtoValue::UInt(value) => integer_matches(i128::from(*value)),
+        // Postgres writes `Infinity`, `-Infinity` and `NaN`, which parse as floats
+        ProtoValue::Float(value) => column_default
+            .parse::<f64>()
+            .is_ok_and(|column| column == *value || (column.is_nan() && value.is_nan())),
+        ProtoValue::Bool(value) => column_default == value.to_string(),
+        ProtoValue::Str(value) => column_default == *value,
+        ProtoValue::Ident(name) => column_default == name.as_ref(),
+    }
+}
+
+/// Strips casts, parentheses and quotes from a Postgres default expression.
+fn normalize_default(expr: &str) -> String {
+    let mut expr = expr.trim();
+    loop {
+        if let Some(inner) = expr
+            .strip_prefix('(')
+            .and_then(|expr| expr.strip_suffix(')'))
+        {
+            expr = inner.trim();
+        } else if let Some(cast) = expr.rfind("::").filter(|i| !expr[*i..].contains('\'')) {
+            expr = expr[..cast].trim();
+        } else {
+            break;
+        }
+    }
+    match expr
+        .strip_prefix('\'')
+        .and_then(|expr| expr.strip_suffix('\''))
+    {
+        Some(literal) => literal.replace("''", "'"),
+        None => expr.to_string(),
+    }
+}
+
+/// Compares column types, ignoring attributes such as length or precision.
+fn same_type(a: &ColumnType, b: &ColumnType) -> bool {
+    match (a, b) {
+        (ColumnType::Unknown(a), ColumnType::Unknown(b)) => a.eq_ignore_ascii_case(b),
+        (a, b) => mem::discriminant(a) == mem::discriminant(b),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use protobuf_parser::parse_complete;
+
+    use super::*;
+
+    #[test]
+    fn test_default_postgres_type() {
+        let proto = parse_complete(
+            r#"message AllTypes {
+                int32 a = 1;
+                int64 b = 2;
+                uint32 c = 3;
+                uint64 d = 4;
+                sint32 e = 5;
+                sint64 f = 6;
+                bool g = 7;
+                fixed64 h = 8;
+                sfixed64 i = 9;
+                double j = 10;
+                string k = 11;
+                bytes l = 12;
+                fixed32 m = 13;
+                sfixed32 n = 14;
+                float o = 15;
+                google.protobuf.Timestamp p = 16;
+                Address q = 17;
+                map<string, int32> r = 18;
+                repeated group S = 19 {
+                    int32 t = 20;
+                }
+                repeated string u = 21;
+            }"#,
+        )
+        .unwrap();
+        let types: Vec<_> = proto.messages[0]
+            .fields
+            .iter()
+            .map(default_postgres_type)
+            .collect();
+        let expected = vec![
+            Some("integer"),
+            Some("bigint"),
+            Some("integer"),
+            Some("bigint"),
+            Some("integer"),
+            Some("bigint"),
+            Some("boolean"),
+            Some("bigint"),
+            Some("bigint"),
+            Some("double precision"),
+            Some("varchar"),
+            Some("bytea"),
+            Some("integer"),
+            Some("integer"),
+            Some("real"),
+            Some("timestamp with time zone"),
+            None,
+            Some("jsonb"),
+            Some("jsonb"),
+            Some("varchar[]"),
+        ];
+        assert_eq!(
+            types,
+            expected
+                .into_iter()
+                .map(|typ| typ.map(str::to_string))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_is_compatible() {
+        assert!(is_compatible(&FieldType::Int32, &ColumnType::Integer));
+        assert!(!is_compatible(&FieldType::Int32, &ColumnType::BigInt));
+        assert!(is_compatible(
+            &FieldType::String,
+            &ColumnType::Varchar(Default::default())
+        ));
+        assert!(is_compatible(&FieldType::String, &ColumnType::Uuid));
+        assert!(!is_compatible(&FieldType::String, &ColumnType::Text));
+
+        assert!(is_compatible_kind(TypeKind::Enum, &ColumnType::Integer));
+        assert!(is_compatible_kind(
+            TypeKind::Message,
+            &ColumnType::Unknown("jsonb".to_string())
+        ));
+        assert!(!is_compatible_kind(TypeKind::Message, &ColumnType::Text));
+    }
+
+    #[test]
+    fn test_matches_sql_type() {
+        let numeric = |precision, scale| {
+            ColumnType::Numeric(ArbitraryPrecisionNumericAttr {
+                precision: Some(precision),
+                scale: Some(scale),
+            })
+        };
+        assert!(matches_sql_type("numeric(10,2)", &numeric(10, 2)));
+        assert!(matches_sql_type("DECIMAL(10, 2)", &numeric(10, 2)));
+        assert!(!matches_sql_type("numeric(10,2)", &numeric(10, 4)));
+        assert!(matches_sql_type("numeric(10)", &numeric(10, 0)));
+        assert!(matches_sql_type("numeric", &numeric(12, 4)));
+        assert!(!matches_sql_type("numeric", &ColumnType::BigInt));
+
+        let varchar = ColumnType::Varchar(StringAttr { length: Some(64) });
+        assert!(matches_sql_type("varchar(64)", &varchar));
+        assert!(!matches_sql_type("varchar(32)", &varchar));
+        assert!(matches_sql_type("varchar", &varchar));
+
+        let timestamp = |precision| ColumnType::Timestamp(TimeAttr { precision });
+        assert!(matches_sql_type("timestamp(3)", &timestamp(Some(3))));
+        assert!(!matches_sql_type("timestamp(3)", &timestamp(Some(6))));
+        assert!(matches_sql_type("timestamp", &timestamp(Some(6))));
+        let timestamptz = ColumnType::TimestampWithTimeZone(TimeAttr { precision: Some(3) });
+        assert!(matches_sql_type(
+            "timestamp(3) with time zone",
+            &timestamptz
+        ));
+        assert!(matches_sql_type("timestamptz(3)", &timestamptz));
+        assert!(!matches_sql_type("timestamp(3)", &timestamptz));
+        assert!(matches_sql_type("text[]", &ColumnType::Array));
+    }
+
+    #[test]
+    fn test_default_matches() {
+        let default_matches = |default: &str, column_default: &str| {
+            let source = format!(
+                "message Job {{ optional string value = 1 [default = {}]; }}",
+                default
+            );
+            let proto = parse_complete(&source).unwrap();
+            let default = proto.messages[0].fields[0].default_value().unwrap();
+            super::default_matches(&default, column_default)
+        };
+        assert!(default_matches("5", "5"));
+        assert!(default_matches("5", "'5'::integer"));
+        assert!(default_matches("-1", "(-1)"));
+        assert!(default_matches("0x10", "16"));
+        assert!(default_matches("5", "5.0"));
+        assert!(default_matches("1.5", "1.50"));
+        assert!(default_matches("inf", "'Infinity'::double precision"));
+        assert!(default_matches("nan", "'NaN'::real"));
+        assert!(!default_matches("5", "6"));
+        assert!(default_matches(
+            "18446744073709551615",
+            "'18446744073709551615'::numeric"
+        ));
+        assert!(!default_matches(
+            "18446744073709551615",
+            "'18446744073709551614'::numeric"
+        ));
+        assert!(default_matches(r#""main""#, "'main'::character varying"));
+        assert!(default_matches(r#""it's""#, "'it''s'::text"));
+        assert!(!default_matches(r#""main""#, "'Main'::text"));
+        assert!(!default_matches(r#""5""#, "6"));
+        assert!(default_matches("true", "true"));
+        assert!(!default_matches("true", "false"));
+        assert!(default_matches("ACTIVE", "'ACTIVE'::status"));
+        assert!(!default_matches("5", "nextval('seq'::regclass)"));
+    }
+
+    #[test]
+    fn test_type_aliases() {
+        assert_eq!(
+            canonical_type_name("timestamptz"),
+            "timestamp with time zone"
+        );
+        assert_eq!(canonical_type_name("INT8"), "bigint");
+        assert_eq!(
+            canonical_type_name("varchar(64)[]"),
+            "character varying(64)[]"
+        );
+        assert_eq!(
+            canonical_type_name("timestamp(3) with time zone"),
+            "timestamp(3) with time zone"
+        );
+        assert_eq!(canonical_type_name("my_status"), "my_status");
+
+        assert_eq!(parse_sql_type("int8"), ColumnType::BigInt);
+        assert_eq!(parse_sql_type("bool"), ColumnType::Boolean);
+        assert_eq!(parse_sql_type("serial"), ColumnType::Integer);
+        assert_eq!(parse_sql_type("float"), ColumnType::DoublePrecision);
+        assert_eq!(
+            parse_sql_type("timestamptz"),
+            ColumnType::TimestampWithTimeZone(Default::default())
+        );
+        assert_eq!(
+            parse_sql_type("varchar(64)"),
+            ColumnType::Varchar(StringAttr { length: Some(64) })
+        );
+        assert_eq!(parse_sql_type("text[]"), ColumnType::Array);
+    }
+
+    #[test]
+    fn test_is_compatible_enum() {
+        assert!(is_compatible_enum("Status", &ColumnType::Integer));
+        assert!(is_compatible_enum("Status", &ColumnType::SmallInt));
+        assert!(is_compatible_enum("Status", &ColumnType::Text));
+        assert!(is_compatible_enum(
+            "shop.MyStatus",
+            &ColumnType::Unknown("my_status".to_string())
+        ));
+        assert!(is_compatible_enum(
+            "MyStatus",
+            &ColumnType::Unknown("shop.my_status".to_string())
+        ));
+        assert!(!is_compatible_enum(
+            "Status",
+            &ColumnType::Unknown("role".to_string())
+        ));
+        assert!(!is_compatible_enum("Status", &ColumnType::Boolean));
+    }
+}
@@ -0,0 +1,52 @@
+//! Custom checks run on every verified message, next to the built-in ones.
+
+use std::fmt;
+
+use protobuf_parser::Message;
+use sea_schema::postgres::def::ColumnInfo;
+
+use crate::config::Config;
+
+/// A problem found by a custom validator, counted and reported like the built-in mismatches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// Column the problem is about, if any
+    pub column: Option<String>,
+    pub description: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.column {
+            Some(column) => write!(f, "column '{}': {}", column, self.description),
+            None => write!(f, "{}", self.description),
+        }
+    }
+}
+
+/// Checks a message against the columns of its table, for rules such as "every table must have
+/// a `created_at` column".
+pub type MessageValidator = dyn Fn(&Message, &[ColumnInfo]) -> Vec<Mismatch>;
+
+/// The validators set up in the config file.
+pub fn from_config(config: &Config) -> Vec<Box<MessageValidator>> {
+    let mut validators: Vec<Box<MessageValidator>> = Vec::new();
+    if !config.required_columns.is_empty() {
+        validators.push(required_columns(config.required_columns.clone()));
+    }
+    validators
+}
+
+/// Flags tables missing any of the given columns, from `required_columns` in the config file.
+pub fn required_columns(required: Vec<String>) -> Box<MessageValidator> {
+    Box::new(move |_, columns| {
+        required
+            .iter()
+            .filter(|name| !columns.iter().any(|column| &column.name == *name))
+            .map(|name| Mismatch {
+                column: Some(name.clone()),
+                description: "required column is missing from the table".to_string(),
+            })
+            .collect()
+    })
+}
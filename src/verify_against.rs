@@ -0,0 +1,237 @@
+//! `protosql verify-against`, which verifies a message against columns given on the command
+//! line instead of a table, for trying out the type mapping without a database.
+
+use std::collections::HashMap;
+
+use heck::SnakeCase;
+
+use crate::{
+    assume_syntax,
+    baseline::Baseline,
+    commands::{Protosql, VerifyAgainst},
+    config::Config,
+    find_proto_message, load_config,
+    log::*,
+    read_proto_source,
+    resolve::{qualify, TypeResolver},
+    schema,
+    types::{self, MappedTypeChecker},
+    validator, verify_message_with_columns, VerifyContext,
+};
+use anyhow::{anyhow, bail, Result};
+use colorful::Colorful;
+use protobuf_parser::{parse_complete, AbstractProto};
+
+/// Verifies the message given with `--message` against the `--columns` spec, returning `false`
+/// if they don't match.
+pub async fn verify_against(opts: &Protosql, cmd: &VerifyAgainst) -> Result<bool> {
+    let path = match &opts.file {
+        Some(file) => file.as_ref(),
+        None => bail!("no --file specified"),
+    };
+    let config = load_config(opts).await?;
+    let source = read_proto_source(path).await?;
+    let mut proto =
+        parse_complete(&source).map_err(|err| anyhow!("could not parse proto file: {}", err))?;
+    if let Some(warning) = assume_syntax(&mut proto, opts) {
+        warn!("{}", warning);
+    }
+    let columns = parse_columns(&cmd.columns)?;
+
+    let mut resolver = TypeResolver::default();
+    resolver.add_proto(&proto);
+    if !opts.include_path.is_empty() {
+        resolver.add_imports(&proto, &opts.include_path).await?;
+    }
+    let mismatches = verify_inline(opts, &config, &proto, &resolver, &cmd.message, &columns)?;
+    if mismatches == 0 {
+        info!("{}", format!("{} matches the columns", cmd.message).bold());
+    }
+    Ok(mismatches == 0)
+}
+
+/// Verifies a message of `proto` against `(name, type, nullable)` columns, returning the number
+/// of mismatches. The columns stand in for the message's table, so the type mappings, validators
+/// and split timestamps of the config file apply as they do for a table in the database.
+fn verify_inline(
+    opts: &Protosql,
+    config: &Config,
+    proto: &AbstractProto,
+    resolver: &TypeResolver,
+    message_name: &str,
+    columns: &[(String, String, bool)],
+) -> Result<usize> {
+    let message = find_proto_message(proto, message_name)?;
+    let package = proto
+        .package
+        .as_ref()
+        .map(|package| package.as_ref())
+        .unwrap_or("");
+    let array_elements = columns
+        .iter()
+        .filter_map(|(name, typ, _)| {
+            let element = typ.strip_suffix("[]")?;
+            Some((name.clone(), element.to_string()))
+        })
+        .collect();
+    let table_name = match &opts.table {
+        Some(table) => schema::parse_identifier(table)?,
+        None => message_name
+            .rsplit('.')
+            .next()
+            .unwrap_or_default()
+            .to_snake_case(),
+    };
+    let ctx = VerifyContext {
+        opts,
+        proto,
+        resolver,
+        scope: qualify(package, message_name),
+        array_elements,
+        collations: HashMap::new(),
+        split_timestamps: config.split_timestamps(&table_name),
+        baseline: &Baseline::default(),
+        checker: &MappedTypeChecker::new(&config.types),
+        validators: &validator::from_config(config),
+    };
+    let columns = schema::columns_from_rows(columns.to_vec());
+    Ok(verify_message_with_columns(&message, &columns, &ctx))
+}
+
+/// Parses a column spec such as `id:bigint:notnull,name:varchar:null` into `(name, type,
+/// nullable)` columns. Commas inside parentheses, such as in `numeric(10,2)`, don't separate
+/// columns.
+pub fn parse_columns(spec: &str) -> Result<Vec<(String, String, bool)>> {
+    let mut rows = Vec::new();
+    for column in split_columns(spec) {
+        let parts: Vec<_> = column.split(':').map(str::trim).collect();
+        let (name, typ, nullable) = match parts.as_slice() {
+            [name, typ, "null"] => (name, typ, true),
+            [name, typ, "notnull"] => (name, typ, false),
+            _ => bail!(
+                "invalid column '{}', expected name:type:null or name:type:notnull",
+                column
+            ),
+        };
+        if name.is_empty() || typ.is_empty() {
+            bail!(
+                "invalid column '{}', the name and type can't be empty",
+                column
+            );
+        }
+        // Named like the catalog query does, so aliases such as `int8` match the same columns
+        rows.push((name.to_string(), types::canonical_type_name(typ), nullable));
+    }
+    Ok(rows)
+}
+
+fn split_columns(spec: &str) -> Vec<&str> {
+    let mut columns = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in spec.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                columns.push(spec[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    columns.push(spec[start..].trim());
+    columns.retain(|column| !column.is_empty());
+    columns
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Clap;
+
+    use super::*;
+
+    #[test]
+    fn test_verify_inline() {
+        let proto = parse_complete(
+            r#"syntax = "proto3";
+            message User {
+                int64 id = 1;
+                string name = 2;
+                optional string email = 3;
+            }"#,
+        )
+        .unwrap();
+        let mut resolver = TypeResolver::default();
+        resolver.add_proto(&proto);
+        let opts = Protosql::parse_from(["protosql", "--schema-sql", "schema.sql"]);
+        let config = Config::default();
+
+        let columns =
+            parse_columns("id:bigint:notnull, name:varchar:notnull,email:varchar:null").unwrap();
+        assert_eq!(columns.len(), 3);
+        assert_eq!(
+            verify_inline(&opts, &config, &proto, &resolver, "User", &columns).unwrap(),
+            0
+        );
+
+        let columns =
+            parse_columns("id:integer:notnull,name:varchar:notnull,email:varchar:null").unwrap();
+        assert_eq!(
+            verify_inline(&opts, &config, &proto, &resolver, "User", &columns).unwrap(),
+            1
+        );
+        assert!(verify_inline(&opts, &config, &proto, &resolver, "Team", &columns).is_err());
+
+        assert_eq!(
+            parse_columns("total:numeric(10,2):null,id:bigint:notnull")
+                .unwrap()
+                .len(),
+            2
+        );
+        // Aliases are named like the catalog does
+        let columns = parse_columns(
+            "id:int8:notnull,name:varchar(64):notnull,email:varchar:null,tags:varchar[]:notnull",
+        )
+        .unwrap();
+        assert_eq!(
+            columns[3],
+            ("tags".to_string(), "character varying[]".to_string(), false)
+        );
+        let aliases = parse_complete(
+            r#"syntax = "proto3";
+            message User {
+                int64 id = 1;
+                string name = 2;
+                optional string email = 3;
+                repeated string tags = 4;
+                bool active = 5;
+                Status status = 6;
+                enum Status { ACTIVE = 0; }
+            }"#,
+        )
+        .unwrap();
+        let mut resolver = TypeResolver::default();
+        resolver.add_proto(&aliases);
+        let mut columns = columns;
+        columns.extend(parse_columns("active:bool:notnull,status:int4:notnull").unwrap());
+        assert_eq!(
+            verify_inline(&opts, &config, &aliases, &resolver, "User", &columns).unwrap(),
+            0
+        );
+
+        // The config file applies as it does for a table in the database
+        let config = Config {
+            required_columns: vec!["created_at".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            verify_inline(&opts, &config, &aliases, &resolver, "User", &columns).unwrap(),
+            1
+        );
+
+        assert!(parse_columns("id:bigint").is_err());
+        assert!(parse_columns("id:bigint:maybe").is_err());
+        assert!(parse_columns(":bigint:null").is_err());
+    }
+}
@@ -0,0 +1,41 @@
+use std::process::Command;
+
+#[test]
+fn test_json_stdout() {
+    let dir = std::env::temp_dir().join(format!("protosql-json-output-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let proto = dir.join("users.proto");
+    std::fs::write(
+        &proto,
+        r#"syntax = "proto3";
+        message Users {
+            int64 id = 1;
+            string name = 2;
+        }"#,
+    )
+    .unwrap();
+    let schema = dir.join("schema.sql");
+    std::fs::write(
+        &schema,
+        "CREATE TABLE public.users (id integer NOT NULL, name character varying NOT NULL);",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_protosql"))
+        .arg("--schema-sql")
+        .arg(&schema)
+        .arg("--file")
+        .arg(&proto)
+        .args(["--format", "json"])
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Log lines go to stderr, so stdout is only the summary
+    assert_eq!(output.status.code(), Some(2));
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(summary["files"], 1);
+    assert_eq!(summary["mismatches"], 1);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("field 'id' has type 'Int64'"));
+}